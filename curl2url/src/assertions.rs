@@ -0,0 +1,361 @@
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+
+use crate::{errors::AppError, models::CurlResponse};
+
+/// 请求执行后对响应进行的断言
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct AssertionSpec {
+    /// 允许的状态码集合
+    #[serde(default)]
+    pub status: Vec<u16>,
+    #[serde(default)]
+    pub header_equals: Vec<HeaderAssertion>,
+    #[serde(default)]
+    pub header_contains: Vec<HeaderAssertion>,
+    #[serde(default)]
+    pub body_contains: Vec<String>,
+    #[serde(default)]
+    pub body_regex: Vec<String>,
+    #[serde(default)]
+    pub json_path: Vec<JsonPathAssertion>,
+    pub max_latency_ms: Option<u64>,
+    /// 任一断言失败时返回的 HTTP 状态码 (417 或 503), 未设置时始终返回 200
+    pub fail_status: Option<u16>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct HeaderAssertion {
+    pub name: String,
+    pub value: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct JsonPathAssertion {
+    pub path: String,
+    pub equals: Value,
+}
+
+/// 单条断言的结果
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AssertionResult {
+    pub assertion: String,
+    pub passed: bool,
+    pub expected: String,
+    pub actual: Option<String>,
+}
+
+impl AssertionSpec {
+    /// 从查询参数解析断言, 参数均以 `assert_` 开头, 除 status/max_latency_ms/fail_status 外可重复
+    pub fn from_query(query: &[(String, String)]) -> Result<Option<Self>, AppError> {
+        let mut spec = AssertionSpec::default();
+        let mut found = false;
+
+        for (key, value) in query {
+            let Some(name) = key.strip_prefix("assert_") else {
+                continue;
+            };
+            found = true;
+            match name {
+                "status" => {
+                    for code in value.split(',') {
+                        spec.status.push(code.trim().parse().map_err(|_| {
+                            AppError::BadRequest(format!("Invalid status code '{}' in assert_status", code))
+                        })?);
+                    }
+                }
+                "header" => spec.header_equals.push(parse_header_assertion(key, value)?),
+                "header_contains" => spec.header_contains.push(parse_header_assertion(key, value)?),
+                "body_contains" => spec.body_contains.push(value.clone()),
+                "body_regex" => spec.body_regex.push(value.clone()),
+                "json" => {
+                    let (path, expected) = value.split_once('=').ok_or_else(|| {
+                        AppError::BadRequest(format!("Invalid assert_json '{}', expected <path>=<value>", value))
+                    })?;
+                    spec.json_path.push(JsonPathAssertion {
+                        path: path.to_string(),
+                        // 非 JSON 的值按字符串比较
+                        equals: serde_json::from_str(expected).unwrap_or_else(|_| Value::String(expected.to_string())),
+                    });
+                }
+                "max_latency_ms" => {
+                    spec.max_latency_ms = Some(value.parse().map_err(|_| {
+                        AppError::BadRequest(format!("Invalid assert_max_latency_ms '{}'", value))
+                    })?);
+                }
+                "fail_status" => {
+                    spec.fail_status = Some(value.parse().map_err(|_| {
+                        AppError::BadRequest(format!("Invalid assert_fail_status '{}'", value))
+                    })?);
+                }
+                other => {
+                    return Err(AppError::BadRequest(format!("Unknown assertion 'assert_{}'", other)));
+                }
+            }
+        }
+
+        if !found {
+            return Ok(None);
+        }
+        spec.validate()?;
+        Ok(Some(spec))
+    }
+
+    /// 在发起请求前检查正则、JSONPath 和 fail_status, 避免请求执行之后才报错
+    pub fn validate(&self) -> Result<(), AppError> {
+        for pattern in &self.body_regex {
+            body_regex(pattern)?;
+        }
+        for assertion in &self.json_path {
+            json_path_to_pointer(&assertion.path)?;
+        }
+        if let Some(code) = self.fail_status
+            && code != 417
+            && code != 503
+        {
+            return Err(AppError::BadRequest("assert_fail_status must be 417 or 503".to_string()));
+        }
+        Ok(())
+    }
+
+    /// 对响应执行全部断言
+    pub fn evaluate(&self, response: &CurlResponse) -> Result<Vec<AssertionResult>, AppError> {
+        let mut results = Vec::new();
+        let empty_headers = HashMap::new();
+        let headers = response.response_headers.as_ref().unwrap_or(&empty_headers);
+        let header = |name: &str| headers.get(&name.to_ascii_lowercase()).cloned();
+        let body = response.response_body.as_deref();
+
+        // 无法连接上游等传输错误时没有可断言的响应, 本身即视为断言失败
+        if let Some(error) = &response.error {
+            results.push(AssertionResult {
+                assertion: "request".to_string(),
+                passed: false,
+                expected: "a response".to_string(),
+                actual: Some(error.trim().to_string()),
+            });
+        }
+
+        if !self.status.is_empty() {
+            let codes: Vec<String> = self.status.iter().map(u16::to_string).collect();
+            results.push(AssertionResult {
+                assertion: "status".to_string(),
+                passed: response.status_code.is_some_and(|code| self.status.contains(&code)),
+                expected: codes.join(","),
+                actual: response.status_code.map(|code| code.to_string()),
+            });
+        }
+
+        for assertion in &self.header_equals {
+            let actual = header(&assertion.name);
+            results.push(AssertionResult {
+                assertion: format!("header {} equals", assertion.name),
+                passed: actual.as_deref() == Some(assertion.value.as_str()),
+                expected: assertion.value.clone(),
+                actual,
+            });
+        }
+
+        for assertion in &self.header_contains {
+            let actual = header(&assertion.name);
+            results.push(AssertionResult {
+                assertion: format!("header {} contains", assertion.name),
+                passed: actual.as_deref().is_some_and(|value| value.contains(&assertion.value)),
+                expected: assertion.value.clone(),
+                actual,
+            });
+        }
+
+        for expected in &self.body_contains {
+            results.push(AssertionResult {
+                assertion: "body contains".to_string(),
+                passed: body.is_some_and(|body| body.contains(expected.as_str())),
+                expected: expected.clone(),
+                actual: None,
+            });
+        }
+
+        for pattern in &self.body_regex {
+            let regex = body_regex(pattern)?;
+            results.push(AssertionResult {
+                assertion: "body matches".to_string(),
+                passed: body.is_some_and(|body| regex.is_match(body)),
+                expected: pattern.clone(),
+                actual: None,
+            });
+        }
+
+        if !self.json_path.is_empty() {
+            let json: Option<Value> = body.and_then(|body| serde_json::from_str(body).ok());
+            for assertion in &self.json_path {
+                let pointer = json_path_to_pointer(&assertion.path)?;
+                let actual = json.as_ref().and_then(|json| json.pointer(&pointer));
+                results.push(AssertionResult {
+                    assertion: format!("json {} equals", assertion.path),
+                    passed: actual == Some(&assertion.equals),
+                    expected: assertion.equals.to_string(),
+                    actual: actual.map(Value::to_string),
+                });
+            }
+        }
+
+        if let Some(max_latency) = self.max_latency_ms {
+            let latency = response.timings.as_ref().map(|timings| timings.total_ms);
+            results.push(AssertionResult {
+                assertion: "max latency ms".to_string(),
+                passed: latency.is_some_and(|latency| latency <= max_latency as f64),
+                expected: max_latency.to_string(),
+                actual: latency.map(|latency| format!("{:.0}", latency)),
+            });
+        }
+
+        Ok(results)
+    }
+}
+
+fn body_regex(pattern: &str) -> Result<Regex, AppError> {
+    Regex::new(pattern).map_err(|e| AppError::BadRequest(format!("Invalid body regex '{}': {}", pattern, e)))
+}
+
+fn parse_header_assertion(key: &str, value: &str) -> Result<HeaderAssertion, AppError> {
+    let (name, expected) = value
+        .split_once(':')
+        .ok_or_else(|| AppError::BadRequest(format!("Invalid {} '{}', expected <name>:<value>", key, value)))?;
+    Ok(HeaderAssertion {
+        name: name.trim().to_string(),
+        value: expected.trim().to_string(),
+    })
+}
+
+/// 将简单的 JSONPath (`$.a.b[0]`、`$['a']`) 转换为 JSON Pointer
+fn json_path_to_pointer(path: &str) -> Result<String, AppError> {
+    let invalid = || AppError::BadRequest(format!("Unsupported JSONPath '{}'", path));
+    let mut rest = path.strip_prefix('$').ok_or_else(invalid)?;
+    let mut pointer = String::new();
+
+    while !rest.is_empty() {
+        let segment;
+        if let Some(after) = rest.strip_prefix("['") {
+            // 引号内的 `\'` 和 `\\` 为转义
+            let mut key = String::new();
+            let mut chars = after.char_indices();
+            let end = loop {
+                match chars.next().ok_or_else(invalid)? {
+                    (_, '\\') => key.push(chars.next().ok_or_else(invalid)?.1),
+                    (index, '\'') if after[index + 1..].starts_with(']') => break index,
+                    (_, c) => key.push(c),
+                }
+            };
+            segment = key;
+            rest = &after[end + 2..];
+        } else if let Some(after) = rest.strip_prefix('[') {
+            let end = after.find(']').ok_or_else(invalid)?;
+            after[..end].parse::<usize>().map_err(|_| invalid())?;
+            segment = after[..end].to_string();
+            rest = &after[end + 1..];
+        } else if let Some(after) = rest.strip_prefix('.') {
+            let end = after.find(['.', '[']).unwrap_or(after.len());
+            if end == 0 {
+                return Err(invalid());
+            }
+            segment = after[..end].to_string();
+            rest = &after[end..];
+        } else {
+            return Err(invalid());
+        }
+        pointer.push('/');
+        pointer.push_str(&segment.replace('~', "~0").replace('/', "~1"));
+    }
+
+    Ok(pointer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn query(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs.iter().map(|(key, value)| (key.to_string(), value.to_string())).collect()
+    }
+
+    #[test]
+    fn json_paths_become_pointers() {
+        assert_eq!(json_path_to_pointer("$").unwrap(), "");
+        assert_eq!(json_path_to_pointer("$.a.b[0]").unwrap(), "/a/b/0");
+        assert_eq!(json_path_to_pointer("$['a/b']['c~d']").unwrap(), "/a~1b/c~0d");
+        assert_eq!(json_path_to_pointer("$['it\\'s']['a\\\\b']").unwrap(), "/it's/a\\b");
+        for path in ["a.b", "$..a", "$[x]", "$['a'", "$.a["] {
+            assert!(json_path_to_pointer(path).is_err(), "{}", path);
+        }
+    }
+
+    #[test]
+    fn query_parameters_are_validated_before_the_request() {
+        assert!(AssertionSpec::from_query(&query(&[("url", "x")])).unwrap().is_none());
+        let spec = AssertionSpec::from_query(&query(&[("assert_status", "200,204"), ("assert_json", "$.ok=true")]))
+            .unwrap()
+            .unwrap();
+        assert_eq!(spec.status, vec![200, 204]);
+        assert_eq!(spec.json_path[0].equals, Value::Bool(true));
+
+        for pairs in [
+            [("assert_body_regex", "(unclosed")],
+            [("assert_json", "status=ok")],
+            [("assert_fail_status", "500")],
+            [("assert_unknown", "x")],
+        ] {
+            assert!(AssertionSpec::from_query(&query(&pairs)).is_err(), "{:?}", pairs);
+        }
+    }
+
+    #[test]
+    fn transport_errors_fail_the_assertions() {
+        let spec = AssertionSpec {
+            body_contains: vec!["ok".to_string()],
+            ..Default::default()
+        };
+        let response = CurlResponse {
+            error: Some("curl exited with code 7".to_string()),
+            ..Default::default()
+        };
+        let results = spec.evaluate(&response).unwrap();
+        assert_eq!(results[0].assertion, "request");
+        assert!(!results[0].passed);
+
+        let response = CurlResponse {
+            assertions: Some(results),
+            ..Default::default()
+        };
+        assert!(response.assertions_failed());
+    }
+
+    #[test]
+    fn evaluates_status_headers_and_json() {
+        let spec = AssertionSpec {
+            status: vec![200],
+            header_contains: vec![HeaderAssertion {
+                name: "Content-Type".to_string(),
+                value: "json".to_string(),
+            }],
+            json_path: vec![JsonPathAssertion {
+                path: "$.items[1].id".to_string(),
+                equals: Value::from(2),
+            }],
+            ..Default::default()
+        };
+        let response = CurlResponse {
+            status_code: Some(200),
+            response_headers: Some(HashMap::from([(
+                "content-type".to_string(),
+                "application/json".to_string(),
+            )])),
+            response_body: Some(r#"{"items":[{"id":1},{"id":2}]}"#.to_string()),
+            ..Default::default()
+        };
+        let results = spec.evaluate(&response).unwrap();
+        assert_eq!(results.len(), 3);
+        assert!(results.iter().all(|result| result.passed));
+    }
+}
@@ -0,0 +1,369 @@
+use base64::prelude::{Engine, BASE64_STANDARD};
+use serde::Deserialize;
+use std::{
+    collections::HashMap,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tracing::info;
+
+use crate::{
+    command::{CurlCommand, MASK},
+    config::Config,
+    errors::AppError,
+    models::AppState,
+};
+
+/// token 过期前提前刷新的时间
+const TOKEN_REFRESH_MARGIN: Duration = Duration::from_secs(30);
+
+/// 请求级别的认证方式
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AuthSpec {
+    Basic {
+        username: String,
+        password: String,
+    },
+    Digest {
+        username: String,
+        password: String,
+    },
+    /// 使用 `[secrets]` 中的命名 secret 作为 Bearer token
+    Bearer {
+        secret: String,
+    },
+    /// 使用 `[[oauth2_clients]]` 中的客户端进行 client-credentials 授权
+    Oauth2 {
+        client: String,
+    },
+    AwsSigv4 {
+        access_key: String,
+        secret_key: String,
+        region: String,
+        #[serde(default = "default_aws_service")]
+        service: String,
+        session_token: Option<String>,
+    },
+}
+
+fn default_aws_service() -> String {
+    "s3".to_string()
+}
+
+/// 缓存的 OAuth2 access token
+#[derive(Debug, Clone)]
+pub struct CachedToken {
+    pub access_token: String,
+    pub expires_at: Instant,
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    expires_in: Option<u64>,
+}
+
+impl AuthSpec {
+    /// 从 `/curl` 的查询参数解析认证方式, 例如 `auth=basic&auth_username=..&auth_password=..`
+    pub fn from_params(params: &HashMap<String, String>) -> Result<Option<Self>, AppError> {
+        let Some(kind) = params.get("auth") else {
+            return Ok(None);
+        };
+        let param = |name: &str| {
+            params
+                .get(&format!("auth_{}", name))
+                .cloned()
+                .ok_or_else(|| AppError::BadRequest(format!("Missing 'auth_{}' parameter", name)))
+        };
+
+        let spec = match kind.as_str() {
+            "basic" => AuthSpec::Basic {
+                username: param("username")?,
+                password: param("password")?,
+            },
+            "digest" => AuthSpec::Digest {
+                username: param("username")?,
+                password: param("password")?,
+            },
+            "bearer" => AuthSpec::Bearer {
+                secret: param("secret")?,
+            },
+            "oauth2" => AuthSpec::Oauth2 {
+                client: param("client")?,
+            },
+            "aws_sigv4" => AuthSpec::AwsSigv4 {
+                access_key: param("access_key")?,
+                secret_key: param("secret_key")?,
+                region: param("region")?,
+                service: param("service").unwrap_or_else(|_| default_aws_service()),
+                session_token: param("session_token").ok(),
+            },
+            other => {
+                return Err(AppError::BadRequest(format!("Unsupported auth type '{}'", other)));
+            }
+        };
+
+        Ok(Some(spec))
+    }
+}
+
+/// 将认证方式转换为 curl 参数, 凭据在展示时会被脱敏
+pub async fn auth_args(state: &Arc<AppState>, spec: &AuthSpec) -> Result<CurlCommand, AppError> {
+    let mut command = CurlCommand::new();
+
+    match spec {
+        AuthSpec::Basic { username, password } => {
+            command
+                .secret_option("-u", format!("{}:{}", username, password), format!("{}:{}", username, MASK));
+        }
+        AuthSpec::Digest { username, password } => {
+            command
+                .arg("--digest")
+                .secret_option("-u", format!("{}:{}", username, password), format!("{}:{}", username, MASK));
+        }
+        AuthSpec::Bearer { secret } => {
+            let token = resolve_secret(&state.config(), secret)?;
+            command.header("Authorization", &format!("Bearer {}", token));
+        }
+        AuthSpec::Oauth2 { client } => {
+            let token = oauth2_token(state, client).await?;
+            command.header("Authorization", &format!("Bearer {}", token));
+        }
+        AuthSpec::AwsSigv4 {
+            access_key,
+            secret_key,
+            region,
+            service,
+            session_token,
+        } => {
+            command
+                .arg("--aws-sigv4")
+                .arg(format!("aws:amz:{}:{}", region, service))
+                .secret_option("-u", format!("{}:{}", access_key, secret_key), format!("{}:{}", access_key, MASK));
+            if let Some(session_token) = session_token {
+                command.header("x-amz-security-token", session_token);
+            }
+        }
+    }
+
+    Ok(command)
+}
+
+/// 不经过 curl 的连接 (WebSocket) 使用的认证请求头, 需要挑战-应答或签名的方式不支持
+pub async fn auth_headers(state: &Arc<AppState>, spec: &AuthSpec) -> Result<Vec<(String, String)>, AppError> {
+    let authorization = match spec {
+        AuthSpec::Basic { username, password } => {
+            format!("Basic {}", BASE64_STANDARD.encode(format!("{}:{}", username, password)))
+        }
+        AuthSpec::Bearer { secret } => format!("Bearer {}", resolve_secret(&state.config(), secret)?),
+        AuthSpec::Oauth2 { client } => format!("Bearer {}", oauth2_token(state, client).await?),
+        AuthSpec::Digest { .. } | AuthSpec::AwsSigv4 { .. } => {
+            return Err(AppError::BadRequest(
+                "Only basic, bearer and oauth2 auth are supported for WebSocket connections".to_string(),
+            ));
+        }
+    };
+    Ok(vec![("Authorization".to_string(), authorization)])
+}
+
+/// 读取命名 secret, 支持直接配置值或从环境变量读取
+pub fn resolve_secret(config: &Config, name: &str) -> Result<String, AppError> {
+    config
+        .secrets
+        .get(name)
+        .ok_or_else(|| AppError::BadRequest(format!("Unknown secret '{}'", name)))?
+        .resolve()
+        .map_err(|e| AppError::InternalServerError(format!("Failed to resolve secret '{}': {}", name, e)))
+}
+
+/// 获取 OAuth2 client-credentials token, 未过期时直接使用缓存
+async fn oauth2_token(state: &Arc<AppState>, client_name: &str) -> Result<String, AppError> {
+    if let Some(cached) = state.oauth2_tokens.lock().await.get(client_name)
+        && cached.expires_at > Instant::now() + TOKEN_REFRESH_MARGIN
+    {
+        return Ok(cached.access_token.clone());
+    }
+
+    let config = state.config();
+    let client = config
+        .oauth2_clients
+        .iter()
+        .find(|client| client.name == client_name)
+        .ok_or_else(|| AppError::BadRequest(format!("Unknown OAuth2 client '{}'", client_name)))?;
+    let client_secret = client.client_secret.resolve().map_err(|e| {
+        AppError::InternalServerError(format!("Failed to resolve client secret for '{}': {}", client.name, e))
+    })?;
+
+    info!("Requesting OAuth2 token for client '{}' from {}", client.name, client.token_url);
+    let mut form = vec![("grant_type", "client_credentials".to_string())];
+    if let Some(scope) = &client.scope {
+        form.push(("scope", scope.clone()));
+    }
+    let response = state
+        .http_client
+        .post(&client.token_url)
+        .basic_auth(&client.client_id, Some(client_secret))
+        .form(&form)
+        .send()
+        .await
+        .map_err(|e| AppError::InternalServerError(format!("OAuth2 token request failed: {}", e)))?;
+
+    if !response.status().is_success() {
+        return Err(AppError::InternalServerError(format!(
+            "OAuth2 token request failed with status: {}",
+            response.status()
+        )));
+    }
+
+    let token: TokenResponse = response
+        .json()
+        .await
+        .map_err(|e| AppError::InternalServerError(format!("Invalid OAuth2 token response: {}", e)))?;
+    let expires_at = Instant::now() + Duration::from_secs(token.expires_in.unwrap_or(3600));
+
+    state.oauth2_tokens.lock().await.insert(
+        client_name.to_string(),
+        CachedToken {
+            access_token: token.access_token.clone(),
+            expires_at,
+        },
+    );
+
+    Ok(token.access_token)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn state(token_url: &str) -> Arc<AppState> {
+        let config: Config = toml::from_str(&format!(
+            r#"
+            [server]
+            listen_addr = "127.0.0.1:0"
+            [proxy]
+            temp_file_host_url = "http://localhost:3001"
+            max_response_size_bytes = 1024
+            [curl]
+            timeout_seconds = 5
+            follow_redirects = true
+            include_headers = true
+
+            [secrets]
+            api_token = "t0ken"
+
+            [[oauth2_clients]]
+            name = "billing"
+            token_url = "{}"
+            client_id = "id"
+            client_secret = "secret"
+            "#,
+            token_url
+        ))
+        .unwrap();
+        Arc::new(AppState::new(config, reqwest::Client::new()))
+    }
+
+    fn params(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs.iter().map(|(name, value)| (name.to_string(), value.to_string())).collect()
+    }
+
+    #[test]
+    fn auth_is_parsed_from_query_parameters() {
+        assert!(AuthSpec::from_params(&params(&[])).unwrap().is_none());
+        assert!(matches!(
+            AuthSpec::from_params(&params(&[("auth", "basic"), ("auth_username", "u"), ("auth_password", "p")])),
+            Ok(Some(AuthSpec::Basic { username, password })) if username == "u" && password == "p"
+        ));
+        assert!(matches!(
+            AuthSpec::from_params(&params(&[
+                ("auth", "aws_sigv4"),
+                ("auth_access_key", "AK"),
+                ("auth_secret_key", "SK"),
+                ("auth_region", "eu-west-1"),
+            ])),
+            Ok(Some(AuthSpec::AwsSigv4 { service, session_token: None, .. })) if service == "s3"
+        ));
+        assert!(matches!(
+            AuthSpec::from_params(&params(&[("auth", "basic"), ("auth_username", "u")])),
+            Err(AppError::BadRequest(message)) if message.contains("auth_password")
+        ));
+        assert!(matches!(
+            AuthSpec::from_params(&params(&[("auth", "ntlm")])),
+            Err(AppError::BadRequest(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn credentials_are_masked_and_kept_off_the_command_line() {
+        let state = state("http://127.0.0.1:9/token");
+        let basic = AuthSpec::Basic {
+            username: "user".to_string(),
+            password: "pa55".to_string(),
+        };
+        let command = auth_args(&state, &basic).await.unwrap();
+        assert!(command.exec_args().is_empty());
+        assert_eq!(command.to_display_string(), "curl -u 'user:******'");
+
+        let bearer = AuthSpec::Bearer {
+            secret: "api_token".to_string(),
+        };
+        let command = auth_args(&state, &bearer).await.unwrap();
+        assert!(!command.exec_args().iter().any(|arg| arg.contains("t0ken")));
+        assert_eq!(command.to_display_string(), "curl -H 'Authorization: ******'");
+
+        let unknown = AuthSpec::Bearer {
+            secret: "missing".to_string(),
+        };
+        assert!(matches!(auth_args(&state, &unknown).await, Err(AppError::BadRequest(_))));
+    }
+
+    #[tokio::test]
+    async fn websocket_auth_headers() {
+        let state = state("http://127.0.0.1:9/token");
+        let basic = AuthSpec::Basic {
+            username: "user".to_string(),
+            password: "pass".to_string(),
+        };
+        assert_eq!(
+            auth_headers(&state, &basic).await.unwrap(),
+            [("Authorization".to_string(), "Basic dXNlcjpwYXNz".to_string())]
+        );
+        let digest = AuthSpec::Digest {
+            username: "user".to_string(),
+            password: "pass".to_string(),
+        };
+        assert!(matches!(auth_headers(&state, &digest).await, Err(AppError::BadRequest(_))));
+    }
+
+    #[tokio::test]
+    async fn oauth2_tokens_are_cached_until_they_expire() {
+        let requests = Arc::new(AtomicUsize::new(0));
+        let counter = requests.clone();
+        let app = axum::Router::new().route(
+            "/token",
+            axum::routing::post(move || {
+                let counter = counter.clone();
+                async move {
+                    let count = counter.fetch_add(1, Ordering::SeqCst) + 1;
+                    axum::Json(serde_json::json!({ "access_token": format!("token-{}", count), "expires_in": 3600 }))
+                }
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let state = state(&format!("http://{}/token", addr));
+        assert_eq!(oauth2_token(&state, "billing").await.unwrap(), "token-1");
+        assert_eq!(oauth2_token(&state, "billing").await.unwrap(), "token-1");
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+
+        // 即将过期的 token 重新获取
+        state.oauth2_tokens.lock().await.get_mut("billing").unwrap().expires_at = Instant::now() + Duration::from_secs(5);
+        assert_eq!(oauth2_token(&state, "billing").await.unwrap(), "token-2");
+        assert!(matches!(oauth2_token(&state, "unknown").await, Err(AppError::BadRequest(_))));
+    }
+}
@@ -0,0 +1,353 @@
+use axum::{
+    body::Bytes,
+    extract::{Json, Path, Query, State},
+    http::{HeaderMap, Method, Uri},
+    response::{IntoResponse, Response},
+};
+use chrono::{DateTime, Duration, Utc};
+use base64::prelude::{Engine, BASE64_STANDARD};
+use serde::{Deserialize, Serialize, Serializer};
+use serde_json::{json, Value};
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::Arc,
+};
+use tracing::info;
+use uuid::Uuid;
+
+use crate::{
+    body::RequestBody,
+    errors::AppError,
+    history,
+    links::{rewrite_response_links, PublicBaseUrl},
+    models::{AppState, CurlRequest},
+    services::execute_curl_command,
+};
+
+/// 回放时不转发的请求头, 由 curl 根据新的目标重新生成
+const REPLAY_SKIPPED_HEADERS: &[&str] = &["host", "content-length", "transfer-encoding", "connection"];
+
+/// 捕获 webhook 请求的收集器
+#[derive(Debug, Clone)]
+pub struct RequestBin {
+    pub id: String,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+    pub requests: VecDeque<CapturedRequest>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CapturedRequest {
+    pub id: String,
+    pub received_at: DateTime<Utc>,
+    pub method: String,
+    pub path: String,
+    pub query: Option<String>,
+    pub headers: Vec<(String, String)>,
+    /// 原始字节, 回放时原样发送; 列表中按请求体格式展示, 非 UTF-8 内容为 base64
+    #[serde(serialize_with = "serialize_body")]
+    pub body: Bytes,
+    pub body_size: usize,
+    pub body_truncated: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ReplayQuery {
+    url: String,
+}
+
+/// 删除已过期的收集器
+async fn prune_expired(state: &Arc<AppState>) {
+    let now = Utc::now();
+    state.bins.write().await.retain(|_, bin| bin.expires_at > now);
+}
+
+/// `POST /bins`: 创建新的收集器, 返回用于接收请求的地址
+pub async fn create_bin(
+    State(state): State<Arc<AppState>>,
+    PublicBaseUrl(public_base): PublicBaseUrl,
+    headers: HeaderMap,
+) -> Result<Json<Value>, AppError> {
+    prune_expired(&state).await;
+
+    let config = state.config().bins.clone();
+    let mut bins = state.bins.write().await;
+    if bins.len() >= config.max_bins {
+        return Err(AppError::BadRequest(format!(
+            "Too many active bins (max {}), try again later",
+            config.max_bins
+        )));
+    }
+
+    let id = Uuid::new_v4().simple().to_string();
+    let created_at = Utc::now();
+    let bin = RequestBin {
+        id: id.clone(),
+        created_at,
+        expires_at: created_at + Duration::seconds(config.retention_seconds as i64),
+        requests: VecDeque::new(),
+    };
+    let expires_at = bin.expires_at;
+    bins.insert(id.clone(), bin);
+    info!("Created request bin {}", id);
+
+    let base_url = public_base.unwrap_or_else(|| request_base_url(&headers));
+    Ok(Json(json!({
+        "id": id,
+        "url": format!("{}/b/{}", base_url, id),
+        "requests_url": format!("{}/bins/{}/requests", base_url, id),
+        "created_at": created_at,
+        "expires_at": expires_at,
+    })))
+}
+
+/// `/b/{id}` 及其子路径: 记录收到的任意请求
+pub async fn capture_request(
+    State(state): State<Arc<AppState>>,
+    Path(params): Path<Vec<(String, String)>>,
+    method: Method,
+    uri: Uri,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<Json<Value>, AppError> {
+    let id = params
+        .iter()
+        .find(|(key, _)| key == "id")
+        .map(|(_, value)| value.clone())
+        .unwrap_or_default();
+
+    let config = state.config().bins.clone();
+    let mut bins = state.bins.write().await;
+    let bin = bins
+        .get_mut(&id)
+        .filter(|bin| bin.expires_at > Utc::now())
+        .ok_or_else(|| AppError::NotFound(format!("Bin '{}' not found", id)))?;
+
+    let body_truncated = body.len() > config.max_body_bytes;
+    let captured = CapturedRequest {
+        id: Uuid::new_v4().simple().to_string(),
+        received_at: Utc::now(),
+        method: method.to_string(),
+        path: uri.path().to_string(),
+        query: uri.query().map(str::to_string),
+        headers: headers
+            .iter()
+            .map(|(name, value)| {
+                (
+                    name.as_str().to_string(),
+                    String::from_utf8_lossy(value.as_bytes()).to_string(),
+                )
+            })
+            .collect(),
+        body: body.slice(..body.len().min(config.max_body_bytes)),
+        body_size: body.len(),
+        body_truncated,
+    };
+    let request_id = captured.id.clone();
+
+    bin.requests.push_back(captured);
+    while bin.requests.len() > config.max_requests_per_bin {
+        bin.requests.pop_front();
+    }
+    evict_oldest(&mut bins, config.max_total_body_bytes);
+
+    Ok(Json(json!({ "ok": true, "request_id": request_id })))
+}
+
+/// `GET /bins/{id}/requests`: 查看收集到的请求, 最新的在前
+pub async fn list_bin_requests(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+) -> Result<Json<Value>, AppError> {
+    prune_expired(&state).await;
+
+    let bins = state.bins.read().await;
+    let bin = bins
+        .get(&id)
+        .ok_or_else(|| AppError::NotFound(format!("Bin '{}' not found", id)))?;
+    let requests: Vec<&CapturedRequest> = bin.requests.iter().rev().collect();
+
+    Ok(Json(json!({
+        "id": bin.id,
+        "created_at": bin.created_at,
+        "expires_at": bin.expires_at,
+        "count": requests.len(),
+        "requests": requests,
+    })))
+}
+
+/// `DELETE /bins/{id}`
+pub async fn delete_bin(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+) -> Result<Json<Value>, AppError> {
+    state
+        .bins
+        .write()
+        .await
+        .remove(&id)
+        .ok_or_else(|| AppError::NotFound(format!("Bin '{}' not found", id)))?;
+
+    Ok(Json(json!({ "ok": true })))
+}
+
+/// `POST /bins/{id}/requests/{request_id}/replay?url=`: 将捕获的请求重新发送到指定地址
+pub async fn replay_request(
+    State(state): State<Arc<AppState>>,
+    PublicBaseUrl(public_base): PublicBaseUrl,
+    Path((id, request_id)): Path<(String, String)>,
+    Query(query): Query<ReplayQuery>,
+) -> Result<Response, AppError> {
+    let captured = {
+        let bins = state.bins.read().await;
+        bins.get(&id)
+            .ok_or_else(|| AppError::NotFound(format!("Bin '{}' not found", id)))?
+            .requests
+            .iter()
+            .find(|request| request.id == request_id)
+            .cloned()
+            .ok_or_else(|| AppError::NotFound(format!("Request '{}' not found in bin '{}'", request_id, id)))?
+    };
+
+    // 截断的请求体回放后与原请求不一致
+    if captured.body_truncated {
+        return Err(AppError::BadRequest(format!(
+            "Request '{}' was truncated to {} of {} bytes and cannot be replayed",
+            request_id,
+            captured.body.len(),
+            captured.body_size
+        )));
+    }
+
+    info!("Replaying captured request {} to {}", captured.id, query.url);
+    let request = CurlRequest {
+        url: query.url,
+        method: captured.method,
+        headers: captured
+            .headers
+            .into_iter()
+            .filter(|(name, _)| !REPLAY_SKIPPED_HEADERS.contains(&name.as_str()))
+            .collect(),
+        body: (!captured.body.is_empty()).then(|| RequestBody::Base64 {
+            content: BASE64_STANDARD.encode(&captured.body),
+        }),
+        ..Default::default()
+    };
+
+    let response = execute_curl_command(&state, &request).await?;
+    let mut response = history::record(&state, &request, response).await;
+    rewrite_response_links(&mut response, public_base.as_deref());
+    Ok(response.into_response())
+}
+
+/// 所有收集器保存的请求体总量超过上限时, 丢弃最早收到的请求
+fn evict_oldest(bins: &mut HashMap<String, RequestBin>, max_total_body_bytes: usize) {
+    let mut total: usize = bins
+        .values()
+        .flat_map(|bin| &bin.requests)
+        .map(|request| request.body.len())
+        .sum();
+    while total > max_total_body_bytes {
+        // 每个收集器内按接收时间排列, 队首即该收集器最早的请求
+        let Some(bin) = bins
+            .values_mut()
+            .filter(|bin| !bin.requests.is_empty())
+            .min_by_key(|bin| bin.requests[0].received_at)
+        else {
+            break;
+        };
+        if let Some(request) = bin.requests.pop_front() {
+            total -= request.body.len();
+        }
+    }
+}
+
+/// 按请求体格式输出, 可以直接用于 `/curl` 的 `body` 字段
+fn serialize_body<S: Serializer>(body: &Bytes, serializer: S) -> Result<S::Ok, S::Error> {
+    let body = match std::str::from_utf8(body) {
+        Ok(content) => RequestBody::Raw {
+            content: content.to_string(),
+        },
+        Err(_) => RequestBody::Base64 {
+            content: BASE64_STANDARD.encode(body),
+        },
+    };
+    body.serialize(serializer)
+}
+
+/// 根据请求的 Host 头推断本服务的访问地址
+pub(crate) fn request_base_url(headers: &HeaderMap) -> String {
+    let host = headers
+        .get("host")
+        .and_then(|host| host.to_str().ok())
+        .unwrap_or("localhost");
+    format!("http://{}", host)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn captured(id: &str, received_at: DateTime<Utc>, body: &'static [u8]) -> CapturedRequest {
+        CapturedRequest {
+            id: id.to_string(),
+            received_at,
+            method: "POST".to_string(),
+            path: "/b/bin".to_string(),
+            query: None,
+            headers: vec![],
+            body: Bytes::from_static(body),
+            body_size: body.len(),
+            body_truncated: false,
+        }
+    }
+
+    fn bin(id: &str, requests: Vec<CapturedRequest>) -> RequestBin {
+        let now = Utc::now();
+        RequestBin {
+            id: id.to_string(),
+            created_at: now,
+            expires_at: now + Duration::hours(1),
+            requests: requests.into(),
+        }
+    }
+
+    #[test]
+    fn bodies_serialize_as_raw_or_base64() {
+        let now = Utc::now();
+        let text = serde_json::to_value(captured("a", now, b"hello")).unwrap();
+        assert_eq!(text["body"], json!({ "type": "raw", "content": "hello" }));
+
+        let binary = serde_json::to_value(captured("b", now, b"\xff\x00\x01")).unwrap();
+        assert_eq!(binary["body"], json!({ "type": "base64", "content": "/wAB" }));
+    }
+
+    #[test]
+    fn oldest_requests_are_evicted_across_bins() {
+        let start = Utc::now();
+        let at = |seconds: i64| start + Duration::seconds(seconds);
+        let mut bins = HashMap::from([
+            ("one".to_string(), bin("one", vec![captured("1", at(0), b"aaaa"), captured("3", at(2), b"cccc")])),
+            ("two".to_string(), bin("two", vec![captured("2", at(1), b"bbbb"), captured("4", at(3), b"dddd")])),
+        ]);
+
+        evict_oldest(&mut bins, 16);
+        assert_eq!(bins["one"].requests.len() + bins["two"].requests.len(), 4);
+
+        evict_oldest(&mut bins, 8);
+        let ids = |bin: &RequestBin| bin.requests.iter().map(|request| request.id.clone()).collect::<Vec<_>>();
+        assert_eq!(ids(&bins["one"]), vec!["3"]);
+        assert_eq!(ids(&bins["two"]), vec!["4"]);
+
+        evict_oldest(&mut bins, 0);
+        assert!(bins.values().all(|bin| bin.requests.is_empty()));
+    }
+
+    #[test]
+    fn base_url_falls_back_to_the_host_header() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(request_base_url(&headers), "http://localhost");
+        headers.insert("host", "bins.example.com:8080".parse().unwrap());
+        assert_eq!(request_base_url(&headers), "http://bins.example.com:8080");
+    }
+}
@@ -0,0 +1,366 @@
+use axum::{
+    body::Bytes,
+    extract::{Query, State},
+    http::{header, HeaderMap, HeaderName, HeaderValue, Method, StatusCode},
+    response::{IntoResponse, Redirect, Response},
+};
+use base64::prelude::{Engine, BASE64_STANDARD};
+use regex::Regex;
+use std::{
+    collections::HashMap,
+    sync::{Arc, LazyLock},
+};
+use tracing::info;
+
+use crate::{
+    auth::AuthSpec,
+    body::RequestBody,
+    errors::AppError,
+    format::escape_html,
+    html::{self, LinkRef, LinkRewriter},
+    links::{rewrite_response_links, PublicBaseUrl},
+    models::{AppState, CurlRequest},
+    services::{fetch_raw_or_store, RawFetch},
+    vcr,
+};
+
+static METHOD_ATTR: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"(?i)\smethod\s*=\s*["']?([A-Za-z]+)"#).expect("valid method regex")
+});
+
+static ACTION_ATTR: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"(?i)\saction\s*=\s*("[^"]*"|'[^']*'|[^\s"'>]+)"#).expect("valid action regex")
+});
+
+/// 从浏览器转发给上游的请求头
+const FORWARDED_HEADERS: &[&str] = &["accept", "accept-language", "content-type"];
+
+/// 原样返回给浏览器的上游响应头
+const PASSED_HEADERS: &[&str] = &[
+    "content-type",
+    "cache-control",
+    "etag",
+    "last-modified",
+    "content-disposition",
+    "content-language",
+];
+
+/// 上游页面与本服务同源, 用沙箱隔离其脚本, 只保留表单提交和新窗口
+const CONTENT_SECURITY_POLICY: &str = "sandbox allow-forms allow-popups";
+
+/// `/browse?url=`: 通过本服务浏览网页, HTML 和 CSS 中的链接改写为经过 `/browse`
+///
+/// `profile`、`auth` 等控制参数之外的查询参数追加到目标地址, 用于 GET 表单提交
+pub async fn browse(
+    State(state): State<Arc<AppState>>,
+    PublicBaseUrl(public_base): PublicBaseUrl,
+    Query(query): Query<Vec<(String, String)>>,
+    method: Method,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    let mut response = match browse_target(&state, public_base, query, method, headers, body).await {
+        Ok(response) => response,
+        Err(e) => e.into_response(),
+    };
+    response.headers_mut().insert(
+        header::CONTENT_SECURITY_POLICY,
+        HeaderValue::from_static(CONTENT_SECURITY_POLICY),
+    );
+    response
+}
+
+async fn browse_target(
+    state: &Arc<AppState>,
+    public_base: Option<String>,
+    query: Vec<(String, String)>,
+    method: Method,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<Response, AppError> {
+    vcr::ensure_live(&state.config().vcr, "Browse")?;
+    let request = browse_request(&query, &method, &headers, &body)?;
+    info!("Browsing {} {}", request.method, request.display_url());
+
+    let raw = match fetch_raw_or_store(state, &request).await? {
+        RawFetch::Raw(raw) => raw,
+        RawFetch::Stored(mut response) => {
+            // 超过大小限制的资源已保存到 sink, 跳转到下载链接
+            rewrite_response_links(&mut response, public_base.as_deref());
+            return match response.stored_file {
+                Some(stored) => Ok(Redirect::to(&stored.url).into_response()),
+                None => Err(AppError::BadGateway(
+                    response.error.unwrap_or_else(|| "Failed to store large response".to_string()),
+                )),
+            };
+        }
+    };
+
+    let status = raw
+        .status_code
+        .and_then(|code| StatusCode::from_u16(code).ok())
+        .unwrap_or(StatusCode::BAD_GATEWAY);
+    let content_type = raw
+        .headers
+        .get("content-type")
+        .map(|value| value.to_ascii_lowercase())
+        .unwrap_or_default();
+
+    let body = if content_type.contains("text/html") || content_type.contains("xhtml") {
+        rewrite_html(&String::from_utf8_lossy(&raw.body), &raw.final_url).into_bytes()
+    } else if content_type.contains("text/css") {
+        rewrite_css(&String::from_utf8_lossy(&raw.body), &raw.final_url).into_bytes()
+    } else {
+        raw.body
+    };
+
+    let mut response = (status, body).into_response();
+    for name in PASSED_HEADERS {
+        if let Some(value) = raw.headers.get(*name).and_then(|value| HeaderValue::from_str(value).ok()) {
+            response.headers_mut().insert(HeaderName::from_static(name), value);
+        }
+    }
+    // 不跟随重定向时, 跳转目标同样经过 /browse
+    if status.is_redirection()
+        && let Some(location) = raw.headers.get("location")
+        && let Some(link) = browse_link(location, &raw.final_url)
+        && let Ok(value) = HeaderValue::from_str(&link)
+    {
+        response.headers_mut().insert(header::LOCATION, value);
+    }
+    Ok(response)
+}
+
+/// 由查询参数和浏览器请求构建上游请求
+///
+/// `profile`、`auth`/`auth_*`、`resolve` 和 `connect_to` 与 `/ws` 相同, 其余参数追加到目标地址
+fn browse_request(
+    query: &[(String, String)],
+    method: &Method,
+    headers: &HeaderMap,
+    body: &Bytes,
+) -> Result<CurlRequest, AppError> {
+    let params: HashMap<String, String> = query.iter().cloned().collect();
+    let values = |name: &str| {
+        query
+            .iter()
+            .filter(|(key, _)| key == name)
+            .map(|(_, value)| value.clone())
+            .collect::<Vec<_>>()
+    };
+
+    let target = params
+        .get("url")
+        .ok_or_else(|| AppError::BadRequest("Missing 'url' parameter".to_string()))?;
+    let mut target = url::Url::parse(target).map_err(|e| AppError::BadRequest(format!("Invalid target URL: {}", e)))?;
+    if !matches!(target.scheme(), "http" | "https") {
+        return Err(AppError::BadRequest(format!("Unsupported scheme '{}'", target.scheme())));
+    }
+    let fields: Vec<&(String, String)> = query.iter().filter(|(name, _)| !is_control_param(name)).collect();
+    if !fields.is_empty() {
+        target.query_pairs_mut().extend_pairs(fields);
+    }
+
+    Ok(CurlRequest {
+        url: target.to_string(),
+        method: method.as_str().to_string(),
+        headers: FORWARDED_HEADERS
+            .iter()
+            .filter_map(|name| {
+                headers
+                    .get(*name)
+                    .and_then(|value| value.to_str().ok())
+                    .map(|value| (name.to_string(), value.to_string()))
+            })
+            .collect(),
+        // 原样转发表单的字节, 非 UTF-8 编码的内容也不会被替换
+        body: (!body.is_empty()).then(|| RequestBody::Base64 {
+            content: BASE64_STANDARD.encode(body),
+        }),
+        profile: params.get("profile").cloned(),
+        auth: AuthSpec::from_params(&params)?,
+        resolve: values("resolve"),
+        connect_to: values("connect_to"),
+        ..Default::default()
+    })
+}
+
+/// 由 `/browse` 自身处理、不转发给上游的查询参数
+fn is_control_param(name: &str) -> bool {
+    matches!(name, "url" | "profile" | "auth" | "resolve" | "connect_to") || name.starts_with("auth_")
+}
+
+/// 链接改写为 `/browse?url=`, GET 表单的目标地址放在隐藏字段中
+struct BrowseLinks<'a> {
+    page_url: &'a str,
+    form_target: Option<String>,
+}
+
+impl LinkRewriter for BrowseLinks<'_> {
+    fn rewrite(&mut self, link: &LinkRef) -> Option<String> {
+        // GET 表单提交会替换 action 中的查询参数, 在 finish_tag 中处理
+        if link.tag == "form" && link.attr == "action" && is_get_form(link.attributes) {
+            self.form_target = Some(link.url.to_string());
+            return None;
+        }
+        Some(format!("/browse?url={}", urlencoding::encode(link.url)))
+    }
+
+    fn finish_tag(&mut self, tag: &str, attributes: &str, rewritten: String) -> String {
+        if tag != "form" || !is_get_form(attributes) {
+            return rewritten;
+        }
+        // 没有 action 的表单提交到当前页面 (不受 <base> 影响)
+        let target = self.form_target.take().unwrap_or_else(|| self.page_url.to_string());
+        let mut form = ACTION_ATTR.replace_all(&rewritten, "").into_owned();
+        form.insert_str(form.len() - 1, " action=\"/browse\"");
+        format!("{}<input type=\"hidden\" name=\"url\" value=\"{}\">", form, escape_html(&target))
+    }
+}
+
+fn is_get_form(attributes: &str) -> bool {
+    METHOD_ATTR
+        .captures(attributes)
+        .is_none_or(|method| method[1].eq_ignore_ascii_case("get"))
+}
+
+/// 改写 HTML 中的链接和内联 CSS 的 `url()`
+pub fn rewrite_html(html: &str, page_url: &str) -> String {
+    html::rewrite_html(
+        html,
+        page_url,
+        &mut BrowseLinks {
+            page_url,
+            form_target: None,
+        },
+    )
+}
+
+/// 改写样式表中的 `url()` 和 `@import`
+pub fn rewrite_css(css: &str, css_url: &str) -> String {
+    html::rewrite_css(
+        css,
+        css_url,
+        false,
+        &mut BrowseLinks {
+            page_url: css_url,
+            form_target: None,
+        },
+    )
+}
+
+/// 转换为 `/browse?url=` 链接, 页内锚点和 `data:`、`javascript:` 等返回 `None`
+pub fn browse_link(link: &str, base: &str) -> Option<String> {
+    let link = link.trim();
+    if link.is_empty() || link.starts_with('#') {
+        return None;
+    }
+    let mut resolved = url::Url::parse(&html::resolve(link, base)?).ok()?;
+    let fragment = resolved.fragment().map(|fragment| format!("#{}", fragment));
+    resolved.set_fragment(None);
+    Some(format!(
+        "/browse?url={}{}",
+        urlencoding::encode(resolved.as_str()),
+        fragment.unwrap_or_default()
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn query(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs.iter().map(|(name, value)| (name.to_string(), value.to_string())).collect()
+    }
+
+    #[test]
+    fn browse_link_resolves_and_keeps_fragment() {
+        assert_eq!(
+            browse_link("b.html?x=1#top", "https://example.com/a/index.html"),
+            Some("/browse?url=https%3A%2F%2Fexample.com%2Fa%2Fb.html%3Fx%3D1#top".to_string())
+        );
+        assert_eq!(
+            browse_link(" /c ", "https://example.com/a/"),
+            Some("/browse?url=https%3A%2F%2Fexample.com%2Fc".to_string())
+        );
+        assert_eq!(browse_link("#section", "https://example.com/"), None);
+        assert_eq!(browse_link("", "https://example.com/"), None);
+        assert_eq!(browse_link("javascript:alert(1)", "https://example.com/"), None);
+        assert_eq!(browse_link("data:text/plain,hi", "https://example.com/"), None);
+    }
+
+    #[test]
+    fn rewrites_css_urls_and_imports() {
+        let css = "@import \"base.css\";\nbody { background: url('img/bg.png'); }";
+        let rewritten = rewrite_css(css, "https://example.com/css/site.css");
+        assert!(rewritten.contains("/browse?url=https%3A%2F%2Fexample.com%2Fcss%2Fbase.css"), "{}", rewritten);
+        assert!(rewritten.contains("/browse?url=https%3A%2F%2Fexample.com%2Fcss%2Fimg%2Fbg.png"), "{}", rewritten);
+        assert!(!rewritten.contains("'img/bg.png'"));
+    }
+
+    #[test]
+    fn get_forms_submit_through_browse() {
+        let page = "https://example.com/search/index.html";
+        let html = rewrite_html(r#"<form action="results?lang=en" method="get"><input name="q"></form>"#, page);
+        assert!(html.contains(r#"action="/browse""#), "{}", html);
+        assert!(
+            html.contains(r#"<input type="hidden" name="url" value="https://example.com/search/results?lang=en">"#),
+            "{}",
+            html
+        );
+        assert_eq!(html.matches("action=").count(), 1, "{}", html);
+
+        // 没有 action 的表单提交到当前页面
+        let html = rewrite_html(r#"<form><input name="q"></form>"#, page);
+        assert!(html.contains(r#"action="/browse""#), "{}", html);
+        assert!(html.contains(&format!(r#"name="url" value="{}""#, page)), "{}", html);
+
+        // POST 表单的 action 直接改写, 表单字段在请求体中
+        let html = rewrite_html(r#"<form action="/login" method="POST"></form>"#, page);
+        assert!(html.contains("/browse?url=https%3A%2F%2Fexample.com%2Flogin"), "{}", html);
+        assert!(!html.contains(r#"type="hidden""#), "{}", html);
+    }
+
+    #[test]
+    fn builds_request_with_control_params() {
+        let query = query(&[
+            ("url", "https://example.com/search?page=2"),
+            ("q", "rust"),
+            ("profile", "internal"),
+            ("auth", "basic"),
+            ("auth_username", "user"),
+            ("auth_password", "secret"),
+            ("resolve", "example.com:443:127.0.0.1"),
+            ("connect_to", "example.com:443:other.test:8443"),
+        ]);
+        let mut headers = HeaderMap::new();
+        headers.insert("accept", HeaderValue::from_static("text/html"));
+        headers.insert("cookie", HeaderValue::from_static("session=1"));
+
+        let request = browse_request(&query, &Method::POST, &headers, &Bytes::from_static(b"a=1")).unwrap();
+        assert_eq!(request.url, "https://example.com/search?page=2&q=rust");
+        assert_eq!(request.method, "POST");
+        assert_eq!(request.headers, vec![("accept".to_string(), "text/html".to_string())]);
+        assert_eq!(request.profile.as_deref(), Some("internal"));
+        assert!(matches!(
+            request.auth,
+            Some(AuthSpec::Basic { ref username, ref password }) if username == "user" && password == "secret"
+        ));
+        assert_eq!(request.resolve, vec!["example.com:443:127.0.0.1"]);
+        assert_eq!(request.connect_to, vec!["example.com:443:other.test:8443"]);
+        assert!(matches!(request.body, Some(RequestBody::Base64 { ref content }) if content == "YT0x"));
+    }
+
+    #[test]
+    fn rejects_invalid_targets() {
+        let build = |pairs: &[(&str, &str)]| browse_request(&query(pairs), &Method::GET, &HeaderMap::new(), &Bytes::new());
+        assert!(matches!(build(&[("q", "x")]), Err(AppError::BadRequest(_))));
+        assert!(matches!(build(&[("url", "not a url")]), Err(AppError::BadRequest(_))));
+        assert!(matches!(build(&[("url", "file:///etc/passwd")]), Err(AppError::BadRequest(_))));
+        assert!(matches!(
+            build(&[("url", "https://example.com/"), ("auth", "basic")]),
+            Err(AppError::BadRequest(_))
+        ));
+        assert!(build(&[("url", "https://example.com/")]).unwrap().body.is_none());
+    }
+}
@@ -0,0 +1,265 @@
+use serde::{Deserialize, Serialize};
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+use std::{collections::HashMap, sync::Arc};
+use tracing::info;
+
+use crate::{
+    errors::AppError,
+    models::{AppState, CurlRequest},
+    services::execute_curl_command,
+    utils::filename_from_url,
+};
+
+/// 期望的校验和, 可以直接给出, 也可以从校验和文件 (`sha256sum` 格式) 中读取
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ChecksumSpec {
+    pub sha256: Option<String>,
+    pub sha1: Option<String>,
+    /// 校验和文件地址, 例如 `https://example.com/release/SHA256SUMS`
+    pub checksum_url: Option<String>,
+}
+
+/// 响应内容的摘要, 总是会计算
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Digests {
+    pub sha256: String,
+    pub sha1: String,
+}
+
+/// 边接收边计算摘要, 大响应不需要在下载完成后再遍历一次
+#[derive(Debug, Clone, Default)]
+pub struct DigestHasher {
+    sha256: Sha256,
+    sha1: Sha1,
+}
+
+impl DigestHasher {
+    pub fn update(&mut self, data: &[u8]) {
+        self.sha256.update(data);
+        self.sha1.update(data);
+    }
+
+    pub fn finish(self) -> Digests {
+        Digests {
+            sha256: hex::encode(self.sha256.finalize()),
+            sha1: hex::encode(self.sha1.finalize()),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ExpectedChecksum {
+    pub algorithm: &'static str,
+    pub value: String,
+}
+
+impl ChecksumSpec {
+    /// 从 `sha256`、`sha1`、`checksum_url` 查询参数解析
+    pub fn from_params(params: &HashMap<String, String>) -> Option<Self> {
+        let spec = ChecksumSpec {
+            sha256: params.get("sha256").cloned(),
+            sha1: params.get("sha1").cloned(),
+            checksum_url: params.get("checksum_url").cloned(),
+        };
+        (spec.sha256.is_some() || spec.sha1.is_some() || spec.checksum_url.is_some()).then_some(spec)
+    }
+}
+
+impl Digests {
+    pub fn compute(data: &[u8]) -> Self {
+        let mut hasher = DigestHasher::default();
+        hasher.update(data);
+        hasher.finish()
+    }
+
+    /// 与全部期望值比较, 第一个不一致的返回 `ChecksumMismatch`
+    pub fn verify(&self, expected: &[ExpectedChecksum]) -> Result<(), AppError> {
+        for checksum in expected {
+            let actual = match checksum.algorithm {
+                "sha1" => &self.sha1,
+                _ => &self.sha256,
+            };
+            if !actual.eq_ignore_ascii_case(&checksum.value) {
+                return Err(AppError::ChecksumMismatch {
+                    algorithm: checksum.algorithm.to_string(),
+                    expected: checksum.value.to_ascii_lowercase(),
+                    actual: actual.clone(),
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+/// 在请求目标之前确定期望的校验和, 校验和文件通过同一套流程获取
+pub async fn expected_checksums(
+    state: &Arc<AppState>,
+    spec: &ChecksumSpec,
+    target_url: &str,
+) -> Result<Vec<ExpectedChecksum>, AppError> {
+    let mut expected = Vec::new();
+    if let Some(value) = &spec.sha256 {
+        expected.push(parse_expected("sha256", value)?);
+    }
+    if let Some(value) = &spec.sha1 {
+        expected.push(parse_expected("sha1", value)?);
+    }
+
+    if let Some(checksum_url) = &spec.checksum_url {
+        info!("Fetching checksum file {}", checksum_url);
+        let request = CurlRequest {
+            url: checksum_url.clone(),
+            method: "GET".to_string(),
+            ..Default::default()
+        };
+        // 与 execute_curl_command 互相调用, 需要装箱
+        let response = Box::pin(execute_curl_command(state, &request)).await?;
+        let content = match (response.status_code, response.response_body) {
+            (Some(200..=299), Some(body)) => body,
+            (status, _) => {
+                return Err(AppError::BadRequest(format!(
+                    "Failed to fetch checksum file {} (status {:?}{})",
+                    checksum_url,
+                    status,
+                    response.error.map(|e| format!(", {}", e.trim())).unwrap_or_default()
+                )));
+            }
+        };
+        expected.push(checksum_from_file(&content, target_url).ok_or_else(|| {
+            AppError::BadRequest(format!("No checksum for {} found in {}", target_url, checksum_url))
+        })?);
+    }
+
+    Ok(expected)
+}
+
+fn parse_expected(algorithm: &'static str, value: &str) -> Result<ExpectedChecksum, AppError> {
+    let value = value.trim();
+    if algorithm_for_length(value.len()) != Some(algorithm) || !value.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(AppError::BadRequest(format!("Invalid {} checksum '{}'", algorithm, value)));
+    }
+    Ok(ExpectedChecksum {
+        algorithm,
+        value: value.to_ascii_lowercase(),
+    })
+}
+
+fn algorithm_for_length(length: usize) -> Option<&'static str> {
+    match length {
+        64 => Some("sha256"),
+        40 => Some("sha1"),
+        _ => None,
+    }
+}
+
+/// 支持 `<hash>  <file>`、`<hash> *<file>`、`SHA256 (<file>) = <hash>` 以及只有一个 hash 的文件,
+/// 有多条记录时按目标 URL 的文件名选择
+fn checksum_from_file(content: &str, target_url: &str) -> Option<ExpectedChecksum> {
+    let target = filename_from_url(target_url);
+    let mut entries = Vec::new();
+
+    for line in content.lines().map(str::trim).filter(|line| !line.is_empty() && !line.starts_with('#')) {
+        let (hash, file) = if let Some((head, hash)) = line.rsplit_once(" = ") {
+            // BSD 格式: SHA256 (file) = hash
+            let file = head.split_once('(').and_then(|(_, rest)| rest.strip_suffix(')'));
+            (hash.trim(), file.map(str::to_string))
+        } else {
+            let mut parts = line.splitn(2, char::is_whitespace);
+            let hash = parts.next().unwrap_or_default();
+            let file = parts.next().map(|file| file.trim().trim_start_matches('*').to_string());
+            (hash, file)
+        };
+        let Some(algorithm) = algorithm_for_length(hash.len()) else {
+            continue;
+        };
+        if !hash.chars().all(|c| c.is_ascii_hexdigit()) {
+            continue;
+        }
+        entries.push((
+            file,
+            ExpectedChecksum {
+                algorithm,
+                value: hash.to_ascii_lowercase(),
+            },
+        ));
+    }
+
+    let matched = entries.iter().position(|(file, _)| {
+        file.as_deref()
+            .and_then(|file| file.rsplit('/').next())
+            .is_some_and(|file| Some(file) == target.as_deref())
+    });
+    match matched {
+        Some(index) => Some(entries.swap_remove(index).1),
+        None if entries.len() == 1 => entries.pop().map(|(_, checksum)| checksum),
+        None => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SHA256: &str = "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824";
+    const SHA1: &str = "aaf4c61ddcc5e8a2dabede0f3b482cd9aea9434d";
+
+    #[test]
+    fn digests_are_the_same_when_hashed_in_pieces() {
+        let digests = Digests::compute(b"hello");
+        assert_eq!(digests.sha256, SHA256);
+        assert_eq!(digests.sha1, SHA1);
+
+        let mut hasher = DigestHasher::default();
+        for piece in [&b"he"[..], b"", b"llo"] {
+            hasher.update(piece);
+        }
+        let streamed = hasher.finish();
+        assert_eq!((streamed.sha256, streamed.sha1), (digests.sha256, digests.sha1));
+    }
+
+    #[test]
+    fn expected_values_are_validated() {
+        let expected = parse_expected("sha256", &format!(" {} ", SHA256.to_ascii_uppercase())).unwrap();
+        assert_eq!((expected.algorithm, expected.value.as_str()), ("sha256", SHA256));
+        assert!(parse_expected("sha1", SHA1).is_ok());
+        // 长度与算法不符、非十六进制字符
+        assert!(parse_expected("sha1", SHA256).is_err());
+        assert!(parse_expected("sha256", &SHA256.replace('2', "g")).is_err());
+        assert!(parse_expected("sha256", "").is_err());
+
+        assert!(Digests::compute(b"hello").verify(&[expected]).is_ok());
+        let mismatch = parse_expected("sha1", &"0".repeat(40)).unwrap();
+        assert!(matches!(
+            Digests::compute(b"hello").verify(&[mismatch]),
+            Err(AppError::ChecksumMismatch { algorithm, .. }) if algorithm == "sha1"
+        ));
+    }
+
+    #[test]
+    fn checksum_files_are_matched_by_file_name() {
+        let url = "https://example.com/releases/tool-1.2.tar.gz?mirror=1";
+        let content = format!(
+            "# release checksums\n{}  tool-1.1.tar.gz\n{} *dist/tool-1.2.tar.gz\n",
+            "0".repeat(64),
+            SHA256
+        );
+        assert_eq!(checksum_from_file(&content, url).unwrap().value, SHA256);
+
+        let bsd = format!("SHA1 (tool-1.2.tar.gz) = {}\nSHA1 (other.zip) = {}\n", SHA1, "1".repeat(40));
+        let checksum = checksum_from_file(&bsd, url).unwrap();
+        assert_eq!((checksum.algorithm, checksum.value.as_str()), ("sha1", SHA1));
+    }
+
+    #[test]
+    fn single_entry_files_are_used_as_is() {
+        let url = "https://example.com/releases/tool-1.2.tar.gz";
+        assert_eq!(checksum_from_file(&format!("{}\n", SHA256), url).unwrap().value, SHA256);
+        assert_eq!(checksum_from_file(&format!("{}  other.tar.gz", SHA256), url).unwrap().value, SHA256);
+
+        // 多条记录都不匹配、或没有有效的 hash
+        let content = format!("{}  a.tar.gz\n{}  b.tar.gz\n", SHA256, SHA256);
+        assert!(checksum_from_file(&content, url).is_none());
+        assert!(checksum_from_file("not-a-hash  tool-1.2.tar.gz\n", url).is_none());
+    }
+}
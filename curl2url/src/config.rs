@@ -1,45 +1,809 @@
 use anyhow::Result;
 use serde::Deserialize;
-use std::path::PathBuf;
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+use tokio::signal::unix::{signal, SignalKind};
+use tracing::{error, info, warn};
+
+use crate::{auth::AuthSpec, models::AppState};
 
 #[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct ServerConfig {
     pub listen_addr: String,
+    /// 返回给用户的下载链接使用的地址, 例如 `https://tools.example.com`
+    #[serde(default)]
+    pub public_base_url: Option<String>,
+    /// 允许通过 X-Forwarded-Host/Proto 和 Origin 指定公开地址的代理 IP 或 CIDR
+    #[serde(default)]
+    pub trusted_proxies: Vec<String>,
 }
 
 #[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct ProxyConfig {
     pub temp_file_host_url: String,
     pub max_response_size_bytes: usize,
+    /// 超过大小限制的响应的存储位置
+    #[serde(default)]
+    pub sink: SinkKind,
+    #[serde(default)]
+    pub local: LocalSinkConfig,
+    #[serde(default)]
+    pub s3: Option<S3SinkConfig>,
+    #[serde(default)]
+    pub segmented: SegmentedDownloadConfig,
 }
 
+/// 上游支持 Range 请求时, 大文件按段并行下载
 #[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SegmentedDownloadConfig {
+    pub enabled: bool,
+    pub segment_size_bytes: u64,
+    /// 同时下载的段数
+    pub max_parallel: usize,
+    /// 大响应下载的总大小上限, 分段下载和单连接下载 (`--max-filesize`) 都适用
+    pub max_total_bytes: u64,
+}
+
+impl Default for SegmentedDownloadConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            segment_size_bytes: 8 * 1024 * 1024,
+            max_parallel: 4,
+            max_total_bytes: 1024 * 1024 * 1024,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SinkKind {
+    /// 上传到 temp-file-host 的 `/upload`
+    #[default]
+    TempFileHost,
+    /// 保存到本地目录, 由本服务的 `/files` 提供下载
+    Local,
+    /// 上传到 S3 兼容的存储 (AWS S3、MinIO 等)
+    S3,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LocalSinkConfig {
+    pub dir: String,
+    /// 下载链接的前缀, 未设置时使用 `server.public_base_url`, 其次是 `http://<listen_addr>`
+    pub public_base_url: Option<String>,
+    /// 文件保留的时间, 0 表示不清理
+    pub retention_seconds: u64,
+}
+
+impl Default for LocalSinkConfig {
+    fn default() -> Self {
+        Self {
+            dir: "data/responses".to_string(),
+            public_base_url: None,
+            retention_seconds: 86400,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct S3SinkConfig {
+    /// 例如 `https://s3.us-east-1.amazonaws.com` 或 `http://minio:9000`, 使用 path-style 访问
+    pub endpoint: String,
+    pub bucket: String,
+    #[serde(default = "default_s3_region")]
+    pub region: String,
+    pub access_key: SecretValue,
+    pub secret_key: SecretValue,
+    #[serde(default)]
+    pub session_token: Option<SecretValue>,
+    /// 对象 key 的前缀
+    #[serde(default)]
+    pub prefix: String,
+    /// 公开读的访问地址前缀, 设置后直接返回 `<public_url>/<key>`, 否则返回预签名链接
+    #[serde(default)]
+    pub public_url: Option<String>,
+    #[serde(default = "default_presign_expires")]
+    pub presign_expires_seconds: u64,
+}
+
+fn default_s3_region() -> String {
+    "us-east-1".to_string()
+}
+
+fn default_presign_expires() -> u64 {
+    3600
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct CurlConfig {
     pub timeout_seconds: u64,
     pub follow_redirects: bool,
     pub include_headers: bool,
+    #[serde(default)]
+    pub max_redirects: Option<u32>,
+    #[serde(default)]
+    pub user_agent: Option<String>,
+    #[serde(default)]
+    pub proxy: Option<String>,
+    #[serde(default)]
+    pub insecure: bool,
+    #[serde(default)]
+    pub ca_cert: Option<String>,
+}
+
+/// 按上游 host 区分的请求配置, 未设置的字段沿用全局 `[curl]` 配置
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ProfileConfig {
+    pub name: String,
+    /// host 通配规则, 例如 `*.example.com`
+    #[serde(default)]
+    pub hosts: Vec<String>,
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+    pub timeout_seconds: Option<u64>,
+    pub follow_redirects: Option<bool>,
+    pub max_redirects: Option<u32>,
+    pub user_agent: Option<String>,
+    pub proxy: Option<String>,
+    pub insecure: Option<bool>,
+    pub ca_cert: Option<String>,
+    pub auth: Option<AuthSpec>,
+    /// `host:port:addr` 形式的 DNS 覆盖
+    #[serde(default)]
+    pub resolve: Vec<String>,
+    /// `host:port:connect_host:connect_port` 形式的连接目标覆盖
+    #[serde(default)]
+    pub connect_to: Vec<String>,
+}
+
+/// 出站请求的安全限制
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SecurityConfig {
+    /// 拒绝访问内网、回环等保留地址 (包括 resolve/connect_to 覆盖后的地址), 默认开启
+    #[serde(default = "default_block_private_networks")]
+    pub block_private_networks: bool,
+    /// 不受上述限制的 host 通配规则
+    #[serde(default)]
+    pub allowed_hosts: Vec<String>,
+}
+
+impl Default for SecurityConfig {
+    fn default() -> Self {
+        Self {
+            block_private_networks: default_block_private_networks(),
+            allowed_hosts: Vec::new(),
+        }
+    }
+}
+
+fn default_block_private_networks() -> bool {
+    true
+}
+
+/// 命名 secret, 可以直接写值, 也可以通过 `{ env = "VAR" }` 从环境变量读取
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum SecretValue {
+    Plain(String),
+    Env { env: String },
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct OAuth2ClientConfig {
+    pub name: String,
+    pub token_url: String,
+    pub client_id: String,
+    pub client_secret: SecretValue,
+    pub scope: Option<String>,
+}
+
+/// 定时可用性检查
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MonitorCheckConfig {
+    pub name: String,
+    pub url: String,
+    #[serde(default = "default_monitor_method")]
+    pub method: String,
+    pub interval_seconds: u64,
+    /// 单次检查的总超时 (`--max-time`), 未设置时使用 `interval_seconds`
+    pub timeout_seconds: Option<u64>,
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+    pub profile: Option<String>,
+    /// 未设置时任何小于 400 的状态码都视为正常
+    pub expect_status: Option<u16>,
+    pub expect_body_contains: Option<String>,
+    pub max_latency_ms: Option<u64>,
 }
 
 #[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MonitorConfig {
+    /// 状态变化 (up/down) 时 POST 通知的地址
+    pub webhook_url: Option<String>,
+    /// 每个监控项保留的历史记录条数
+    #[serde(default = "default_history_size")]
+    pub history_size: usize,
+}
+
+impl Default for MonitorConfig {
+    fn default() -> Self {
+        Self {
+            webhook_url: None,
+            history_size: default_history_size(),
+        }
+    }
+}
+
+/// webhook 请求收集器
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BinsConfig {
+    pub max_bins: usize,
+    pub max_requests_per_bin: usize,
+    /// 收集器创建后保留的时间
+    pub retention_seconds: u64,
+    /// 每个请求保存的最大请求体大小, 超出部分会被截断
+    pub max_body_bytes: usize,
+    /// 所有收集器保存的请求体总大小, 超出时丢弃最早收到的请求
+    pub max_total_body_bytes: usize,
+}
+
+impl Default for BinsConfig {
+    fn default() -> Self {
+        Self {
+            max_bins: 100,
+            max_requests_per_bin: 100,
+            retention_seconds: 86400,
+            max_body_bytes: 1024 * 1024,
+            max_total_body_bytes: 64 * 1024 * 1024,
+        }
+    }
+}
+
+/// 最近执行的请求记录, 用于响应对比
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HistoryConfig {
+    /// 保留的记录条数, 0 表示不记录
+    pub max_entries: usize,
+}
+
+impl Default for HistoryConfig {
+    fn default() -> Self {
+        Self { max_entries: 100 }
+    }
+}
+
+/// `POST /snapshot` 的默认上限, 请求中只能调低
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SnapshotConfig {
+    /// 页面之外最多保存的资源数
+    pub max_assets: usize,
+    /// 页面和资源的总字节数上限
+    pub max_total_bytes: u64,
+}
+
+impl Default for SnapshotConfig {
+    fn default() -> Self {
+        Self {
+            max_assets: 100,
+            max_total_bytes: 50 * 1024 * 1024,
+        }
+    }
+}
+
+/// `stream` 模式的超时, 请求中只能调低
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StreamConfig {
+    /// 超过该时间没有收到新数据时结束
+    pub idle_timeout_seconds: u64,
+    /// 单次流式请求的最长持续时间
+    pub max_duration_seconds: u64,
+}
+
+impl Default for StreamConfig {
+    fn default() -> Self {
+        Self {
+            idle_timeout_seconds: 60,
+            max_duration_seconds: 600,
+        }
+    }
+}
+
+/// `/ws` 中继和一次性探测的限制
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WebSocketConfig {
+    /// 一次性探测默认收集回复的时间
+    pub wait_seconds: u64,
+    /// 请求中 `wait` 的上限
+    pub max_wait_seconds: u64,
+    /// 中继连接两个方向都没有消息时关闭
+    pub relay_idle_timeout_seconds: u64,
+    /// 中继连接的最长持续时间
+    pub relay_max_duration_seconds: u64,
+    /// 单条消息的大小上限
+    pub max_message_bytes: usize,
+    /// 记录帧日志时每条消息保留的字符数
+    pub log_preview_chars: usize,
+}
+
+impl Default for WebSocketConfig {
+    fn default() -> Self {
+        Self {
+            wait_seconds: 5,
+            max_wait_seconds: 60,
+            relay_idle_timeout_seconds: 300,
+            relay_max_duration_seconds: 3600,
+            max_message_bytes: 1024 * 1024,
+            log_preview_chars: 200,
+        }
+    }
+}
+
+/// `POST /crawls` 爬取任务的上限和礼貌策略
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CrawlConfig {
+    /// 同时运行的任务数
+    pub max_jobs: usize,
+    /// 单个任务的页面数和深度上限, 请求中只能调低
+    pub max_pages: usize,
+    pub max_depth: usize,
+    /// 对同一主机两次请求之间的最小间隔, robots.txt 的 Crawl-delay 更大时以其为准
+    pub delay_ms: u64,
+    /// 请求中的 User-Agent, 也用于匹配 robots.txt 中的规则
+    pub user_agent: String,
+    /// 任务结束后保留结果的时间
+    pub retention_seconds: u64,
+}
+
+impl Default for CrawlConfig {
+    fn default() -> Self {
+        Self {
+            max_jobs: 4,
+            max_pages: 500,
+            max_depth: 5,
+            delay_ms: 500,
+            user_agent: "curl2url".to_string(),
+            retention_seconds: 3600,
+        }
+    }
+}
+
+/// 带 `{{variable}}` 占位符的请求模板
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TemplateConfig {
+    pub name: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    /// 模板的默认变量, 会被环境和请求中的同名变量覆盖
+    #[serde(default)]
+    pub variables: HashMap<String, SecretValue>,
+    /// 与 `POST /curl` 格式相同的请求描述, 字符串中可以使用占位符
+    pub request: serde_json::Value,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum VcrMode {
+    #[default]
+    Off,
+    /// 正常访问上游, 并将请求/响应保存为 fixture
+    Record,
+    /// 只从 fixture 返回响应, 不访问网络
+    Replay,
+}
+
+/// 录制/回放 (VCR) 模式, 用于离线测试
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct VcrConfig {
+    pub mode: VcrMode,
+    pub fixtures_dir: String,
+    /// 参与匹配的字段: method, url, body, profile 或 header:<name>
+    pub match_on: Vec<String>,
+}
+
+impl Default for VcrConfig {
+    fn default() -> Self {
+        Self {
+            mode: VcrMode::Off,
+            fixtures_dir: "fixtures".to_string(),
+            match_on: vec!["method".to_string(), "url".to_string()],
+        }
+    }
+}
+
+fn default_monitor_method() -> String {
+    "GET".to_string()
+}
+
+fn default_history_size() -> usize {
+    1440
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct Config {
     pub server: ServerConfig,
     pub proxy: ProxyConfig,
     pub curl: CurlConfig,
+    #[serde(default)]
+    pub security: SecurityConfig,
+    #[serde(default)]
+    pub profiles: Vec<ProfileConfig>,
+    #[serde(default)]
+    pub secrets: HashMap<String, SecretValue>,
+    #[serde(default)]
+    pub oauth2_clients: Vec<OAuth2ClientConfig>,
+    #[serde(default)]
+    pub monitor: MonitorConfig,
+    #[serde(default)]
+    pub monitors: Vec<MonitorCheckConfig>,
+    #[serde(default)]
+    pub bins: BinsConfig,
+    #[serde(default)]
+    pub vcr: VcrConfig,
+    #[serde(default)]
+    pub history: HistoryConfig,
+    #[serde(default)]
+    pub snapshot: SnapshotConfig,
+    #[serde(default)]
+    pub crawl: CrawlConfig,
+    #[serde(default)]
+    pub stream: StreamConfig,
+    #[serde(default)]
+    pub websocket: WebSocketConfig,
+    #[serde(default)]
+    pub templates: Vec<TemplateConfig>,
+    /// 命名的变量集合 (dev/staging/prod 等), 供模板使用
+    #[serde(default)]
+    pub environments: HashMap<String, HashMap<String, SecretValue>>,
+}
+
+impl SecretValue {
+    pub fn resolve(&self) -> Result<String> {
+        match self {
+            SecretValue::Plain(value) => Ok(value.clone()),
+            SecretValue::Env { env } => std::env::var(env)
+                .map_err(|_| anyhow::anyhow!("Environment variable '{}' is not set", env)),
+        }
+    }
+}
+
+impl CurlConfig {
+    /// 将 profile 中设置的字段覆盖到当前配置上
+    pub fn merge(&self, profile: &ProfileConfig) -> CurlConfig {
+        CurlConfig {
+            timeout_seconds: profile.timeout_seconds.unwrap_or(self.timeout_seconds),
+            follow_redirects: profile.follow_redirects.unwrap_or(self.follow_redirects),
+            include_headers: self.include_headers,
+            max_redirects: profile.max_redirects.or(self.max_redirects),
+            user_agent: profile.user_agent.clone().or_else(|| self.user_agent.clone()),
+            proxy: profile.proxy.clone().or_else(|| self.proxy.clone()),
+            insecure: profile.insecure.unwrap_or(self.insecure),
+            ca_cert: profile.ca_cert.clone().or_else(|| self.ca_cert.clone()),
+        }
+    }
 }
 
+/// 环境变量覆盖的前缀, `CURL2URL__PROXY__MAX_RESPONSE_SIZE_BYTES=2097152` 对应 `[proxy] max_response_size_bytes`
+const ENV_PREFIX: &str = "CURL2URL__";
+
 impl Config {
-    pub fn new() -> Result<Self> {
-        let config_path = PathBuf::from("config/app_config.toml");
-        if !config_path.exists() {
-            return Err(anyhow::anyhow!("Config file not found at {:?}", config_path));
-        }
-        
-        let config_str = std::fs::read_to_string(&config_path)
-            .map_err(|e| anyhow::anyhow!("Failed to read config file {:?}: {}", config_path, e))?;
-        
-        let config: Config = toml::from_str(&config_str)
-            .map_err(|e| anyhow::anyhow!("Failed to parse config: {}", e))?;
-        
+    /// 读取配置文件, 应用 `CURL2URL__SECTION__KEY` 环境变量覆盖并校验
+    pub fn load(path: &Path) -> Result<Self> {
+        let config_str = std::fs::read_to_string(path)
+            .map_err(|e| anyhow::anyhow!("Failed to read config file {}: {}", path.display(), e))?;
+
+        let mut overrides: Vec<(String, String)> = std::env::vars()
+            .filter(|(name, _)| name.starts_with(ENV_PREFIX))
+            .collect();
+        overrides.sort();
+
+        // 没有覆盖时直接解析原文, 错误信息中的行号与配置文件一致
+        let config: Config = if overrides.is_empty() {
+            toml::from_str(&config_str).map_err(|e| anyhow::anyhow!("Invalid config file {}: {}", path.display(), e))?
+        } else {
+            let table: toml::Table = toml::from_str(&config_str)
+                .map_err(|e| anyhow::anyhow!("Invalid config file {}: {}", path.display(), e))?;
+            let mut root = toml::Value::Table(table);
+            for (name, value) in &overrides {
+                apply_override(&mut root, name, value)?;
+            }
+            let names: Vec<&str> = overrides.iter().map(|(name, _)| name.as_str()).collect();
+            root.try_into().map_err(|e| {
+                anyhow::anyhow!(
+                    "Invalid config file {} with overrides {}: {}",
+                    path.display(),
+                    names.join(", "),
+                    e
+                )
+            })?
+        };
+
+        config
+            .validate()
+            .map_err(|e| anyhow::anyhow!("Invalid config file {}: {}", path.display(), e))?;
         Ok(config)
     }
-} 
\ No newline at end of file
+
+    /// 类型之外的检查, 一次列出全部问题
+    fn validate(&self) -> Result<()> {
+        let mut problems = Vec::new();
+        if url::Url::parse(&self.proxy.temp_file_host_url).is_err() {
+            problems.push(format!(
+                "proxy.temp_file_host_url '{}' is not a valid URL",
+                self.proxy.temp_file_host_url
+            ));
+        }
+        if self.proxy.max_response_size_bytes == 0 {
+            problems.push("proxy.max_response_size_bytes must be greater than 0".to_string());
+        }
+        if self.proxy.segmented.enabled
+            && (self.proxy.segmented.segment_size_bytes == 0
+                || self.proxy.segmented.max_parallel == 0
+                || self.proxy.segmented.max_total_bytes == 0)
+        {
+            problems.push("proxy.segmented.segment_size_bytes, max_parallel and max_total_bytes must be greater than 0".to_string());
+        }
+        if self.curl.timeout_seconds == 0 {
+            problems.push("curl.timeout_seconds must be greater than 0".to_string());
+        }
+        if let Some(public_base_url) = &self.server.public_base_url
+            && url::Url::parse(public_base_url).is_err()
+        {
+            problems.push(format!("server.public_base_url '{}' is not a valid URL", public_base_url));
+        }
+
+        let mut names = HashSet::new();
+        for profile in &self.profiles {
+            if !names.insert(&profile.name) {
+                problems.push(format!("profile '{}' is defined more than once", profile.name));
+            }
+        }
+        let mut names = HashSet::new();
+        for client in &self.oauth2_clients {
+            if !names.insert(&client.name) {
+                problems.push(format!("oauth2 client '{}' is defined more than once", client.name));
+            }
+        }
+        let mut names = HashSet::new();
+        for check in &self.monitors {
+            if !names.insert(&check.name) {
+                problems.push(format!("monitor '{}' is defined more than once", check.name));
+            }
+            if check.interval_seconds == 0 {
+                problems.push(format!("monitor '{}': interval_seconds must be greater than 0", check.name));
+            }
+            if check.timeout_seconds == Some(0) {
+                problems.push(format!("monitor '{}': timeout_seconds must be greater than 0", check.name));
+            }
+            if let Some(profile) = &check.profile
+                && !self.profiles.iter().any(|candidate| &candidate.name == profile)
+            {
+                problems.push(format!("monitor '{}' uses unknown profile '{}'", check.name, profile));
+            }
+        }
+
+        let checks = [
+            crate::vcr::validate_rules(&self.vcr),
+            crate::templates::validate_templates(self),
+            crate::sinks::validate_sink(self),
+            crate::links::validate_trusted_proxies(&self.server),
+        ];
+        problems.extend(checks.into_iter().filter_map(|check| check.err().map(|e| e.to_string())));
+
+        if !problems.is_empty() {
+            return Err(anyhow::anyhow!("{}", problems.join("; ")));
+        }
+        Ok(())
+    }
+
+    /// 重新加载时需要重启才能生效的设置保留当前值, 返回合并后的配置和被忽略的设置
+    pub fn reloaded(&self, mut next: Config) -> (Config, Vec<&'static str>) {
+        let mut ignored = Vec::new();
+        keep_current("server.listen_addr", &self.server.listen_addr, &mut next.server.listen_addr, &mut ignored);
+        // `/files` 路由只在启动时按 sink 挂载
+        keep_current("proxy.sink", &self.proxy.sink, &mut next.proxy.sink, &mut ignored);
+        keep_current("proxy.local.dir", &self.proxy.local.dir, &mut next.proxy.local.dir, &mut ignored);
+        // 监控任务在启动时创建
+        keep_current("monitors", &self.monitors, &mut next.monitors, &mut ignored);
+        (next, ignored)
+    }
+}
+
+fn keep_current<T: Clone + PartialEq>(name: &'static str, current: &T, next: &mut T, ignored: &mut Vec<&'static str>) {
+    if next != current {
+        *next = current.clone();
+        ignored.push(name);
+    }
+}
+
+/// 值为映射的配置项, 其后的一段是映射的键 (请求头名、密钥名、环境名等), `*` 匹配任意下标或键
+const OVERRIDE_MAP_PATHS: &[&[&str]] = &[
+    &["profiles", "*", "headers"],
+    &["monitors", "*", "headers"],
+    &["templates", "*", "variables"],
+    &["templates", "*", "request", "headers"],
+    &["secrets"],
+    &["environments"],
+    &["environments", "*"],
+];
+
+/// 按 `__` 拆分变量名并写入对应的键, 数组 (例如 `PROFILES__0__PROXY`) 按下标访问
+///
+/// 字段名转为小写, 映射的键保留原样
+fn apply_override(root: &mut toml::Value, name: &str, raw: &str) -> Result<()> {
+    let mut keys: Vec<String> = Vec::new();
+    for key in name[ENV_PREFIX.len()..].split("__") {
+        let map_key = OVERRIDE_MAP_PATHS.iter().any(|path| {
+            path.len() == keys.len() && path.iter().zip(&keys).all(|(pattern, key)| *pattern == "*" || pattern == key)
+        });
+        keys.push(if map_key { key.to_string() } else { key.to_ascii_lowercase() });
+    }
+    if keys.iter().any(String::is_empty) {
+        return Err(anyhow::anyhow!("Invalid config override {}: empty key", name));
+    }
+    let value = parse_override_value(raw);
+
+    let mut current = root;
+    for (position, key) in keys.iter().enumerate() {
+        current = match current {
+            toml::Value::Table(table) => {
+                // 下标只能访问配置文件中已有的数组项
+                let indexed = keys.get(position + 1).is_some_and(|next| next.parse::<usize>().is_ok());
+                if indexed && !table.contains_key(key) {
+                    return Err(anyhow::anyhow!(
+                        "Invalid config override {}: '{}' is not defined in the config file",
+                        name,
+                        key
+                    ));
+                }
+                table
+                    .entry(key.clone())
+                    .or_insert_with(|| toml::Value::Table(toml::Table::new()))
+            }
+            toml::Value::Array(items) => {
+                let len = items.len();
+                key.parse::<usize>()
+                    .ok()
+                    .and_then(|index| items.get_mut(index))
+                    .ok_or_else(|| {
+                        anyhow::anyhow!(
+                            "Invalid config override {}: '{}' is not an index of an array with {} entries",
+                            name,
+                            key,
+                            len
+                        )
+                    })?
+            }
+            _ => {
+                return Err(anyhow::anyhow!(
+                    "Invalid config override {}: '{}' is not inside a table or array",
+                    name,
+                    key
+                ));
+            }
+        };
+    }
+    *current = value;
+    Ok(())
+}
+
+/// 按 TOML 值解析 (数字、布尔、数组等), 无法解析时作为字符串, 例如 `0.0.0.0:3000`
+fn parse_override_value(raw: &str) -> toml::Value {
+    format!("value = {}", raw)
+        .parse::<toml::Table>()
+        .ok()
+        .and_then(|mut table| table.remove("value"))
+        .unwrap_or_else(|| toml::Value::String(raw.to_string()))
+}
+
+/// 收到 `SIGHUP` 时重新加载配置, 校验失败时保留当前配置
+pub fn spawn_reload_on_sighup(state: Arc<AppState>, path: PathBuf) {
+    tokio::spawn(async move {
+        let mut hangup = match signal(SignalKind::hangup()) {
+            Ok(hangup) => hangup,
+            Err(e) => {
+                warn!("Failed to listen for SIGHUP, configuration reload is disabled: {}", e);
+                return;
+            }
+        };
+        while hangup.recv().await.is_some() {
+            let next = match Config::load(&path) {
+                Ok(next) => next,
+                Err(e) => {
+                    error!("Failed to reload configuration, keeping the current one: {}", e);
+                    continue;
+                }
+            };
+            let (next, ignored) = state.config().reloaded(next);
+            if !ignored.is_empty() {
+                warn!("Changes to {} require a restart and were not applied", ignored.join(", "));
+            }
+            state.replace_config(next);
+            // OAuth2 客户端配置可能已变化
+            state.oauth2_tokens.lock().await.clear();
+            info!("Reloaded configuration from {}", path.display());
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn overridden(file: &str, overrides: &[(&str, &str)]) -> Result<toml::Value> {
+        let mut root = toml::Value::Table(toml::from_str(file)?);
+        for (name, value) in overrides {
+            apply_override(&mut root, name, value)?;
+        }
+        Ok(root)
+    }
+
+    #[test]
+    fn overrides_lowercase_fields_and_parse_values() {
+        let root = overridden(
+            "[server]\nlisten_addr = \"0.0.0.0:3000\"\n",
+            &[
+                ("CURL2URL__SERVER__LISTEN_ADDR", "127.0.0.1:4000"),
+                ("CURL2URL__PROXY__MAX_RESPONSE_SIZE_BYTES", "2097152"),
+                ("CURL2URL__SECURITY__ALLOWED_HOSTS", "[\"a\", \"b\"]"),
+                ("CURL2URL__CURL__USER_AGENT", "'\"1.0\"'"),
+            ],
+        )
+        .unwrap();
+        assert_eq!(root["server"]["listen_addr"].as_str(), Some("127.0.0.1:4000"));
+        assert_eq!(root["proxy"]["max_response_size_bytes"].as_integer(), Some(2097152));
+        assert_eq!(root["security"]["allowed_hosts"].as_array().map(Vec::len), Some(2));
+        assert_eq!(root["curl"]["user_agent"].as_str(), Some("\"1.0\""));
+    }
+
+    #[test]
+    fn overrides_keep_map_keys_verbatim() {
+        let root = overridden(
+            "[[profiles]]\nname = \"api\"\nhosts = [\"*\"]\n",
+            &[
+                ("CURL2URL__PROFILES__0__HEADERS__X-Api-Key", "abc"),
+                ("CURL2URL__PROFILES__0__PROXY", "http://proxy:3128"),
+                ("CURL2URL__SECRETS__api_Token__ENV", "API_TOKEN"),
+                ("CURL2URL__ENVIRONMENTS__Staging__BASE_URL", "https://staging"),
+            ],
+        )
+        .unwrap();
+        let profile = &root["profiles"][0];
+        assert_eq!(profile["headers"]["X-Api-Key"].as_str(), Some("abc"));
+        assert_eq!(profile["proxy"].as_str(), Some("http://proxy:3128"));
+        assert_eq!(root["secrets"]["api_Token"]["env"].as_str(), Some("API_TOKEN"));
+        assert_eq!(root["environments"]["Staging"]["BASE_URL"].as_str(), Some("https://staging"));
+    }
+
+    #[test]
+    fn overrides_only_index_existing_array_entries() {
+        let file = "[[profiles]]\nname = \"api\"\nhosts = [\"*\"]\n";
+        assert!(overridden(file, &[("CURL2URL__PROFILES__1__PROXY", "x")]).is_err());
+        assert!(overridden(file, &[("CURL2URL__MONITORS__0__URL", "x")]).is_err());
+        assert!(overridden(file, &[("CURL2URL__PROFILES__NAME", "x")]).is_err());
+        assert!(overridden(file, &[("CURL2URL__SERVER____PORT", "x")]).is_err());
+    }
+}
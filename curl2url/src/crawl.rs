@@ -0,0 +1,715 @@
+use axum::{
+    extract::{Json, Path, State},
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+};
+use chrono::{DateTime, Duration, Utc};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet, VecDeque},
+    sync::{Arc, LazyLock},
+    time::Instant,
+};
+use tracing::{error, info};
+use uuid::Uuid;
+
+use crate::{
+    bins::request_base_url,
+    errors::AppError,
+    format::decode_entities,
+    html::{self, LinkRef, LinkRewriter},
+    links::PublicBaseUrl,
+    models::{AppState, CurlRequest},
+    services::{fetch_raw, RawResponse},
+    vcr,
+};
+
+static TITLE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?is)<title[^>]*>(.*?)</title>").expect("valid title regex"));
+
+/// robots.txt 中的 Crawl-delay 最多等待的秒数
+const MAX_CRAWL_DELAY_SECONDS: f64 = 60.0;
+
+/// `POST /crawls` 的请求体
+#[derive(Debug, Clone, Deserialize)]
+pub struct CrawlSpec {
+    pub url: String,
+    /// 低于配置时生效
+    pub max_depth: Option<usize>,
+    pub max_pages: Option<usize>,
+    /// 只跟随与起始页面同一主机的链接
+    #[serde(default = "default_same_host")]
+    pub same_host: bool,
+    /// 匹配完整 URL 的正则, `include` 非空时链接至少需要匹配其中一个
+    #[serde(default)]
+    pub include: Vec<String>,
+    #[serde(default)]
+    pub exclude: Vec<String>,
+    #[serde(default)]
+    pub profile: Option<String>,
+}
+
+fn default_same_host() -> bool {
+    true
+}
+
+/// 实际生效的爬取参数
+#[derive(Debug, Clone, Serialize)]
+pub struct CrawlOptions {
+    pub max_depth: usize,
+    pub max_pages: usize,
+    pub same_host: bool,
+    pub include: Vec<String>,
+    pub exclude: Vec<String>,
+    pub profile: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CrawlState {
+    Running,
+    Completed,
+    Cancelled,
+    /// 任务异常退出 (panic)
+    Failed,
+}
+
+/// 一个已抓取的页面
+#[derive(Debug, Clone, Serialize)]
+pub struct CrawlPage {
+    pub url: String,
+    pub final_url: String,
+    pub depth: usize,
+    pub status_code: Option<u16>,
+    pub title: Option<String>,
+    pub content_type: Option<String>,
+    pub size_bytes: u64,
+    /// 页面中的链接 (`a`/`area` 的 href, `iframe`/`frame` 的 src), 已解析为绝对地址
+    pub links: Vec<String>,
+    pub error: Option<String>,
+    pub fetched_at: DateTime<Utc>,
+}
+
+/// 发现了但没有抓取的地址
+#[derive(Debug, Clone, Serialize)]
+pub struct SkippedUrl {
+    pub url: String,
+    pub reason: String,
+}
+
+/// 后台爬取任务及其结果
+#[derive(Debug, Clone)]
+pub struct CrawlJob {
+    pub id: String,
+    pub url: String,
+    pub options: CrawlOptions,
+    pub state: CrawlState,
+    pub created_at: DateTime<Utc>,
+    pub finished_at: Option<DateTime<Utc>>,
+    /// 等待抓取的地址数
+    pub queued: usize,
+    /// 达到 `max_pages` 时队列中仍有地址
+    pub limit_reached: bool,
+    pub pages: Vec<CrawlPage>,
+    pub skipped: Vec<SkippedUrl>,
+}
+
+impl CrawlJob {
+    /// 请求失败或返回 4xx/5xx 的页面
+    fn broken_pages(&self) -> impl Iterator<Item = &CrawlPage> {
+        self.pages
+            .iter()
+            .filter(|page| page.error.is_some() || page.status_code.is_some_and(|code| code >= 400))
+    }
+
+    fn summary(&self) -> Value {
+        json!({
+            "id": self.id,
+            "url": self.url,
+            "state": self.state,
+            "created_at": self.created_at,
+            "finished_at": self.finished_at,
+            "pages_crawled": self.pages.len(),
+            "queued": self.queued,
+            "skipped": self.skipped.len(),
+            "broken": self.broken_pages().count(),
+            "limit_reached": self.limit_reached,
+        })
+    }
+}
+
+/// 编译后的爬取范围
+struct Scope {
+    start_host: Option<String>,
+    same_host: bool,
+    include: Vec<Regex>,
+    exclude: Vec<Regex>,
+}
+
+impl Scope {
+    fn contains(&self, url: &url::Url) -> bool {
+        (!self.same_host || url.host_str().map(str::to_string) == self.start_host)
+            && (self.include.is_empty() || self.include.iter().any(|pattern| pattern.is_match(url.as_str())))
+            && !self.exclude.iter().any(|pattern| pattern.is_match(url.as_str()))
+    }
+}
+
+/// 收集页面中可以继续跟随的链接
+#[derive(Default)]
+struct PageLinks {
+    links: Vec<String>,
+}
+
+impl LinkRewriter for PageLinks {
+    fn rewrite(&mut self, link: &LinkRef) -> Option<String> {
+        let followed = matches!(
+            (link.tag, link.attr),
+            ("a" | "area", "href") | ("iframe" | "frame", "src")
+        );
+        if followed && !self.links.iter().any(|existing| existing == link.url) {
+            self.links.push(link.url.to_string());
+        }
+        None
+    }
+}
+
+/// robots.txt 中适用于本服务的规则
+#[derive(Debug, Default)]
+struct Robots {
+    /// (是否允许, 路径模式, 模式长度)
+    rules: Vec<(bool, Regex, usize)>,
+    crawl_delay: Option<f64>,
+    /// robots.txt 无法获取 (5xx 或请求失败) 时全部禁止
+    disallow_all: bool,
+}
+
+impl Robots {
+    /// 按 RFC 9309 解析, 优先使用与 User-Agent 匹配的分组, 没有时使用 `*`
+    fn parse(text: &str, user_agent: &str) -> Self {
+        struct Group {
+            agents: Vec<String>,
+            rules: Vec<(bool, String)>,
+            crawl_delay: Option<f64>,
+        }
+
+        let token = user_agent.split('/').next().unwrap_or(user_agent).trim().to_ascii_lowercase();
+        let mut groups: Vec<Group> = Vec::new();
+        let mut in_agents = false;
+        for line in text.lines() {
+            let line = line.split('#').next().unwrap_or_default();
+            let Some((key, value)) = line.split_once(':') else {
+                continue;
+            };
+            let value = value.trim();
+            match key.trim().to_ascii_lowercase().as_str() {
+                "user-agent" => {
+                    if !in_agents {
+                        groups.push(Group {
+                            agents: Vec::new(),
+                            rules: Vec::new(),
+                            crawl_delay: None,
+                        });
+                    }
+                    if let Some(group) = groups.last_mut() {
+                        group.agents.push(value.to_ascii_lowercase());
+                    }
+                    in_agents = true;
+                }
+                key @ ("allow" | "disallow") => {
+                    in_agents = false;
+                    // 空的 Disallow 表示不限制
+                    if let Some(group) = groups.last_mut()
+                        && !value.is_empty()
+                    {
+                        group.rules.push((key == "allow", value.to_string()));
+                    }
+                }
+                "crawl-delay" => {
+                    in_agents = false;
+                    if let Some(group) = groups.last_mut() {
+                        group.crawl_delay = value.parse().ok().filter(|delay: &f64| delay.is_finite() && *delay >= 0.0);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        let matches_agent =
+            |group: &&Group| group.agents.iter().any(|agent| !agent.is_empty() && agent != "*" && token.contains(agent.as_str()));
+        let selected: Vec<&Group> = if groups.iter().any(|group| matches_agent(&group)) {
+            groups.iter().filter(matches_agent).collect()
+        } else {
+            groups
+                .iter()
+                .filter(|group| group.agents.iter().any(|agent| agent == "*"))
+                .collect()
+        };
+
+        Self {
+            rules: selected
+                .iter()
+                .flat_map(|group| &group.rules)
+                .filter_map(|(allow, pattern)| Some((*allow, robots_pattern(pattern)?, pattern.len())))
+                .collect(),
+            crawl_delay: selected.iter().find_map(|group| group.crawl_delay),
+            disallow_all: false,
+        }
+    }
+
+    /// 最长匹配的规则生效, 长度相同时 Allow 优先
+    fn allows(&self, url: &url::Url) -> bool {
+        if self.disallow_all {
+            return url.path() == "/robots.txt";
+        }
+        let path = match url.query() {
+            Some(query) => format!("{}?{}", url.path(), query),
+            None => url.path().to_string(),
+        };
+        self.rules
+            .iter()
+            .filter(|(_, pattern, _)| pattern.is_match(&path))
+            .max_by_key(|(allow, _, len)| (*len, *allow))
+            .is_none_or(|(allow, _, _)| *allow)
+    }
+}
+
+/// `*` 匹配任意字符, 结尾的 `$` 表示路径结束
+fn robots_pattern(pattern: &str) -> Option<Regex> {
+    let (pattern, anchored) = match pattern.strip_suffix('$') {
+        Some(pattern) => (pattern, true),
+        None => (pattern, false),
+    };
+    let body = pattern.split('*').map(regex::escape).collect::<Vec<_>>().join(".*");
+    Regex::new(&format!("^{}{}", body, if anchored { "$" } else { "" })).ok()
+}
+
+/// 删除已结束且超过保留时间的任务
+async fn prune_finished(state: &Arc<AppState>) {
+    let cutoff = Utc::now() - Duration::seconds(state.config().crawl.retention_seconds as i64);
+    state
+        .crawls
+        .write()
+        .await
+        .retain(|_, job| job.finished_at.is_none_or(|finished_at| finished_at > cutoff));
+}
+
+/// `POST /crawls`: 启动后台爬取任务, 返回任务状态和报告地址
+pub async fn create_crawl(
+    State(state): State<Arc<AppState>>,
+    PublicBaseUrl(public_base): PublicBaseUrl,
+    headers: HeaderMap,
+    Json(spec): Json<CrawlSpec>,
+) -> Result<Response, AppError> {
+    vcr::ensure_live(&state.config().vcr, "Crawls")?;
+    let start = url::Url::parse(&spec.url).map_err(|e| AppError::BadRequest(format!("Invalid start URL: {}", e)))?;
+    if !matches!(start.scheme(), "http" | "https") {
+        return Err(AppError::BadRequest(format!("Unsupported scheme '{}'", start.scheme())));
+    }
+    let compile = |patterns: &[String]| {
+        patterns
+            .iter()
+            .map(|pattern| {
+                Regex::new(pattern).map_err(|e| AppError::BadRequest(format!("Invalid pattern '{}': {}", pattern, e)))
+            })
+            .collect::<Result<Vec<_>, _>>()
+    };
+    let scope = Scope {
+        start_host: start.host_str().map(str::to_string),
+        same_host: spec.same_host,
+        include: compile(&spec.include)?,
+        exclude: compile(&spec.exclude)?,
+    };
+
+    let config = state.config().crawl.clone();
+    let options = CrawlOptions {
+        max_depth: spec.max_depth.unwrap_or(config.max_depth).min(config.max_depth),
+        max_pages: spec.max_pages.unwrap_or(config.max_pages).min(config.max_pages),
+        same_host: spec.same_host,
+        include: spec.include,
+        exclude: spec.exclude,
+        profile: spec.profile,
+    };
+
+    prune_finished(&state).await;
+    let id = Uuid::new_v4().simple().to_string();
+    let job = CrawlJob {
+        id: id.clone(),
+        url: start.to_string(),
+        options,
+        state: CrawlState::Running,
+        created_at: Utc::now(),
+        finished_at: None,
+        queued: 1,
+        limit_reached: false,
+        pages: Vec::new(),
+        skipped: Vec::new(),
+    };
+    {
+        let mut crawls = state.crawls.write().await;
+        let running = crawls.values().filter(|job| job.state == CrawlState::Running).count();
+        if running >= config.max_jobs {
+            return Err(AppError::BadRequest(format!(
+                "Too many running crawls (max {}), try again later",
+                config.max_jobs
+            )));
+        }
+        crawls.insert(id.clone(), job.clone());
+    }
+    info!("Starting crawl {} from {}", id, job.url);
+    let crawl = tokio::spawn(run_crawl(state.clone(), job.clone(), scope));
+    // 任务异常退出时标记为失败, 否则会一直占用 max_jobs 的名额
+    let supervisor_state = state.clone();
+    let crawl_id = id.clone();
+    tokio::spawn(async move {
+        if let Err(e) = crawl.await {
+            error!("Crawl {} aborted: {}", crawl_id, e);
+            update(&supervisor_state, &crawl_id, |job| {
+                job.state = CrawlState::Failed;
+                job.finished_at = Some(Utc::now());
+            })
+            .await;
+        }
+    });
+
+    let base_url = public_base.unwrap_or_else(|| request_base_url(&headers));
+    let mut body = job.summary();
+    body["status_url"] = json!(format!("{}/crawls/{}", base_url, id));
+    body["report_url"] = json!(format!("{}/crawls/{}/report", base_url, id));
+    body["broken_links_url"] = json!(format!("{}/crawls/{}/broken", base_url, id));
+    Ok((StatusCode::ACCEPTED, Json(body)).into_response())
+}
+
+/// `GET /crawls`: 所有保留中的任务, 最新的在前
+pub async fn list_crawls(State(state): State<Arc<AppState>>) -> Json<Value> {
+    prune_finished(&state).await;
+    let crawls = state.crawls.read().await;
+    let mut jobs: Vec<&CrawlJob> = crawls.values().collect();
+    jobs.sort_by_key(|job| std::cmp::Reverse(job.created_at));
+    Json(json!({ "crawls": jobs.iter().map(|job| job.summary()).collect::<Vec<_>>() }))
+}
+
+/// `GET /crawls/{id}`: 任务进度
+pub async fn crawl_status(State(state): State<Arc<AppState>>, Path(id): Path<String>) -> Result<Json<Value>, AppError> {
+    let job = find(&state, &id).await?;
+    let mut body = job.summary();
+    body["options"] = json!(job.options);
+    Ok(Json(body))
+}
+
+/// `GET /crawls/{id}/report`: 全部页面和跳过的地址, 任务运行中时为当前结果
+pub async fn crawl_report(State(state): State<Arc<AppState>>, Path(id): Path<String>) -> Result<Json<Value>, AppError> {
+    let job = find(&state, &id).await?;
+    let mut body = job.summary();
+    body["options"] = json!(job.options);
+    body["pages"] = json!(job.pages);
+    body["skipped_urls"] = json!(job.skipped);
+    Ok(Json(body))
+}
+
+/// `GET /crawls/{id}/broken`: 失败的地址以及引用它们的页面
+pub async fn broken_links(State(state): State<Arc<AppState>>, Path(id): Path<String>) -> Result<Json<Value>, AppError> {
+    let job = find(&state, &id).await?;
+    let mut referrers: HashMap<&str, Vec<&str>> = HashMap::new();
+    for page in &job.pages {
+        for link in &page.links {
+            referrers.entry(link.as_str()).or_default().push(page.url.as_str());
+        }
+    }
+
+    let mut by_status: BTreeMap<String, usize> = BTreeMap::new();
+    let broken: Vec<Value> = job
+        .broken_pages()
+        .map(|page| {
+            let key = page
+                .status_code
+                .map(|code| code.to_string())
+                .unwrap_or_else(|| "error".to_string());
+            *by_status.entry(key).or_default() += 1;
+            json!({
+                "url": page.url,
+                "status_code": page.status_code,
+                "error": page.error,
+                "referrers": referrers.get(page.url.as_str()).cloned().unwrap_or_default(),
+            })
+        })
+        .collect();
+
+    Ok(Json(json!({
+        "id": job.id,
+        "state": job.state,
+        "pages_crawled": job.pages.len(),
+        "broken": broken.len(),
+        "by_status": by_status,
+        "links": broken,
+    })))
+}
+
+/// `DELETE /crawls/{id}`: 停止运行中的任务, 已有结果保留到过期
+pub async fn cancel_crawl(State(state): State<Arc<AppState>>, Path(id): Path<String>) -> Result<Json<Value>, AppError> {
+    let mut crawls = state.crawls.write().await;
+    let job = crawls
+        .get_mut(&id)
+        .ok_or_else(|| AppError::NotFound(format!("Crawl '{}' not found", id)))?;
+    if job.state == CrawlState::Running {
+        job.state = CrawlState::Cancelled;
+        job.finished_at = Some(Utc::now());
+        info!("Cancelled crawl {}", id);
+    }
+    Ok(Json(job.summary()))
+}
+
+async fn find(state: &Arc<AppState>, id: &str) -> Result<CrawlJob, AppError> {
+    state
+        .crawls
+        .read()
+        .await
+        .get(id)
+        .cloned()
+        .ok_or_else(|| AppError::NotFound(format!("Crawl '{}' not found", id)))
+}
+
+/// 更新任务, 任务已取消或被删除时返回 false
+async fn update(state: &Arc<AppState>, id: &str, apply: impl FnOnce(&mut CrawlJob)) -> bool {
+    let mut crawls = state.crawls.write().await;
+    match crawls.get_mut(id) {
+        Some(job) if job.state == CrawlState::Running => {
+            apply(job);
+            true
+        }
+        _ => false,
+    }
+}
+
+/// 广度优先抓取, 每个主机的请求按 `delay_ms` 和 Crawl-delay 间隔执行
+async fn run_crawl(state: Arc<AppState>, job: CrawlJob, scope: Scope) {
+    let config = state.config().crawl.clone();
+    let options = &job.options;
+    let mut queue = VecDeque::from([(job.url.clone(), 0)]);
+    let mut seen = HashSet::from([job.url.clone()]);
+    let mut robots: HashMap<String, Robots> = HashMap::new();
+    let mut last_request: HashMap<String, Instant> = HashMap::new();
+    let mut crawled = 0;
+
+    while let Some((page_url, depth)) = queue.pop_front() {
+        if crawled >= options.max_pages {
+            queue.push_front((page_url, depth));
+            update(&state, &job.id, |job| job.limit_reached = true).await;
+            break;
+        }
+        let Ok(parsed) = url::Url::parse(&page_url) else {
+            continue;
+        };
+        let host = parsed.host_str().unwrap_or_default().to_string();
+        let origin = parsed.origin().ascii_serialization();
+
+        if !robots.contains_key(&origin) {
+            wait_for_host(&mut last_request, &host, config.delay_ms, None).await;
+            let rules = fetch_robots(&state, &origin, options.profile.clone()).await;
+            robots.insert(origin.clone(), rules);
+        }
+        let rules = &robots[&origin];
+        if !rules.allows(&parsed) {
+            let skipped = SkippedUrl {
+                url: page_url,
+                reason: "disallowed by robots.txt".to_string(),
+            };
+            if !update(&state, &job.id, |job| job.skipped.push(skipped)).await {
+                return;
+            }
+            continue;
+        }
+
+        wait_for_host(&mut last_request, &host, config.delay_ms, rules.crawl_delay).await;
+        let request = crawl_request(&state, &page_url, options.profile.clone());
+        let result = fetch_raw(&state, &request).await;
+        crawled += 1;
+
+        let page = match result {
+            Ok(Some(response)) => Some(crawl_page(&page_url, depth, response)),
+            Ok(None) => None,
+            Err(e) => Some(CrawlPage {
+                url: page_url.clone(),
+                final_url: page_url.clone(),
+                depth,
+                status_code: None,
+                title: None,
+                content_type: None,
+                size_bytes: 0,
+                links: Vec::new(),
+                error: Some(e.to_string()),
+                fetched_at: Utc::now(),
+            }),
+        };
+
+        if let Some(page) = &page
+            && depth < options.max_depth
+        {
+            for link in &page.links {
+                if let Ok(link_url) = url::Url::parse(link)
+                    && scope.contains(&link_url)
+                    && seen.insert(link.clone())
+                {
+                    queue.push_back((link.clone(), depth + 1));
+                }
+            }
+        }
+
+        let queued = queue.len();
+        let running = update(&state, &job.id, |job| {
+            match page {
+                Some(page) => job.pages.push(page),
+                None => job.skipped.push(SkippedUrl {
+                    url: page_url,
+                    reason: "larger than max_response_size_bytes".to_string(),
+                }),
+            }
+            job.queued = queued;
+        })
+        .await;
+        if !running {
+            info!("Crawl {} stopped after {} pages", job.id, crawled);
+            return;
+        }
+    }
+
+    let remaining = queue.len();
+    update(&state, &job.id, |job| {
+        job.state = CrawlState::Completed;
+        job.finished_at = Some(Utc::now());
+        job.queued = remaining;
+    })
+    .await;
+    info!("Crawl {} completed with {} pages", job.id, crawled);
+}
+
+fn crawl_request(state: &Arc<AppState>, url: &str, profile: Option<String>) -> CurlRequest {
+    CurlRequest {
+        url: url.to_string(),
+        method: "GET".to_string(),
+        headers: vec![("User-Agent".to_string(), state.config().crawl.user_agent.clone())],
+        profile,
+        ..Default::default()
+    }
+}
+
+fn crawl_page(url: &str, depth: usize, response: RawResponse) -> CrawlPage {
+    let content_type = response.headers.get("content-type").cloned();
+    let is_html = content_type
+        .as_deref()
+        .map(|value| value.to_ascii_lowercase())
+        .is_some_and(|value| value.contains("text/html") || value.contains("xhtml"));
+
+    let (title, links) = if is_html && matches!(response.status_code, Some(200..=299)) {
+        let page = String::from_utf8_lossy(&response.body);
+        let title = TITLE.captures(&page).map(|captures| {
+            decode_entities(&captures[1])
+                .split_whitespace()
+                .collect::<Vec<_>>()
+                .join(" ")
+        });
+        let mut collector = PageLinks::default();
+        html::rewrite_html(&page, &response.final_url, &mut collector);
+        (title, collector.links)
+    } else {
+        (None, Vec::new())
+    };
+
+    CrawlPage {
+        url: url.to_string(),
+        final_url: response.final_url,
+        depth,
+        status_code: response.status_code,
+        title,
+        content_type,
+        size_bytes: response.body.len() as u64,
+        links,
+        error: None,
+        fetched_at: Utc::now(),
+    }
+}
+
+/// 4xx 视为没有限制, 5xx 或请求失败时按 RFC 9309 视为全部禁止
+async fn fetch_robots(state: &Arc<AppState>, origin: &str, profile: Option<String>) -> Robots {
+    let request = crawl_request(state, &format!("{}/robots.txt", origin), profile);
+    match fetch_raw(state, &request).await {
+        Ok(Some(response)) if matches!(response.status_code, Some(200..=299)) => {
+            Robots::parse(&String::from_utf8_lossy(&response.body), &state.config().crawl.user_agent)
+        }
+        Ok(Some(response)) if matches!(response.status_code, Some(400..=499)) => Robots::default(),
+        _ => {
+            info!("robots.txt of {} is unavailable, skipping the host", origin);
+            Robots {
+                disallow_all: true,
+                ..Default::default()
+            }
+        }
+    }
+}
+
+async fn wait_for_host(last_request: &mut HashMap<String, Instant>, host: &str, delay_ms: u64, crawl_delay: Option<f64>) {
+    let delay = std::time::Duration::from_millis(delay_ms).max(std::time::Duration::from_secs_f64(
+        crawl_delay.unwrap_or_default().clamp(0.0, MAX_CRAWL_DELAY_SECONDS),
+    ));
+    if let Some(last) = last_request.get(host) {
+        let elapsed = last.elapsed();
+        if elapsed < delay {
+            tokio::time::sleep(delay - elapsed).await;
+        }
+    }
+    last_request.insert(host.to_string(), Instant::now());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn url(path: &str) -> url::Url {
+        url::Url::parse(&format!("https://example.com{}", path)).unwrap()
+    }
+
+    #[test]
+    fn robots_patterns_support_wildcards_and_end_anchors() {
+        let pattern = robots_pattern("/private*.html$").unwrap();
+        assert!(pattern.is_match("/private/page.html"));
+        assert!(!pattern.is_match("/private/page.html?x=1"));
+        assert!(!pattern.is_match("/public/private.html"));
+        assert!(robots_pattern("/a.b").unwrap().is_match("/a.b/c"));
+        assert!(!robots_pattern("/a.b").unwrap().is_match("/axb"));
+    }
+
+    #[test]
+    fn robots_prefers_the_matching_agent_group() {
+        let robots = Robots::parse(
+            "User-agent: *\nDisallow: /\n\nUser-agent: curl2url\nDisallow: /admin\nCrawl-delay: 2\n",
+            "curl2url/1.0",
+        );
+        assert!(robots.allows(&url("/docs")));
+        assert!(!robots.allows(&url("/admin/users")));
+        assert_eq!(robots.crawl_delay, Some(2.0));
+
+        let robots = Robots::parse("User-agent: *\nDisallow: /\n", "curl2url/1.0");
+        assert!(!robots.allows(&url("/docs")));
+    }
+
+    #[test]
+    fn robots_longest_match_wins_and_allow_breaks_ties() {
+        let robots = Robots::parse(
+            "User-agent: *\nDisallow: /shop\nAllow: /shop/public\nDisallow: /page\nAllow: /page\nDisallow:\n",
+            "curl2url",
+        );
+        assert!(!robots.allows(&url("/shop/cart")));
+        assert!(robots.allows(&url("/shop/public/item")));
+        assert!(robots.allows(&url("/page")));
+        assert!(robots.allows(&url("/other")));
+    }
+
+    #[test]
+    fn robots_ignores_invalid_crawl_delays() {
+        for delay in ["NaN", "inf", "-1", "soon"] {
+            let robots = Robots::parse(&format!("User-agent: *\nCrawl-delay: {}\n", delay), "curl2url");
+            assert_eq!(robots.crawl_delay, None, "Crawl-delay: {}", delay);
+        }
+    }
+}
@@ -0,0 +1,415 @@
+use axum::{
+    extract::{Query, State},
+    Json,
+};
+use chrono::{NaiveDateTime, Utc};
+use hickory_resolver::{
+    proto::rr::{RData, RecordType},
+    TokioResolver,
+};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::{net::IpAddr, sync::Arc, time::Instant};
+use tracing::info;
+
+use crate::{
+    command::CurlCommand,
+    errors::AppError,
+    models::AppState,
+    ssrf::{check_addresses, check_target},
+    utils::{alpn_from_verbose, split_curl_output, META_MARKER},
+};
+
+#[derive(Debug, Deserialize)]
+pub struct DnsQuery {
+    host: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TlsQuery {
+    host: Option<String>,
+    port: Option<u16>,
+}
+
+/// 校验 `host` 参数, 只接受域名或 IP, 不含协议和端口
+fn host_param(host: Option<String>) -> Result<url::Host, AppError> {
+    let host = host
+        .map(|host| host.trim().trim_end_matches('.').to_ascii_lowercase())
+        .filter(|host| !host.is_empty())
+        .ok_or_else(|| AppError::BadRequest("Missing 'host' parameter".to_string()))?;
+    let bare = host.trim_start_matches('[').trim_end_matches(']');
+    if let Ok(addr) = bare.parse::<IpAddr>() {
+        return Ok(match addr {
+            IpAddr::V4(addr) => url::Host::Ipv4(addr),
+            IpAddr::V6(addr) => url::Host::Ipv6(addr),
+        });
+    }
+    match url::Host::parse(&host) {
+        Ok(url::Host::Domain(domain)) if !domain.contains([':', '/', '@']) => Ok(url::Host::Domain(domain)),
+        _ => Err(AppError::BadRequest(format!("Invalid host '{}'", host))),
+    }
+}
+
+/// `GET /diag/dns?host=`: 使用系统解析配置查询 A/AAAA 记录和 CNAME 链, 不使用缓存
+pub async fn dns_lookup(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<DnsQuery>,
+) -> Result<Json<Value>, AppError> {
+    let url::Host::Domain(host) = host_param(query.host)? else {
+        return Err(AppError::BadRequest("'host' is already an IP address".to_string()));
+    };
+
+    let resolver = TokioResolver::builder_tokio()
+        .map_err(|e| AppError::InternalServerError(format!("Failed to read the system resolver config: {}", e)))?
+        .build();
+    let mut name_servers: Vec<String> = Vec::new();
+    for server in resolver.config().name_servers() {
+        let address = server.socket_addr.to_string();
+        if !name_servers.contains(&address) {
+            name_servers.push(address);
+        }
+    }
+    let search: Vec<String> = resolver.config().search().iter().map(|name| name.to_string()).collect();
+
+    let started = Instant::now();
+    let (a, aaaa) = tokio::join!(
+        lookup(&resolver, &host, RecordType::A),
+        lookup(&resolver, &host, RecordType::AAAA)
+    );
+    let elapsed_ms = started.elapsed().as_secs_f64() * 1000.0;
+
+    let addresses: Vec<IpAddr> = a.addresses.iter().chain(&aaaa.addresses).map(|(addr, _)| *addr).collect();
+    check_addresses(&state.config().security, &host, &addresses)?;
+    info!("DNS lookup of {} returned {} addresses in {:.1} ms", host, addresses.len(), elapsed_ms);
+
+    // 两次查询得到的 CNAME 链相同, 取较长的一个
+    let cname = if a.cname.len() >= aaaa.cname.len() { &a.cname } else { &aaaa.cname };
+    let records = |result: &LookupResult| -> Vec<Value> {
+        result
+            .addresses
+            .iter()
+            .map(|(addr, ttl)| json!({ "address": addr, "ttl": ttl }))
+            .collect()
+    };
+    Ok(Json(json!({
+        "host": host,
+        "cname": cname,
+        "a": records(&a),
+        "aaaa": records(&aaaa),
+        "queries": [a.summary("A"), aaaa.summary("AAAA")],
+        "resolver": {
+            "config": "system",
+            "name_servers": name_servers,
+            "search": search,
+        },
+        "elapsed_ms": elapsed_ms,
+    })))
+}
+
+struct LookupResult {
+    addresses: Vec<(IpAddr, u32)>,
+    cname: Vec<String>,
+    elapsed_ms: f64,
+    error: Option<String>,
+}
+
+impl LookupResult {
+    fn summary(&self, record_type: &str) -> Value {
+        json!({
+            "type": record_type,
+            "answers": self.addresses.len(),
+            "elapsed_ms": self.elapsed_ms,
+            "error": self.error,
+        })
+    }
+}
+
+/// 查询失败 (NXDOMAIN、没有记录、超时) 不作为请求错误, 记录在结果中
+async fn lookup(resolver: &TokioResolver, host: &str, record_type: RecordType) -> LookupResult {
+    let started = Instant::now();
+    let mut result = LookupResult {
+        addresses: Vec::new(),
+        cname: Vec::new(),
+        elapsed_ms: 0.0,
+        error: None,
+    };
+    match resolver.lookup(host, record_type).await {
+        Ok(lookup) => {
+            for record in lookup.records() {
+                match record.data() {
+                    RData::A(addr) => result.addresses.push((IpAddr::V4(addr.0), record.ttl())),
+                    RData::AAAA(addr) => result.addresses.push((IpAddr::V6(addr.0), record.ttl())),
+                    RData::CNAME(target) => result.cname.push(target.0.to_string()),
+                    _ => {}
+                }
+            }
+        }
+        Err(e) if e.is_no_records_found() => {}
+        Err(e) => result.error = Some(e.to_string()),
+    }
+    result.elapsed_ms = started.elapsed().as_secs_f64() * 1000.0;
+    result
+}
+
+/// `GET /diag/tls?host=&port=`: 握手并返回协议、加密套件、证书链和主机名匹配结果
+pub async fn tls_info(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<TlsQuery>,
+) -> Result<Json<Value>, AppError> {
+    let host = host_param(query.host)?;
+    let port = query.port.unwrap_or(443);
+    let config = state.config();
+    let url = format!("https://{}:{}/", host, port);
+    let pinned = check_target(&config.security, &url, &[], &[]).await?;
+
+    // 使用 `-k` 完成握手后再单独报告校验结果, 证书无效时也能看到证书内容
+    let mut command = CurlCommand::new();
+    command
+        .arg("-s")
+        .arg("-v")
+        .arg("-k")
+        .arg("-I")
+        .arg("-o")
+        .arg("/dev/null")
+        .arg("--max-time")
+        .arg(config.curl.timeout_seconds.to_string());
+    if let Some(ca_cert) = &config.curl.ca_cert {
+        command.arg("--cacert").arg(ca_cert);
+    }
+    if let Some(pinned) = &pinned {
+        command.hidden_arg("--resolve").hidden_arg(pinned.resolve_arg());
+    }
+    command
+        .hidden_arg("-w")
+        .hidden_arg(format!("%{{certs}}{}%{{json}}", META_MARKER))
+        .arg(&url);
+    info!("Inspecting TLS of {}:{}", host, port);
+
+    let output = command
+        .output()
+        .await
+        .map_err(|e| AppError::InternalServerError(format!("Failed to execute curl: {}", e)))?;
+    let verbose = String::from_utf8_lossy(&output.stderr);
+    let (certs, meta) = split_curl_output(&output.stdout);
+    let meta = meta.unwrap_or_default();
+    let chain = parse_certs(&String::from_utf8_lossy(certs));
+
+    // 握手之后的 HTTP 请求失败 (例如超时) 不影响结果
+    if chain.is_empty() {
+        let reason = verbose
+            .lines()
+            .rev()
+            .filter_map(|line| line.strip_prefix("* "))
+            .find(|line| {
+                let lower = line.to_ascii_lowercase();
+                lower.contains("error") || lower.contains("fail") || lower.contains("timed out")
+            })
+            .unwrap_or("no certificate received");
+        return Err(AppError::BadGateway(format!(
+            "TLS handshake with {}:{} failed (curl exit code {}): {}",
+            host,
+            port,
+            output.status.code().unwrap_or(-1),
+            reason
+        )));
+    }
+
+    let (protocol, cipher) = verbose
+        .lines()
+        .find_map(|line| line.strip_prefix("* SSL connection using "))
+        .and_then(|rest| rest.split_once(" / "))
+        .map(|(protocol, cipher)| (Some(protocol.trim().to_string()), Some(cipher.trim().to_string())))
+        .unwrap_or_default();
+    // 校验失败时为 "self-signed certificate (18), continuing anyway."
+    let verify_error = verbose.lines().find_map(|line| {
+        line.split_once("SSL certificate verify result: ")
+            .map(|(_, result)| result.trim_end_matches(", continuing anyway.").to_string())
+    });
+
+    let hostname_match = hostname_matches(&host, &chain[0]);
+    let now = Utc::now().naive_utc();
+    let certificates: Vec<Value> = chain
+        .iter()
+        .map(|cert| {
+            json!({
+                "subject": cert.subject,
+                "issuer": cert.issuer,
+                "serial_number": cert.serial_number,
+                "subject_alt_names": cert.subject_alt_names,
+                "not_before": cert.not_before.map(|date| date.and_utc()),
+                "not_after": cert.not_after.map(|date| date.and_utc()),
+                "days_until_expiry": cert.not_after.map(|date| (date - now).num_days()),
+                "signature_algorithm": cert.signature_algorithm,
+                "public_key_algorithm": cert.public_key_algorithm,
+                "pem": cert.pem,
+            })
+        })
+        .collect();
+
+    Ok(Json(json!({
+        "host": host.to_string(),
+        "port": port,
+        "remote_ip": meta.remote_ip,
+        "protocol": protocol,
+        "cipher": cipher,
+        "alpn": alpn_from_verbose(&verbose),
+        "verified": verify_error.is_none(),
+        "verify_error": verify_error,
+        "hostname_match": hostname_match,
+        "days_until_expiry": chain[0].not_after.map(|date| (date - now).num_days()),
+        "certificates": certificates,
+        "timings": meta.timings(),
+    })))
+}
+
+/// curl `%{certs}` 输出中的一张证书
+#[derive(Debug, Default)]
+struct CertInfo {
+    subject: Option<String>,
+    issuer: Option<String>,
+    serial_number: Option<String>,
+    subject_alt_names: Vec<String>,
+    not_before: Option<NaiveDateTime>,
+    not_after: Option<NaiveDateTime>,
+    signature_algorithm: Option<String>,
+    public_key_algorithm: Option<String>,
+    pem: Option<String>,
+}
+
+/// 每张证书是若干 `Name:value` 行加上 PEM, 第一张为服务端证书
+fn parse_certs(output: &str) -> Vec<CertInfo> {
+    const PEM_END: &str = "-----END CERTIFICATE-----";
+    output
+        .split_inclusive(PEM_END)
+        .filter(|block| block.contains(PEM_END))
+        .map(|block| {
+            let mut cert = CertInfo::default();
+            if let Some(start) = block.find("-----BEGIN CERTIFICATE-----") {
+                cert.pem = Some(block[start..].trim().to_string());
+            }
+            for line in block.lines() {
+                let Some((name, value)) = line.split_once(':') else {
+                    continue;
+                };
+                let value = value.trim().to_string();
+                match name.trim() {
+                    "Subject" => cert.subject = Some(value),
+                    "Issuer" => cert.issuer = Some(value),
+                    "Serial Number" => cert.serial_number = Some(value),
+                    "Signature Algorithm" => cert.signature_algorithm = Some(value),
+                    "Public Key Algorithm" => cert.public_key_algorithm = Some(value),
+                    "Start date" => cert.not_before = parse_cert_date(&value),
+                    "Expire date" => cert.not_after = parse_cert_date(&value),
+                    "X509v3 Subject Alternative Name" => {
+                        cert.subject_alt_names = value.split(',').map(|name| name.trim().to_string()).collect();
+                    }
+                    _ => {}
+                }
+            }
+            cert
+        })
+        .collect()
+}
+
+/// OpenSSL 格式的时间, 例如 `Jan  2 03:04:05 2026 GMT`
+fn parse_cert_date(value: &str) -> Option<NaiveDateTime> {
+    let normalized = value.split_whitespace().collect::<Vec<_>>().join(" ");
+    NaiveDateTime::parse_from_str(&normalized, "%b %d %H:%M:%S %Y GMT").ok()
+}
+
+/// 按 RFC 6125 比较: 有 SAN 时只看 SAN, 通配符只匹配最左边的一级; 没有 SAN 时使用 CN
+fn hostname_matches(host: &url::Host, cert: &CertInfo) -> bool {
+    let host = match host {
+        url::Host::Domain(domain) => domain.clone(),
+        url::Host::Ipv4(addr) => addr.to_string(),
+        url::Host::Ipv6(addr) => addr.to_string(),
+    };
+    let matches_name = |pattern: &str| {
+        let pattern = pattern.trim_end_matches('.').to_ascii_lowercase();
+        match pattern.strip_prefix("*.") {
+            Some(suffix) => host
+                .split_once('.')
+                .is_some_and(|(label, rest)| !label.is_empty() && rest == suffix),
+            None => pattern == host,
+        }
+    };
+
+    if !cert.subject_alt_names.is_empty() {
+        return cert.subject_alt_names.iter().any(|name| {
+            if let Some(dns) = name.strip_prefix("DNS:") {
+                matches_name(dns)
+            } else if let Some(ip) = name.strip_prefix("IP Address:") {
+                ip.parse::<IpAddr>().ok() == host.parse::<IpAddr>().ok() && host.parse::<IpAddr>().is_ok()
+            } else {
+                false
+            }
+        });
+    }
+    cert.subject
+        .as_deref()
+        .and_then(|subject| subject.split(',').find_map(|part| part.trim().strip_prefix("CN = ")))
+        .is_some_and(matches_name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CERTS: &str = "Subject:CN = www.example.com, O = Example\n\
+                         Issuer:C = US, O = Example CA\n\
+                         Serial Number:0a:1b\n\
+                         Start date:Jan  2 03:04:05 2026 GMT\n\
+                         Expire date:Feb 10 00:00:00 2027 GMT\n\
+                         X509v3 Subject Alternative Name:DNS:example.com, DNS:*.example.com, IP Address:10.0.0.1\n\
+                         -----BEGIN CERTIFICATE-----\nMIIB\n-----END CERTIFICATE-----\n\
+                         Subject:C = US, O = Example CA\n\
+                         -----BEGIN CERTIFICATE-----\nMIIC\n-----END CERTIFICATE-----\n";
+
+    fn cert(subject: Option<&str>, sans: &[&str]) -> CertInfo {
+        CertInfo {
+            subject: subject.map(str::to_string),
+            subject_alt_names: sans.iter().map(|name| name.to_string()).collect(),
+            ..Default::default()
+        }
+    }
+
+    fn host(value: &str) -> url::Host {
+        url::Host::parse(value).unwrap()
+    }
+
+    #[test]
+    fn parse_certs_splits_the_chain() {
+        let certs = parse_certs(CERTS);
+        assert_eq!(certs.len(), 2);
+        let leaf = &certs[0];
+        assert_eq!(leaf.subject.as_deref(), Some("CN = www.example.com, O = Example"));
+        assert_eq!(leaf.serial_number.as_deref(), Some("0a:1b"));
+        assert_eq!(leaf.subject_alt_names, ["DNS:example.com", "DNS:*.example.com", "IP Address:10.0.0.1"]);
+        assert_eq!(leaf.not_before.map(|date| date.to_string()).as_deref(), Some("2026-01-02 03:04:05"));
+        assert_eq!(leaf.not_after.map(|date| date.to_string()).as_deref(), Some("2027-02-10 00:00:00"));
+        assert!(leaf.pem.as_deref().is_some_and(|pem| pem.starts_with("-----BEGIN") && pem.contains("MIIB")));
+        assert_eq!(certs[1].issuer, None);
+        assert!(certs[1].pem.as_deref().is_some_and(|pem| pem.contains("MIIC")));
+        assert!(parse_certs("").is_empty());
+    }
+
+    #[test]
+    fn hostnames_match_sans_with_single_label_wildcards() {
+        let leaf = cert(Some("CN = other.example"), &["DNS:example.com", "DNS:*.Example.com.", "IP Address:10.0.0.1"]);
+        assert!(hostname_matches(&host("example.com"), &leaf));
+        assert!(hostname_matches(&host("api.example.com"), &leaf));
+        assert!(!hostname_matches(&host("a.b.example.com"), &leaf));
+        assert!(hostname_matches(&host("10.0.0.1"), &leaf));
+        assert!(!hostname_matches(&host("10.0.0.2"), &leaf));
+        // 有 SAN 时忽略 CN
+        assert!(!hostname_matches(&host("other.example"), &leaf));
+    }
+
+    #[test]
+    fn hostnames_fall_back_to_the_common_name() {
+        let leaf = cert(Some("C = US, CN = www.example.com"), &[]);
+        assert!(hostname_matches(&host("www.example.com"), &leaf));
+        assert!(!hostname_matches(&host("example.com"), &leaf));
+        assert!(!hostname_matches(&host("example.com"), &cert(None, &[])));
+    }
+}
@@ -0,0 +1,295 @@
+use axum::extract::{Json, State};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use similar::TextDiff;
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    sync::Arc,
+};
+
+use crate::{
+    errors::AppError,
+    history,
+    models::{AppState, CurlRequest, CurlResponse},
+    services::execute_curl_command,
+};
+
+/// 对比的一侧: 现场执行的请求, 或历史记录中已保存的响应
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum DiffSide {
+    History { history_id: String },
+    Request(Box<CurlRequest>),
+}
+
+/// `POST /curl/diff` 的请求体
+#[derive(Debug, Deserialize)]
+pub struct DiffRequest {
+    pub left: DiffSide,
+    pub right: DiffSide,
+    /// 不参与对比的响应头, 不区分大小写
+    #[serde(default)]
+    pub ignore_headers: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DiffResult {
+    pub identical: bool,
+    pub left: DiffSummary,
+    pub right: DiffSummary,
+    /// 状态码相同时为空
+    pub status: Option<Change<Option<u16>>>,
+    pub headers: HeaderDiff,
+    pub body: BodyDiff,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DiffSummary {
+    pub history_id: Option<String>,
+    pub curl_command: String,
+    pub status_code: Option<u16>,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Change<T> {
+    pub left: T,
+    pub right: T,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct HeaderDiff {
+    pub added: BTreeMap<String, String>,
+    pub removed: BTreeMap<String, String>,
+    pub changed: BTreeMap<String, Change<String>>,
+}
+
+/// 两侧都是 JSON 时按结构对比, 否则按行对比
+#[derive(Debug, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum BodyDiff {
+    Json { changes: Vec<JsonChange> },
+    Text { diff: String },
+}
+
+#[derive(Debug, Serialize)]
+pub struct JsonChange {
+    /// JSONPath 形式的位置, 例如 `$.items[0].id`
+    pub path: String,
+    /// added, removed 或 changed
+    pub op: &'static str,
+    pub left: Option<Value>,
+    pub right: Option<Value>,
+}
+
+impl HeaderDiff {
+    fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+impl BodyDiff {
+    fn is_empty(&self) -> bool {
+        match self {
+            BodyDiff::Json { changes } => changes.is_empty(),
+            BodyDiff::Text { diff } => diff.is_empty(),
+        }
+    }
+}
+
+impl DiffSummary {
+    fn new(response: &CurlResponse) -> Self {
+        Self {
+            history_id: response.history_id.clone(),
+            curl_command: response.curl_command.clone(),
+            status_code: response.status_code,
+            error: response.error.clone(),
+        }
+    }
+}
+
+/// `POST /curl/diff`: 执行 (或从历史记录读取) 两个请求并对比状态码、响应头和响应体
+pub async fn diff_responses(
+    State(state): State<Arc<AppState>>,
+    Json(spec): Json<DiffRequest>,
+) -> Result<Json<DiffResult>, AppError> {
+    let (left, right) = tokio::join!(resolve_side(&state, &spec.left), resolve_side(&state, &spec.right));
+    let (left, right) = (left?, right?);
+
+    let status = (left.status_code != right.status_code).then_some(Change {
+        left: left.status_code,
+        right: right.status_code,
+    });
+    let headers = diff_headers(&left, &right, &spec.ignore_headers);
+    let body = diff_body(
+        left.response_body.as_deref().unwrap_or_default(),
+        right.response_body.as_deref().unwrap_or_default(),
+    );
+
+    Ok(Json(DiffResult {
+        identical: status.is_none() && headers.is_empty() && body.is_empty(),
+        left: DiffSummary::new(&left),
+        right: DiffSummary::new(&right),
+        status,
+        headers,
+        body,
+    }))
+}
+
+async fn resolve_side(state: &Arc<AppState>, side: &DiffSide) -> Result<CurlResponse, AppError> {
+    match side {
+        DiffSide::History { history_id } => Ok(history::find(state, history_id).await?.response),
+        DiffSide::Request(request) => {
+            let response = execute_curl_command(state, request).await?;
+            Ok(history::record(state, request, response).await)
+        }
+    }
+}
+
+fn diff_headers(left: &CurlResponse, right: &CurlResponse, ignore: &[String]) -> HeaderDiff {
+    // status 是解析时保存的状态行, 已在状态码中对比
+    let mut ignored: HashSet<String> = ignore.iter().map(|name| name.to_ascii_lowercase()).collect();
+    ignored.insert("status".to_string());
+
+    let empty = HashMap::new();
+    let left = left.response_headers.as_ref().unwrap_or(&empty);
+    let right = right.response_headers.as_ref().unwrap_or(&empty);
+    let mut diff = HeaderDiff::default();
+
+    for (name, left_value) in left.iter().filter(|(name, _)| !ignored.contains(*name)) {
+        match right.get(name) {
+            Some(right_value) if right_value != left_value => {
+                diff.changed.insert(
+                    name.clone(),
+                    Change {
+                        left: left_value.clone(),
+                        right: right_value.clone(),
+                    },
+                );
+            }
+            Some(_) => {}
+            None => {
+                diff.removed.insert(name.clone(), left_value.clone());
+            }
+        }
+    }
+    for (name, right_value) in right.iter().filter(|(name, _)| !ignored.contains(*name)) {
+        if !left.contains_key(name) {
+            diff.added.insert(name.clone(), right_value.clone());
+        }
+    }
+
+    diff
+}
+
+fn diff_body(left: &str, right: &str) -> BodyDiff {
+    if let (Ok(left), Ok(right)) = (
+        serde_json::from_str::<Value>(left),
+        serde_json::from_str::<Value>(right),
+    ) {
+        let mut changes = Vec::new();
+        diff_json("$", &left, &right, &mut changes);
+        return BodyDiff::Json { changes };
+    }
+
+    let diff = if left == right {
+        String::new()
+    } else {
+        TextDiff::from_lines(left, right)
+            .unified_diff()
+            .context_radius(3)
+            .header("left", "right")
+            .to_string()
+    };
+    BodyDiff::Text { diff }
+}
+
+fn diff_json(path: &str, left: &Value, right: &Value, changes: &mut Vec<JsonChange>) {
+    let change = |path: String, op, left: Option<&Value>, right: Option<&Value>| JsonChange {
+        path,
+        op,
+        left: left.cloned(),
+        right: right.cloned(),
+    };
+
+    match (left, right) {
+        (Value::Object(left), Value::Object(right)) => {
+            for (key, left_value) in left {
+                let child = child_path(path, key);
+                match right.get(key) {
+                    Some(right_value) => diff_json(&child, left_value, right_value, changes),
+                    None => changes.push(change(child, "removed", Some(left_value), None)),
+                }
+            }
+            for (key, right_value) in right.iter().filter(|(key, _)| !left.contains_key(*key)) {
+                changes.push(change(child_path(path, key), "added", None, Some(right_value)));
+            }
+        }
+        (Value::Array(left), Value::Array(right)) => {
+            for index in 0..left.len().max(right.len()) {
+                let child = format!("{}[{}]", path, index);
+                match (left.get(index), right.get(index)) {
+                    (Some(left_value), Some(right_value)) => diff_json(&child, left_value, right_value, changes),
+                    (Some(left_value), None) => changes.push(change(child, "removed", Some(left_value), None)),
+                    (None, Some(right_value)) => changes.push(change(child, "added", None, Some(right_value))),
+                    (None, None) => {}
+                }
+            }
+        }
+        _ if left != right => changes.push(change(path.to_string(), "changed", Some(left), Some(right))),
+        _ => {}
+    }
+}
+
+/// 普通字段名使用 `.key`, 其他使用 `['key']` (`\` 和 `'` 加反斜杠转义), 与断言中的 JSONPath 写法一致
+fn child_path(path: &str, key: &str) -> String {
+    if !key.is_empty() && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-') {
+        format!("{}.{}", path, key)
+    } else {
+        format!("{}['{}']", path, key.replace('\\', "\\\\").replace('\'', "\\'"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn changes(left: Value, right: Value) -> Vec<(String, &'static str)> {
+        let mut changes = Vec::new();
+        diff_json("$", &left, &right, &mut changes);
+        changes.into_iter().map(|change| (change.path, change.op)).collect()
+    }
+
+    #[test]
+    fn diff_json_reports_added_removed_and_changed_paths() {
+        let diff = changes(
+            json!({ "id": 1, "name": "a", "tags": ["x", "y"], "meta": { "v": 1 } }),
+            json!({ "id": 2, "tags": ["x"], "meta": { "v": 1, "w": true }, "extra": null }),
+        );
+        assert_eq!(diff.len(), 5);
+        for expected in [
+            ("$.id", "changed"),
+            ("$.name", "removed"),
+            ("$.tags[1]", "removed"),
+            ("$.meta.w", "added"),
+            ("$.extra", "added"),
+        ] {
+            assert!(diff.contains(&(expected.0.to_string(), expected.1)), "missing {:?}", expected);
+        }
+    }
+
+    #[test]
+    fn diff_json_quotes_unusual_keys_and_compares_types() {
+        assert_eq!(
+            changes(json!({ "a b": 1 }), json!({ "a b": 2 })),
+            [("$['a b']".to_string(), "changed")]
+        );
+        assert_eq!(
+            changes(json!({ "it's": 1, "a\\b": 1 }), json!({ "it's": 2, "a\\b": 2 })),
+            [("$['a\\\\b']".to_string(), "changed"), ("$['it\\'s']".to_string(), "changed")]
+        );
+        assert_eq!(changes(json!([1]), json!({ "0": 1 })), [("$".to_string(), "changed")]);
+        assert!(changes(json!({ "a": [1, { "b": null }] }), json!({ "a": [1, { "b": null }] })).is_empty());
+    }
+}
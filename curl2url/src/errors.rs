@@ -6,14 +6,46 @@ use axum::{
 #[derive(Debug)]
 pub enum AppError {
     BadRequest(String),
+    Forbidden(String),
+    NotFound(String),
     InternalServerError(String),
+    /// 上游请求失败
+    BadGateway(String),
+    /// 获取的内容与期望的校验和不一致
+    ChecksumMismatch {
+        algorithm: String,
+        expected: String,
+        actual: String,
+    },
+}
+
+impl std::fmt::Display for AppError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AppError::BadRequest(message)
+            | AppError::Forbidden(message)
+            | AppError::NotFound(message)
+            | AppError::InternalServerError(message)
+            | AppError::BadGateway(message) => f.write_str(message),
+            AppError::ChecksumMismatch {
+                algorithm,
+                expected,
+                actual,
+            } => write!(f, "{} checksum mismatch: expected {}, got {}", algorithm, expected, actual),
+        }
+    }
 }
 
 impl IntoResponse for AppError {
     fn into_response(self) -> Response {
         let (status, error_message) = match self {
             AppError::BadRequest(message) => (StatusCode::BAD_REQUEST, message),
+            AppError::Forbidden(message) => (StatusCode::FORBIDDEN, message),
+            AppError::NotFound(message) => (StatusCode::NOT_FOUND, message),
             AppError::InternalServerError(message) => (StatusCode::INTERNAL_SERVER_ERROR, message),
+            AppError::BadGateway(message) => (StatusCode::BAD_GATEWAY, message),
+            // 上游返回的内容不可信, 按网关错误处理
+            AppError::ChecksumMismatch { .. } => (StatusCode::BAD_GATEWAY, self.to_string()),
         };
 
         let body = serde_json::json!({
@@ -0,0 +1,549 @@
+use axum::{
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+};
+use regex::Regex;
+use std::{collections::HashMap, fmt::Write, sync::LazyLock};
+
+use crate::{
+    errors::AppError,
+    models::{CurlRequest, CurlResponse},
+};
+
+/// `&amp;`、`&#39;`、`&#x27;` 形式的 HTML 实体
+static ENTITY: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"&(#[0-9]+|#[xX][0-9a-fA-F]+|[A-Za-z]+);").expect("valid entity regex"));
+
+/// `/curl` 等接口的输出格式, 由 `?format=` 指定
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// JSON 信封 (默认)
+    #[default]
+    Json,
+    /// 只返回按内容类型格式化后的响应内容
+    Pretty,
+    /// 类似 `curl -v` 的纯文本报告
+    Text,
+    /// 可在浏览器中查看的报告页面
+    Html,
+}
+
+/// 响应内容的类型, 决定格式化方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BodyKind {
+    Json,
+    Xml,
+    Html,
+    Text,
+}
+
+impl OutputFormat {
+    pub fn from_param(value: Option<&str>) -> Result<Self, AppError> {
+        match value.map(|value| value.trim().to_ascii_lowercase()).as_deref() {
+            None | Some("") | Some("json") => Ok(OutputFormat::Json),
+            Some("pretty") => Ok(OutputFormat::Pretty),
+            Some("text") => Ok(OutputFormat::Text),
+            Some("html") => Ok(OutputFormat::Html),
+            Some(other) => Err(AppError::BadRequest(format!(
+                "Unknown format '{}', expected json, pretty, text or html",
+                other
+            ))),
+        }
+    }
+
+    /// 文本和 HTML 报告展示连接细节, 需要读取 ALPN
+    pub fn is_diagnostic(self) -> bool {
+        matches!(self, OutputFormat::Text | OutputFormat::Html)
+    }
+}
+
+/// 按指定格式输出响应, `json` 保持原有的信封结构
+pub fn render(format: OutputFormat, request: &CurlRequest, response: CurlResponse) -> Response {
+    match format {
+        OutputFormat::Json => response.into_response(),
+        OutputFormat::Pretty => match &response.response_body {
+            Some(body) => {
+                let kind = body_kind(response.response_headers.as_ref(), body);
+                let content_type = match kind {
+                    BodyKind::Json => "application/json",
+                    BodyKind::Xml => "application/xml; charset=utf-8",
+                    // 实体解码后不再是合法的 HTML, 按文本返回
+                    BodyKind::Html | BodyKind::Text => "text/plain; charset=utf-8",
+                };
+                with_content_type(content_type, pretty_body(kind, body))
+            }
+            // 没有响应内容 (出错或已保存到 sink) 时返回文本报告
+            None => with_content_type("text/plain; charset=utf-8", text_report(request, &response)),
+        },
+        OutputFormat::Text => with_content_type("text/plain; charset=utf-8", text_report(request, &response)),
+        OutputFormat::Html => with_content_type("text/html; charset=utf-8", html_report(request, &response)),
+    }
+}
+
+fn with_content_type(content_type: &'static str, body: String) -> Response {
+    (StatusCode::OK, [(header::CONTENT_TYPE, content_type)], body).into_response()
+}
+
+/// 优先按 Content-Type 判断, 缺失或为通用类型时根据内容猜测
+pub fn body_kind(headers: Option<&HashMap<String, String>>, body: &str) -> BodyKind {
+    let content_type = headers
+        .and_then(|headers| headers.get("content-type"))
+        .map(|value| value.to_ascii_lowercase())
+        .unwrap_or_default();
+    if content_type.contains("json") {
+        return BodyKind::Json;
+    }
+    // application/xhtml+xml 按 HTML 处理
+    if content_type.contains("html") {
+        return BodyKind::Html;
+    }
+    if content_type.contains("xml") {
+        return BodyKind::Xml;
+    }
+    if content_type.starts_with("text/") && content_type != "text/plain" {
+        return BodyKind::Text;
+    }
+
+    let trimmed = body.trim_start();
+    let head = trimmed.chars().take(256).collect::<String>().to_ascii_lowercase();
+    if (trimmed.starts_with('{') || trimmed.starts_with('[')) && serde_json::from_str::<serde_json::Value>(body).is_ok() {
+        BodyKind::Json
+    } else if head.starts_with("<!doctype html") || head.starts_with("<html") || head.contains("<html") {
+        BodyKind::Html
+    } else if head.starts_with("<?xml") || (trimmed.starts_with('<') && trimmed.trim_end().ends_with('>')) {
+        BodyKind::Xml
+    } else {
+        BodyKind::Text
+    }
+}
+
+/// JSON/XML 缩进, HTML 解码实体, 无法解析时原样返回
+pub fn pretty_body(kind: BodyKind, body: &str) -> String {
+    match kind {
+        BodyKind::Json => serde_json::from_str::<serde_json::Value>(body)
+            .ok()
+            .and_then(|value| serde_json::to_string_pretty(&value).ok())
+            .unwrap_or_else(|| body.to_string()),
+        BodyKind::Xml => indent_xml(body),
+        BodyKind::Html => decode_entities(body),
+        BodyKind::Text => body.to_string(),
+    }
+}
+
+/// 按标签嵌套层级缩进, 只包含文本的元素保持在一行
+fn indent_xml(source: &str) -> String {
+    let mut tokens = Vec::new();
+    let mut rest = source.trim();
+    while !rest.is_empty() {
+        let end = if rest.starts_with("<!--") {
+            rest.find("-->").map(|index| index + 3)
+        } else if rest.starts_with("<![CDATA[") {
+            rest.find("]]>").map(|index| index + 3)
+        } else if rest.starts_with('<') {
+            rest.find('>').map(|index| index + 1)
+        } else {
+            rest.find('<')
+        };
+        let (token, remaining) = rest.split_at(end.unwrap_or(rest.len()));
+        if !token.trim().is_empty() {
+            tokens.push(token.trim());
+        }
+        rest = remaining;
+    }
+
+    let is_open = |token: &str| {
+        token.starts_with('<')
+            && !token.starts_with("</")
+            && !token.starts_with("<?")
+            && !token.starts_with("<!")
+            && !token.ends_with("/>")
+    };
+    let is_close = |token: &str| token.starts_with("</");
+
+    let mut lines = Vec::new();
+    let mut depth = 0usize;
+    let mut index = 0;
+    while index < tokens.len() {
+        let token = tokens[index];
+        let indent = "  ".repeat(depth);
+        // <a>text</a> 与 <a></a> 合并为一行
+        if is_open(token) {
+            match (tokens.get(index + 1), tokens.get(index + 2)) {
+                (Some(next), _) if is_close(next) => {
+                    lines.push(format!("{}{}{}", indent, token, next));
+                    index += 2;
+                    continue;
+                }
+                (Some(text), Some(next)) if !text.starts_with('<') && is_close(next) => {
+                    lines.push(format!("{}{}{}{}", indent, token, text, next));
+                    index += 3;
+                    continue;
+                }
+                _ => {}
+            }
+        }
+
+        if is_close(token) {
+            depth = depth.saturating_sub(1);
+            lines.push(format!("{}{}", "  ".repeat(depth), token));
+        } else {
+            lines.push(format!("{}{}", indent, token));
+            if is_open(token) {
+                depth += 1;
+            }
+        }
+        index += 1;
+    }
+    lines.join("\n")
+}
+
+/// 解码数字实体和常见的命名实体, 未知实体保持不变
+pub fn decode_entities(text: &str) -> String {
+    ENTITY
+        .replace_all(text, |captures: &regex::Captures| {
+            let name = &captures[1];
+            let decoded = if let Some(hex) = name.strip_prefix("#x").or_else(|| name.strip_prefix("#X")) {
+                u32::from_str_radix(hex, 16).ok().and_then(char::from_u32)
+            } else if let Some(decimal) = name.strip_prefix('#') {
+                decimal.parse::<u32>().ok().and_then(char::from_u32)
+            } else {
+                match name {
+                    "amp" => Some('&'),
+                    "lt" => Some('<'),
+                    "gt" => Some('>'),
+                    "quot" => Some('"'),
+                    "apos" => Some('\''),
+                    "nbsp" => Some('\u{a0}'),
+                    "copy" => Some('©'),
+                    "reg" => Some('®'),
+                    "trade" => Some('™'),
+                    "hellip" => Some('…'),
+                    "mdash" => Some('—'),
+                    "ndash" => Some('–'),
+                    "laquo" => Some('«'),
+                    "raquo" => Some('»'),
+                    "euro" => Some('€'),
+                    _ => None,
+                }
+            };
+            decoded.map(String::from).unwrap_or_else(|| captures[0].to_string())
+        })
+        .into_owned()
+}
+
+/// 响应头按名称排序, 状态行单独取出
+fn sorted_headers(response: &CurlResponse) -> (Option<&String>, Vec<(&String, &String)>) {
+    let Some(headers) = &response.response_headers else {
+        return (None, Vec::new());
+    };
+    let mut sorted: Vec<_> = headers.iter().filter(|(name, _)| name.as_str() != "status").collect();
+    sorted.sort();
+    (headers.get("status"), sorted)
+}
+
+/// 类似 `curl -v` 的报告: `>` 为请求, `<` 为响应, `*` 为附加信息
+pub fn text_report(request: &CurlRequest, response: &CurlResponse) -> String {
+    let mut report = String::new();
+    let _ = writeln!(report, "* {}", response.curl_command);
+    let _ = writeln!(report, "> {} {}", request.method, request.display_url());
+    for (name, value) in request.display_headers() {
+        let _ = writeln!(report, "> {}: {}", name, value);
+    }
+    let _ = writeln!(report, ">");
+    if let Some(remote_ip) = &response.remote_ip {
+        let _ = writeln!(report, "* Connected to {}", remote_ip);
+    }
+    if let Some(alpn) = &response.alpn {
+        let _ = writeln!(report, "* ALPN: server accepted {}", alpn);
+    }
+    if let Some(http_version) = &response.http_version {
+        let _ = writeln!(report, "* Using HTTP/{}", http_version);
+    }
+    for hop in &response.redirects {
+        let _ = writeln!(
+            report,
+            "* Redirect {} {} -> {}",
+            hop.status_code.map(|code| code.to_string()).unwrap_or_else(|| "?".to_string()),
+            hop.url,
+            hop.location.as_deref().unwrap_or_default()
+        );
+    }
+
+    let (status, headers) = sorted_headers(response);
+    match (status, response.status_code) {
+        (Some(status), _) => {
+            let _ = writeln!(report, "< {}", status);
+        }
+        (None, Some(code)) => {
+            let _ = writeln!(report, "< HTTP {}", code);
+        }
+        (None, None) => {}
+    }
+    for (name, value) in &headers {
+        let _ = writeln!(report, "< {}: {}", name, value);
+    }
+    if response.status_code.is_some() {
+        let _ = writeln!(report, "<");
+    }
+
+    if let Some(body) = &response.response_body {
+        let kind = body_kind(response.response_headers.as_ref(), body);
+        let _ = writeln!(report, "{}", pretty_body(kind, body));
+        let _ = writeln!(report);
+    }
+    if let Some(stored) = &response.stored_file {
+        let _ = writeln!(
+            report,
+            "* Stored {} ({} bytes, {}) via {}: {}",
+            stored.filename, stored.size_bytes, stored.content_type, stored.sink, stored.url
+        );
+    }
+    if let Some(timings) = &response.timings {
+        let _ = writeln!(
+            report,
+            "* Timings: dns {:.1} ms, connect {:.1} ms, tls {:.1} ms, first byte {:.1} ms, total {:.1} ms",
+            timings.dns_ms, timings.connect_ms, timings.tls_ms, timings.first_byte_ms, timings.total_ms
+        );
+    }
+    if let Some(digests) = &response.digests {
+        let _ = writeln!(report, "* sha256 {}", digests.sha256);
+    }
+    for result in response.assertions.iter().flatten() {
+        let _ = writeln!(
+            report,
+            "* Assertion {} {}: expected {}, got {}",
+            if result.passed { "passed" } else { "FAILED" },
+            result.assertion,
+            result.expected,
+            result.actual.as_deref().unwrap_or("nothing")
+        );
+    }
+    if let Some(error) = &response.error {
+        let _ = writeln!(report, "* Error: {}", error.trim());
+    }
+    if let Some(history_id) = &response.history_id {
+        let _ = writeln!(report, "* History id {}", history_id);
+    }
+    report
+}
+
+pub fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+fn table(rows: &[(String, String)]) -> String {
+    let rows: String = rows
+        .iter()
+        .map(|(name, value)| format!("<tr><td>{}</td><td>{}</td></tr>", escape_html(name), escape_html(value)))
+        .collect();
+    format!("<table>{}</table>", rows)
+}
+
+/// 单页 HTML 报告, HTML 响应在沙箱 iframe 中预览
+pub fn html_report(request: &CurlRequest, response: &CurlResponse) -> String {
+    let mut sections = String::new();
+
+    let status_class = match response.status_code {
+        Some(200..=299) => "ok",
+        Some(300..=399) => "redirect",
+        _ => "fail",
+    };
+    let status = response
+        .status_code
+        .map(|code| code.to_string())
+        .unwrap_or_else(|| "no response".to_string());
+    let _ = write!(
+        sections,
+        "<h1><span class=\"status {}\">{}</span> {} {}</h1>",
+        status_class,
+        status,
+        escape_html(&request.method),
+        escape_html(&request.display_url())
+    );
+    if let Some(error) = &response.error {
+        let _ = write!(sections, "<p class=\"error\">{}</p>", escape_html(error.trim()));
+    }
+    let _ = write!(
+        sections,
+        "<h2>curl command</h2><pre>{}</pre>",
+        escape_html(&response.curl_command)
+    );
+
+    if let Some(stored) = &response.stored_file {
+        let _ = write!(
+            sections,
+            "<h2>Stored file</h2><p><a href=\"{}\">{}</a> ({} bytes, {}, via {})</p>",
+            escape_html(&stored.url),
+            escape_html(&stored.filename),
+            stored.size_bytes,
+            escape_html(&stored.content_type),
+            escape_html(&stored.sink)
+        );
+    }
+
+    if !response.redirects.is_empty() {
+        let rows: Vec<(String, String)> = response
+            .redirects
+            .iter()
+            .map(|hop| {
+                (
+                    format!(
+                        "{} {}",
+                        hop.status_code.map(|code| code.to_string()).unwrap_or_else(|| "?".to_string()),
+                        hop.url
+                    ),
+                    format!("→ {}", hop.location.as_deref().unwrap_or_default()),
+                )
+            })
+            .collect();
+        let _ = write!(sections, "<h2>Redirects</h2>{}", table(&rows));
+    }
+
+    let connection_rows: Vec<(String, String)> = [
+        ("Remote IP", &response.remote_ip),
+        ("HTTP version", &response.http_version),
+        ("ALPN", &response.alpn),
+    ]
+    .iter()
+    .filter_map(|(name, value)| value.as_ref().map(|value| (name.to_string(), value.clone())))
+    .collect();
+    if !connection_rows.is_empty() {
+        let _ = write!(sections, "<h2>Connection</h2>{}", table(&connection_rows));
+    }
+
+    if let Some(timings) = &response.timings {
+        let rows: Vec<(String, String)> = [
+            ("DNS", timings.dns_ms),
+            ("Connect", timings.connect_ms),
+            ("TLS", timings.tls_ms),
+            ("First byte", timings.first_byte_ms),
+            ("Total", timings.total_ms),
+        ]
+        .iter()
+        .map(|(name, ms)| (name.to_string(), format!("{:.1} ms", ms)))
+        .collect();
+        let _ = write!(sections, "<h2>Timings</h2>{}", table(&rows));
+    }
+
+    let request_rows: Vec<(String, String)> = request
+        .display_headers()
+        .into_iter()
+        .map(|(name, value)| (name.to_string(), value))
+        .collect();
+    if !request_rows.is_empty() {
+        let _ = write!(sections, "<h2>Request headers</h2>{}", table(&request_rows));
+    }
+
+    let (status_line, headers) = sorted_headers(response);
+    if status_line.is_some() || !headers.is_empty() {
+        let mut rows: Vec<(String, String)> = status_line
+            .map(|status| ("status".to_string(), status.clone()))
+            .into_iter()
+            .collect();
+        rows.extend(headers.iter().map(|(name, value)| (name.to_string(), value.to_string())));
+        let _ = write!(sections, "<h2>Response headers</h2>{}", table(&rows));
+    }
+
+    if let Some(body) = &response.response_body {
+        let kind = body_kind(response.response_headers.as_ref(), body);
+        if kind == BodyKind::Html {
+            // 空的 sandbox 禁止脚本、表单和同源访问
+            let _ = write!(
+                sections,
+                "<h2>Body (preview)</h2><iframe sandbox srcdoc=\"{}\"></iframe>\
+                 <details><summary>Source</summary><pre>{}</pre></details>",
+                escape_html(body),
+                escape_html(body)
+            );
+        } else {
+            let _ = write!(sections, "<h2>Body</h2><pre>{}</pre>", escape_html(&pretty_body(kind, body)));
+        }
+    }
+
+    if let Some(assertions) = &response.assertions {
+        let rows: Vec<(String, String)> = assertions
+            .iter()
+            .map(|result| {
+                (
+                    format!("{} {}", if result.passed { "✔" } else { "✘" }, result.assertion),
+                    format!(
+                        "expected {}, got {}",
+                        result.expected,
+                        result.actual.as_deref().unwrap_or("nothing")
+                    ),
+                )
+            })
+            .collect();
+        let _ = write!(sections, "<h2>Assertions</h2>{}", table(&rows));
+    }
+
+    if let Some(digests) = &response.digests {
+        let rows = vec![
+            ("sha256".to_string(), digests.sha256.clone()),
+            ("sha1".to_string(), digests.sha1.clone()),
+        ];
+        let _ = write!(sections, "<h2>Digests</h2>{}", table(&rows));
+    }
+
+    format!(
+        "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"UTF-8\">\n<title>{} {}</title>\n<style>{}</style>\n</head>\n<body>\n{}\n</body>\n</html>\n",
+        escape_html(&request.method),
+        escape_html(&request.display_url()),
+        REPORT_STYLE,
+        sections
+    )
+}
+
+const REPORT_STYLE: &str = "body{margin:20px;font:14px/1.4 system-ui,sans-serif;color:#222}\
+h1{font-size:18px;word-break:break-all}h2{font-size:13px;text-transform:uppercase;color:#666;margin-top:20px}\
+pre{padding:8px;background:#f7f7f9;border:1px solid #eee;border-radius:4px;white-space:pre-wrap;word-break:break-all}\
+table{border-collapse:collapse;width:100%;font:12px ui-monospace,monospace}\
+td{padding:3px 6px;border-bottom:1px solid #eee;vertical-align:top;word-break:break-all}td:first-child{width:30%;color:#555}\
+iframe{width:100%;height:400px;border:1px solid #ddd}.error{color:#e3342f;white-space:pre-wrap}\
+.status{padding:2px 8px;border-radius:4px;color:#fff;background:#888}.ok{background:#1f9d55}\
+.redirect{background:#d69e2e}.fail{background:#e3342f}";
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_report_formats_are_diagnostic() {
+        assert!(!OutputFormat::Json.is_diagnostic());
+        assert!(!OutputFormat::Pretty.is_diagnostic());
+        assert!(OutputFormat::Text.is_diagnostic());
+        assert!(OutputFormat::Html.is_diagnostic());
+    }
+
+    #[test]
+    fn indent_xml_nests_elements_and_keeps_text_inline() {
+        let xml = r#"<?xml version="1.0"?><feed><title>News</title><entry id="1"><empty></empty><link href="/a"/></entry></feed>"#;
+        assert_eq!(
+            indent_xml(xml),
+            [
+                r#"<?xml version="1.0"?>"#,
+                "<feed>",
+                "  <title>News</title>",
+                r#"  <entry id="1">"#,
+                "    <empty></empty>",
+                r#"    <link href="/a"/>"#,
+                "  </entry>",
+                "</feed>",
+            ]
+            .join("\n")
+        );
+    }
+
+    #[test]
+    fn indent_xml_keeps_comments_and_cdata_whole() {
+        let xml = "<a>\n  <!-- <b>not a tag</b> -->\n  <c><![CDATA[x < y]]></c>\n</a>";
+        assert_eq!(
+            indent_xml(xml),
+            ["<a>", "  <!-- <b>not a tag</b> -->", "  <c>", "    <![CDATA[x < y]]>", "  </c>", "</a>"].join("\n")
+        );
+    }
+}
@@ -1,34 +1,210 @@
 use axum::{
-    extract::{Query, Request, State},
-    http::HeaderMap,
+    extract::{Json, Path, Query, Request, State},
+    http::{HeaderMap, StatusCode},
     response::Response,
 };
+use serde::Deserialize;
+use serde_json::{json, Value};
 use std::{collections::HashMap, sync::Arc};
 
 use crate::{
+    assertions::AssertionSpec,
+    auth::AuthSpec,
+    checksum::ChecksumSpec,
     errors::AppError,
-    models::AppState,
+    format::{render, OutputFormat},
+    history,
+    links::{rewrite_response_links, PublicBaseUrl},
+    models::{AppState, CurlRequest, CurlResponse, HttpVersion},
     services::execute_curl_command,
+    stream::{stream_response, StreamOptions},
 };
 
 pub async fn curl_proxy(
     State(state): State<Arc<AppState>>,
-    Query(params): Query<HashMap<String, String>>,
+    PublicBaseUrl(public_base): PublicBaseUrl,
+    Query(query): Query<Vec<(String, String)>>,
     headers: HeaderMap,
     req: Request,
 ) -> Result<Response, AppError> {
+    // 可重复的参数 (例如 resolve) 保留全部取值, 其余参数以最后一次出现为准
+    let params: HashMap<String, String> = query.iter().cloned().collect();
+    let values = |name: &str| {
+        query
+            .iter()
+            .filter(|(key, _)| key == name)
+            .map(|(_, value)| value.clone())
+            .collect::<Vec<_>>()
+    };
+
+    let format = OutputFormat::from_param(params.get("format").map(String::as_str))?;
+    let stream = StreamOptions::from_params(
+        &state.config().stream,
+        params.get("stream").map(String::as_str),
+        seconds_param(&params, "idle_timeout")?,
+        seconds_param(&params, "max_duration")?,
+    )?;
+    if stream.is_some() && params.contains_key("format") {
+        return Err(AppError::BadRequest("'format' cannot be combined with 'stream'".to_string()));
+    }
+
     // 获取目标URL
     let target_url = params
         .get("url")
         .ok_or_else(|| AppError::BadRequest("Missing 'url' parameter".to_string()))?;
 
-    // 获取HTTP方法
-    let method = req.method().as_str();
+    let request = CurlRequest {
+        url: target_url.to_string(),
+        // 获取HTTP方法
+        method: req.method().as_str().to_string(),
+        headers: headers
+            .iter()
+            .filter_map(|(name, value)| {
+                value
+                    .to_str()
+                    .ok()
+                    .map(|value| (name.as_str().to_string(), value.to_string()))
+            })
+            .collect(),
+        profile: params.get("profile").cloned(),
+        auth: AuthSpec::from_params(&params)?,
+        body: None,
+        resolve: values("resolve"),
+        connect_to: values("connect_to"),
+        assertions: AssertionSpec::from_query(&query)?,
+        checksum: ChecksumSpec::from_params(&params),
+        http_version: params
+            .get("http_version")
+            .filter(|value| !value.is_empty())
+            .map(|value| HttpVersion::from_param(value))
+            .transpose()?,
+        alpn: format.is_diagnostic() || params.get("alpn").is_some_and(|value| value == "true" || value == "1"),
+        secret_values: Vec::new(),
+    };
+
+    if let Some(stream) = stream {
+        return stream_response(&state, &request, stream).await;
+    }
 
     // 执行curl命令
-    execute_curl_command(&state, target_url, &headers, method).await
+    let response = execute_curl_command(&state, &request).await?;
+    let mut response = history::record(&state, &request, response).await;
+    rewrite_response_links(&mut response, public_base.as_deref());
+    Ok(curl_response(&request, response, format))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct FormatQuery {
+    format: Option<String>,
+    stream: Option<String>,
+    idle_timeout: Option<u64>,
+    max_duration: Option<u64>,
+}
+
+/// 以秒为单位的数字参数
+fn seconds_param(params: &HashMap<String, String>, name: &str) -> Result<Option<u64>, AppError> {
+    params
+        .get(name)
+        .map(|value| {
+            value
+                .parse()
+                .map_err(|_| AppError::BadRequest(format!("Invalid '{}' parameter: {}", name, value)))
+        })
+        .transpose()
+}
+
+/// `POST /curl`: 使用 JSON 请求描述执行请求, 支持表单和 multipart 请求体
+pub async fn curl_request(
+    State(state): State<Arc<AppState>>,
+    PublicBaseUrl(public_base): PublicBaseUrl,
+    Query(query): Query<FormatQuery>,
+    Json(mut request): Json<CurlRequest>,
+) -> Result<Response, AppError> {
+    let format = OutputFormat::from_param(query.format.as_deref())?;
+    request.alpn |= format.is_diagnostic();
+    if let Some(stream) = StreamOptions::from_params(
+        &state.config().stream,
+        query.stream.as_deref(),
+        query.idle_timeout,
+        query.max_duration,
+    )? {
+        if query.format.is_some() {
+            return Err(AppError::BadRequest("'format' cannot be combined with 'stream'".to_string()));
+        }
+        return stream_response(&state, &request, stream).await;
+    }
+    let response = execute_curl_command(&state, &request).await?;
+    let mut response = history::record(&state, &request, response).await;
+    rewrite_response_links(&mut response, public_base.as_deref());
+    Ok(curl_response(&request, response, format))
+}
+
+/// 按 `format` 输出; 断言失败且指定了 fail_status 时, 使用对应的 HTTP 状态码返回, 便于负载均衡器直接判断
+pub(crate) fn curl_response(request: &CurlRequest, response: CurlResponse, format: OutputFormat) -> Response {
+    let fail_status = request
+        .assertions
+        .as_ref()
+        .and_then(|assertions| assertions.fail_status)
+        .filter(|_| response.assertions_failed())
+        .and_then(|code| StatusCode::from_u16(code).ok());
+
+    let mut http_response = render(format, request, response);
+    if let Some(status) = fail_status {
+        *http_response.status_mut() = status;
+    }
+    http_response
+}
+
+#[derive(Debug, Deserialize)]
+pub struct HistoryQuery {
+    limit: Option<usize>,
+}
+
+/// `GET /monitors`: 所有监控项的当前状态
+pub async fn list_monitors(State(state): State<Arc<AppState>>) -> Json<Value> {
+    let monitors = state.monitors.read().await;
+    let mut list: Vec<Value> = monitors
+        .values()
+        .map(|status| {
+            json!({
+                "name": status.name,
+                "url": status.url,
+                "interval_seconds": status.interval_seconds,
+                "up": status.up,
+                "last_check": status.last_check,
+                "last_change": status.last_change,
+                "uptime_ratio": status.uptime_ratio(),
+                "last_result": status.history.back(),
+            })
+        })
+        .collect();
+    list.sort_by(|a, b| a["name"].as_str().cmp(&b["name"].as_str()));
+
+    Json(json!({ "monitors": list }))
+}
+
+/// `GET /monitors/{name}/history`: 监控项的检查历史, 按时间先后排列
+pub async fn monitor_history(
+    State(state): State<Arc<AppState>>,
+    Path(name): Path<String>,
+    Query(query): Query<HistoryQuery>,
+) -> Result<Json<Value>, AppError> {
+    let monitors = state.monitors.read().await;
+    let status = monitors
+        .get(&name)
+        .ok_or_else(|| AppError::NotFound(format!("Monitor '{}' not found", name)))?;
+
+    let limit = query.limit.unwrap_or(status.history.len());
+    let skip = status.history.len().saturating_sub(limit);
+    let history: Vec<_> = status.history.iter().skip(skip).collect();
+
+    Ok(Json(json!({
+        "name": status.name,
+        "up": status.up,
+        "history": history,
+    })))
 }
 
 pub async fn health_check() -> &'static str {
     "curl2url service is running"
-} 
\ No newline at end of file
+}
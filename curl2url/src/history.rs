@@ -0,0 +1,86 @@
+use axum::extract::{Json, Path, State};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::sync::Arc;
+use uuid::Uuid;
+
+use crate::{
+    errors::AppError,
+    models::{AppState, CurlRequest, CurlResponse},
+};
+
+/// 一次已执行的请求及其响应
+#[derive(Debug, Clone, Serialize)]
+pub struct HistoryEntry {
+    pub id: String,
+    pub timestamp: DateTime<Utc>,
+    pub method: String,
+    pub url: String,
+    pub response: CurlResponse,
+}
+
+/// 保存响应到历史记录, 并在响应中写入对应的 history_id
+pub async fn record(state: &Arc<AppState>, request: &CurlRequest, mut response: CurlResponse) -> CurlResponse {
+    let max_entries = state.config().history.max_entries;
+    if max_entries == 0 {
+        return response;
+    }
+
+    let id = Uuid::new_v4().simple().to_string();
+    response.history_id = Some(id.clone());
+
+    let mut history = state.history.write().await;
+    history.push_back(HistoryEntry {
+        id,
+        timestamp: Utc::now(),
+        method: request.method.to_ascii_uppercase(),
+        url: request.display_url(),
+        response: response.clone(),
+    });
+    while history.len() > max_entries {
+        history.pop_front();
+    }
+
+    response
+}
+
+/// 按 id 查找历史记录
+pub async fn find(state: &Arc<AppState>, id: &str) -> Result<HistoryEntry, AppError> {
+    state
+        .history
+        .read()
+        .await
+        .iter()
+        .find(|entry| entry.id == id)
+        .cloned()
+        .ok_or_else(|| AppError::NotFound(format!("History entry '{}' not found", id)))
+}
+
+/// `GET /history`: 最近执行的请求摘要, 最新的在前
+pub async fn list_history(State(state): State<Arc<AppState>>) -> Json<Value> {
+    let history = state.history.read().await;
+    let entries: Vec<Value> = history
+        .iter()
+        .rev()
+        .map(|entry| {
+            json!({
+                "id": entry.id,
+                "timestamp": entry.timestamp,
+                "method": entry.method,
+                "url": entry.url,
+                "status_code": entry.response.status_code,
+            })
+        })
+        .collect();
+
+    Json(json!({ "entries": entries }))
+}
+
+/// `GET /history/{id}`: 完整的历史记录
+pub async fn get_history(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+) -> Result<Json<HistoryEntry>, AppError> {
+    Ok(Json(find(&state, &id).await?))
+}
@@ -0,0 +1,178 @@
+use regex::{Captures, Regex};
+use std::sync::LazyLock;
+
+use crate::format::{decode_entities, escape_html};
+
+/// HTML 开始标签, 属性值中包含 `>` 的情况不处理
+static TAG: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"<([A-Za-z][A-Za-z0-9-]*)(\s[^>]*)?>").expect("valid tag regex"));
+
+/// 包含链接的属性
+static LINK_ATTR: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"(?i)(\s)(href|src|action|srcset|poster)(\s*=\s*)("[^"]*"|'[^']*'|[^\s"'>]+)"#)
+        .expect("valid attribute regex")
+});
+
+static BASE_HREF: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"(?i)<base\s[^>]*href\s*=\s*(?:"([^"]*)"|'([^']*)'|([^\s"'>]+))"#).expect("valid base regex")
+});
+
+/// CSS 中的 `url(...)` 和 `@import "..."`
+static CSS_URL: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"(?i)url\(\s*(?:"([^"]*)"|'([^']*)'|([^)"'\s]*))\s*\)|@import\s+(?:"([^"]*)"|'([^']*)')"#)
+        .expect("valid css url regex")
+});
+
+/// 页面中的一处链接引用
+pub struct LinkRef<'a> {
+    /// 小写的标签名, CSS 中的引用为空
+    pub tag: &'a str,
+    /// 小写的属性名, CSS 中的引用为 `url` 或 `import`
+    pub attr: &'a str,
+    /// 所在标签的全部属性
+    pub attributes: &'a str,
+    /// 解析后的绝对地址, 不含 fragment
+    pub url: &'a str,
+}
+
+/// 决定链接如何改写, 返回 `None` 时保持原样
+pub trait LinkRewriter {
+    fn rewrite(&mut self, link: &LinkRef) -> Option<String>;
+
+    /// 标签中的链接改写完成后调用, 可以继续修改整个标签
+    fn finish_tag(&mut self, _tag: &str, _attributes: &str, rewritten: String) -> String {
+        rewritten
+    }
+}
+
+/// 相对链接的基准: 页面中的 `<base href>`, 没有时为页面地址
+pub fn document_base(html: &str, page_url: &str) -> String {
+    BASE_HREF
+        .captures(html)
+        .and_then(|captures| captures.get(1).or(captures.get(2)).or(captures.get(3)))
+        .and_then(|href| url::Url::parse(page_url).ok()?.join(&decode_entities(href.as_str())).ok())
+        .map(|base| base.to_string())
+        .unwrap_or_else(|| page_url.to_string())
+}
+
+/// 改写 HTML 中标签的链接属性以及内联 CSS 的 `url()`
+pub fn rewrite_html(html: &str, page_url: &str, rewriter: &mut impl LinkRewriter) -> String {
+    let base = document_base(html, page_url);
+    let html = rewrite_css(html, &base, true, rewriter);
+    TAG.replace_all(&html, |captures: &Captures| rewrite_tag(captures, &base, rewriter))
+        .into_owned()
+}
+
+fn rewrite_tag(captures: &Captures, base: &str, rewriter: &mut impl LinkRewriter) -> String {
+    let tag = captures[1].to_ascii_lowercase();
+    let attributes = captures.get(2).map(|attributes| attributes.as_str()).unwrap_or_default();
+
+    let rewritten = LINK_ATTR.replace_all(&captures[0], |attr: &Captures| {
+        let value = decode_entities(attr[4].trim_matches(|c| c == '"' || c == '\''));
+        let attr_name = attr[2].to_ascii_lowercase();
+        let new_value = if attr_name == "srcset" {
+            rewrite_srcset(&value, base, &tag, attributes, rewriter)
+        } else {
+            rewrite_link(&value, base, &tag, &attr_name, attributes, rewriter)
+        };
+        match new_value {
+            Some(new_value) => format!("{}{}{}\"{}\"", &attr[1], &attr[2], &attr[3], escape_html(&new_value)),
+            None => attr[0].to_string(),
+        }
+    });
+    let rewritten = rewritten.into_owned();
+    rewriter.finish_tag(&tag, attributes, rewritten)
+}
+
+/// `srcset` 为逗号分隔的 `地址 描述符` 列表
+fn rewrite_srcset(
+    srcset: &str,
+    base: &str,
+    tag: &str,
+    attributes: &str,
+    rewriter: &mut impl LinkRewriter,
+) -> Option<String> {
+    let mut changed = false;
+    let candidates: Vec<String> = srcset
+        .split(',')
+        .map(|candidate| {
+            let candidate = candidate.trim();
+            let (link, descriptor) = candidate.split_once(char::is_whitespace).unwrap_or((candidate, ""));
+            let link = match rewrite_link(link, base, tag, "srcset", attributes, rewriter) {
+                Some(link) => {
+                    changed = true;
+                    link
+                }
+                None => link.to_string(),
+            };
+            if descriptor.is_empty() {
+                link
+            } else {
+                format!("{} {}", link, descriptor.trim())
+            }
+        })
+        .collect();
+    changed.then(|| candidates.join(", "))
+}
+
+/// 改写 CSS 中的链接, `in_html` 时地址中的实体需要先解码
+pub fn rewrite_css(css: &str, base: &str, in_html: bool, rewriter: &mut impl LinkRewriter) -> String {
+    CSS_URL
+        .replace_all(css, |captures: &Captures| {
+            let import = captures.get(4).or(captures.get(5)).is_some();
+            let Some(value) = (1..=5).find_map(|index| captures.get(index)).map(|value| value.as_str()) else {
+                return captures[0].to_string();
+            };
+            // style 属性中的引号可能写成 &quot;
+            let value = if in_html { decode_entities(value) } else { value.to_string() };
+            let value = value.trim_matches(|c| c == '"' || c == '\'');
+            let attr = if import { "import" } else { "url" };
+            match rewrite_link(value, base, "", attr, "", rewriter) {
+                // 不加引号, 可以同时用于 style 属性和 <style> 中
+                Some(link) if import => format!("@import url({})", css_safe(&link)),
+                Some(link) => format!("url({})", css_safe(&link)),
+                None => captures[0].to_string(),
+            }
+        })
+        .into_owned()
+}
+
+/// 无引号的 `url()` 中不能出现括号、引号和空白
+fn css_safe(link: &str) -> String {
+    link.replace('(', "%28")
+        .replace(')', "%29")
+        .replace('\'', "%27")
+        .replace('"', "%22")
+        .replace(char::is_whitespace, "%20")
+}
+
+fn rewrite_link(
+    value: &str,
+    base: &str,
+    tag: &str,
+    attr: &str,
+    attributes: &str,
+    rewriter: &mut impl LinkRewriter,
+) -> Option<String> {
+    let value = value.trim();
+    // 页内锚点保持不变
+    if value.is_empty() || value.starts_with('#') {
+        return None;
+    }
+    let mut resolved = url::Url::parse(&resolve(value, base)?).ok()?;
+    let fragment = resolved.fragment().map(|fragment| format!("#{}", fragment));
+    resolved.set_fragment(None);
+    let link = rewriter.rewrite(&LinkRef {
+        tag,
+        attr,
+        attributes,
+        url: resolved.as_str(),
+    })?;
+    Some(format!("{}{}", link, fragment.unwrap_or_default()))
+}
+
+/// 解析为绝对地址, 只接受 http/https, `data:`、`javascript:` 等返回 `None`
+pub fn resolve(link: &str, base: &str) -> Option<String> {
+    let resolved = url::Url::parse(base).ok()?.join(link.trim()).ok()?;
+    matches!(resolved.scheme(), "http" | "https").then(|| resolved.to_string())
+}
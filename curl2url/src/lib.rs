@@ -1,6 +1,30 @@
+pub mod assertions;
+pub mod auth;
+pub mod bins;
+pub mod body;
+pub mod browse;
+pub mod checksum;
+pub mod command;
 pub mod config;
+pub mod crawl;
+pub mod diag;
+pub mod diff;
 pub mod errors;
+pub mod format;
 pub mod handlers;
+pub mod history;
+pub mod html;
+pub mod links;
 pub mod models;
+pub mod monitor;
+pub mod profiles;
+pub mod segmented;
 pub mod services;
-pub mod utils; 
\ No newline at end of file
+pub mod sinks;
+pub mod snapshot;
+pub mod ssrf;
+pub mod stream;
+pub mod templates;
+pub mod utils;
+pub mod vcr;
+pub mod websocket; 
\ No newline at end of file
@@ -0,0 +1,180 @@
+use axum::{
+    extract::{ConnectInfo, FromRequestParts},
+    http::{request::Parts, HeaderMap},
+};
+use std::{
+    convert::Infallible,
+    net::{IpAddr, SocketAddr},
+    sync::Arc,
+};
+
+use crate::{
+    config::ServerConfig,
+    models::{AppState, CurlResponse},
+    sinks::StoredFile,
+};
+
+/// 调用方访问本服务时使用的地址, 例如 `https://tools.example.com`, 无法确定时为空
+#[derive(Debug, Clone)]
+pub struct PublicBaseUrl(pub Option<String>);
+
+impl FromRequestParts<Arc<AppState>> for PublicBaseUrl {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, state: &Arc<AppState>) -> Result<Self, Self::Rejection> {
+        let peer = parts
+            .extensions
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|ConnectInfo(addr)| addr.ip());
+        Ok(PublicBaseUrl(public_base_url(&state.config().server, peer, &parts.headers)))
+    }
+}
+
+/// 校验 `trusted_proxies` 中的 IP/CIDR
+pub fn validate_trusted_proxies(config: &ServerConfig) -> anyhow::Result<()> {
+    for entry in &config.trusted_proxies {
+        parse_cidr(entry).ok_or_else(|| anyhow::anyhow!("Invalid server.trusted_proxies entry '{}'", entry))?;
+    }
+    Ok(())
+}
+
+/// 优先使用配置的 `public_base_url`; 请求来自受信任代理时依次使用 X-Forwarded-Host/Proto 和 Origin
+pub fn public_base_url(config: &ServerConfig, peer: Option<IpAddr>, headers: &HeaderMap) -> Option<String> {
+    if let Some(base_url) = &config.public_base_url {
+        return Some(base_url.trim_end_matches('/').to_string());
+    }
+
+    let trusted = peer.is_some_and(|peer| {
+        config
+            .trusted_proxies
+            .iter()
+            .filter_map(|entry| parse_cidr(entry))
+            .any(|(network, prefix)| ip_in_network(peer, network, prefix))
+    });
+    if !trusted {
+        return None;
+    }
+
+    let header = |name: &str| {
+        headers
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            // 经过多层代理时取第一个 (最靠近客户端的) 值
+            .and_then(|value| value.split(',').next())
+            .map(str::trim)
+            .filter(|value| !value.is_empty())
+    };
+
+    if let Some(host) = header("x-forwarded-host") {
+        let proto = header("x-forwarded-proto").unwrap_or("http");
+        if let Some(origin) = parse_origin(&format!("{}://{}", proto, host)) {
+            return Some(origin);
+        }
+    }
+    header("origin").and_then(parse_origin)
+}
+
+/// 只接受 http/https 且不带路径的来源地址
+fn parse_origin(raw: &str) -> Option<String> {
+    let parsed = url::Url::parse(raw).ok()?;
+    if !matches!(parsed.scheme(), "http" | "https") || parsed.host_str().is_none() || parsed.path() != "/" {
+        return None;
+    }
+    Some(parsed.origin().ascii_serialization())
+}
+
+/// 将响应中的下载链接改为公开地址, S3 预签名链接的签名包含 host, 不做改写
+pub fn rewrite_response_links(response: &mut CurlResponse, public_base: Option<&str>) {
+    let Some(public_base) = public_base else {
+        return;
+    };
+    if response
+        .stored_file
+        .as_ref()
+        .is_some_and(|stored| stored.sink == "s3")
+    {
+        return;
+    }
+
+    if let Some(url) = &response.temp_file_url {
+        response.temp_file_url = Some(rewrite_link(url, public_base));
+    }
+    if let Some(stored) = &mut response.stored_file {
+        rewrite_stored_file(stored, Some(public_base));
+    }
+}
+
+/// 改写单个已保存文件的下载链接, 规则同 `rewrite_response_links`
+pub fn rewrite_stored_file(stored: &mut StoredFile, public_base: Option<&str>) {
+    if let Some(public_base) = public_base
+        && stored.sink != "s3"
+    {
+        stored.url = rewrite_link(&stored.url, public_base);
+    }
+}
+
+/// 保留链接的路径和查询参数, 替换 scheme 和 host
+pub fn rewrite_link(link: &str, public_base: &str) -> String {
+    let Ok(parsed) = url::Url::parse(link) else {
+        return link.to_string();
+    };
+    let query = parsed.query().map(|query| format!("?{}", query)).unwrap_or_default();
+    format!("{}{}{}", public_base.trim_end_matches('/'), parsed.path(), query)
+}
+
+/// 解析 `10.0.0.0/8` 或单个 IP
+fn parse_cidr(entry: &str) -> Option<(IpAddr, u8)> {
+    let (addr, prefix) = match entry.split_once('/') {
+        Some((addr, prefix)) => (addr.trim().parse::<IpAddr>().ok()?, Some(prefix.trim().parse::<u8>().ok()?)),
+        None => (entry.trim().parse::<IpAddr>().ok()?, None),
+    };
+    let max = if addr.is_ipv4() { 32 } else { 128 };
+    let prefix = prefix.unwrap_or(max);
+    (prefix <= max).then_some((addr, prefix))
+}
+
+fn ip_in_network(ip: IpAddr, network: IpAddr, prefix: u8) -> bool {
+    // IPv4 映射的 IPv6 地址按 IPv4 比较
+    let ip = match ip {
+        IpAddr::V6(v6) => v6.to_ipv4_mapped().map(IpAddr::V4).unwrap_or(ip),
+        IpAddr::V4(_) => ip,
+    };
+    match (ip, network) {
+        (IpAddr::V4(ip), IpAddr::V4(network)) => {
+            let mask = u32::MAX.checked_shl(32 - prefix as u32).unwrap_or(0);
+            u32::from(ip) & mask == u32::from(network) & mask
+        }
+        (IpAddr::V6(ip), IpAddr::V6(network)) => {
+            let mask = u128::MAX.checked_shl(128 - prefix as u32).unwrap_or(0);
+            u128::from(ip) & mask == u128::from(network) & mask
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_networks_and_single_addresses() {
+        assert_eq!(parse_cidr("10.0.0.0/8"), Some(("10.0.0.0".parse().unwrap(), 8)));
+        assert_eq!(parse_cidr(" 192.168.1.5 "), Some(("192.168.1.5".parse().unwrap(), 32)));
+        assert_eq!(parse_cidr("fd00::/8"), Some(("fd00::".parse().unwrap(), 8)));
+        assert_eq!(parse_cidr("::1"), Some(("::1".parse().unwrap(), 128)));
+        assert_eq!(parse_cidr("10.0.0.0/33"), None);
+        assert_eq!(parse_cidr("10.0.0.0/x"), None);
+        assert_eq!(parse_cidr("proxy.internal"), None);
+    }
+
+    #[test]
+    fn networks_match_by_prefix() {
+        let ip = |value: &str| value.parse::<IpAddr>().unwrap();
+        assert!(ip_in_network(ip("10.1.2.3"), ip("10.0.0.0"), 8));
+        assert!(!ip_in_network(ip("11.0.0.1"), ip("10.0.0.0"), 8));
+        assert!(ip_in_network(ip("1.2.3.4"), ip("0.0.0.0"), 0));
+        assert!(ip_in_network(ip("::ffff:10.0.0.1"), ip("10.0.0.0"), 8));
+        assert!(ip_in_network(ip("fd12::1"), ip("fd00::"), 8));
+        assert!(!ip_in_network(ip("10.0.0.1"), ip("fd00::"), 8));
+    }
+}
@@ -1,22 +1,48 @@
 use anyhow::Result;
-use axum::{routing::get, Router};
-use std::sync::Arc;
-use tower_http::trace::TraceLayer;
+use axum::{
+    response::Html,
+    routing::{any, delete, get, post},
+    Router,
+};
+use clap::Parser;
+use std::{net::SocketAddr, path::PathBuf, sync::Arc};
+use tower_http::{services::ServeDir, trace::TraceLayer};
 use tracing::info;
 
 use curl2url::{
-    config::Config,
-    handlers::{curl_proxy, health_check},
+    bins::{capture_request, create_bin, delete_bin, list_bin_requests, replay_request},
+    browse::browse,
+    config::{spawn_reload_on_sighup, Config, SinkKind},
+    crawl::{broken_links, cancel_crawl, crawl_report, crawl_status, create_crawl, list_crawls},
+    diag::{dns_lookup, tls_info},
+    diff::diff_responses,
+    handlers::{curl_proxy, curl_request, health_check, list_monitors, monitor_history},
+    history::{get_history, list_history},
     models::AppState,
+    monitor::spawn_monitors,
+    sinks::spawn_local_cleanup,
+    snapshot::create_snapshot,
+    templates::{list_templates, run_template},
+    websocket::{websocket, websocket_probe},
 };
 
+#[derive(Parser)]
+#[command(author, version, about, long_about = None)]
+struct Cli {
+    /// Path to the configuration file
+    #[arg(long, default_value = "config/app_config.toml")]
+    config: PathBuf,
+}
+
 #[tokio::main]
 async fn main() -> Result<()> {
+    let cli = Cli::parse();
+
     // 初始化日志
     tracing_subscriber::fmt::init();
 
     // 加载配置
-    let config = Config::new()?;
+    let config = Config::load(&cli.config)?;
     info!("Loaded configuration: temp_file_host_url={}, max_response_size={}MB", 
           config.proxy.temp_file_host_url, 
           config.proxy.max_response_size_bytes / (1024 * 1024));
@@ -25,27 +51,61 @@ async fn main() -> Result<()> {
     let http_client = reqwest::Client::new();
 
     // 创建应用状态
-    let app_state = Arc::new(AppState {
-        config: config.clone(),
-        http_client,
-    });
+    let app_state = Arc::new(AppState::new(config.clone(), http_client));
+
+    // 启动定时可用性检查
+    spawn_monitors(app_state.clone());
+    // 清理本地 sink 中过期的文件
+    spawn_local_cleanup(app_state.clone());
+    // `kill -HUP` 重新加载配置
+    spawn_reload_on_sighup(app_state.clone(), cli.config.clone());
 
     // 构建路由
-    let app = Router::new()
-        .route("/", get(health_check))
-        .route("/curl", get(curl_proxy))
-        .layer(TraceLayer::new_for_http())
-        .with_state(app_state);
+    let mut app = Router::new()
+        .route(
+            "/",
+            get(|| async { Html(include_str!("../static/index.html")) }),
+        )
+        .route("/health", get(health_check))
+        .route("/curl", get(curl_proxy).post(curl_request))
+        .route("/curl/diff", post(diff_responses))
+        .route("/browse", get(browse).post(browse))
+        .route("/ws", get(websocket).post(websocket_probe))
+        .route("/snapshot", post(create_snapshot))
+        .route("/crawls", get(list_crawls).post(create_crawl))
+        .route("/crawls/{id}", get(crawl_status).delete(cancel_crawl))
+        .route("/crawls/{id}/report", get(crawl_report))
+        .route("/crawls/{id}/broken", get(broken_links))
+        .route("/diag/dns", get(dns_lookup))
+        .route("/diag/tls", get(tls_info))
+        .route("/history", get(list_history))
+        .route("/history/{id}", get(get_history))
+        .route("/templates", get(list_templates))
+        .route("/templates/{name}/run", post(run_template))
+        .route("/monitors", get(list_monitors))
+        .route("/monitors/{name}/history", get(monitor_history))
+        .route("/bins", post(create_bin))
+        .route("/bins/{id}", delete(delete_bin))
+        .route("/bins/{id}/requests", get(list_bin_requests))
+        .route("/bins/{id}/requests/{request_id}/replay", post(replay_request))
+        .route("/b/{id}", any(capture_request))
+        .route("/b/{id}/{*path}", any(capture_request));
+    // 本地 sink 保存的大响应由本服务直接提供下载
+    if config.proxy.sink == SinkKind::Local {
+        app = app.nest_service("/files", ServeDir::new(&config.proxy.local.dir));
+    }
+    let app = app.layer(TraceLayer::new_for_http()).with_state(app_state);
 
     // 启动服务器
     let listener = tokio::net::TcpListener::bind(&config.server.listen_addr).await?;
     info!("curl2url service listening on http://{}", config.server.listen_addr);
-    info!("Usage: GET /curl?url=<target_url>");
+    info!("Usage: GET /curl?url=<target_url> or POST /curl with a JSON request spec, playground at /");
     info!("Large responses (>{}MB) will be uploaded to: {}", 
           config.proxy.max_response_size_bytes / (1024 * 1024),
           config.proxy.temp_file_host_url);
     
-    axum::serve(listener, app).await?;
+    // 记录对端地址, 用于判断 X-Forwarded-* 是否来自受信任的代理
+    axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>()).await?;
 
     Ok(())
 }
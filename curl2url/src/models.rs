@@ -2,18 +2,255 @@ use axum::{
     http::StatusCode,
     response::{IntoResponse, Response},
 };
-use serde::Serialize;
-use std::collections::HashMap;
+use serde::{Deserialize, Deserializer, Serialize};
+use std::{
+    collections::{BTreeMap, HashMap, VecDeque},
+    sync::{Arc, PoisonError},
+};
+use tokio::sync::{Mutex, RwLock};
 
-use crate::config::Config;
+use crate::{
+    assertions::{AssertionResult, AssertionSpec},
+    auth::{AuthSpec, CachedToken},
+    bins::RequestBin,
+    crawl::CrawlJob,
+    body::RequestBody,
+    checksum::{ChecksumSpec, Digests},
+    command::{is_sensitive_header, mask_values, MASK},
+    config::Config,
+    errors::AppError,
+    history::HistoryEntry,
+    monitor::MonitorStatus,
+    sinks::StoredFile,
+};
 
-#[derive(Debug, Clone)]
+#[derive(Debug)]
 pub struct AppState {
-    pub config: Config,
+    /// 当前生效的配置, 通过 `config()` 读取, `SIGHUP` 时整体替换
+    pub config: std::sync::RwLock<Arc<Config>>,
     pub http_client: reqwest::Client,
+    /// OAuth2 client-credentials token 缓存, key 为客户端名称
+    pub oauth2_tokens: Mutex<HashMap<String, CachedToken>>,
+    /// 定时检查的状态和历史, key 为监控项名称
+    pub monitors: RwLock<HashMap<String, MonitorStatus>>,
+    /// webhook 请求收集器, key 为收集器 id
+    pub bins: RwLock<HashMap<String, RequestBin>>,
+    /// 最近执行的请求和响应, 最新的在末尾
+    pub history: RwLock<VecDeque<HistoryEntry>>,
+    /// 后台爬取任务, key 为任务 id
+    pub crawls: RwLock<HashMap<String, CrawlJob>>,
+}
+
+impl AppState {
+    pub fn new(config: Config, http_client: reqwest::Client) -> Self {
+        Self {
+            monitors: RwLock::new(
+                config
+                    .monitors
+                    .iter()
+                    .map(|check| (check.name.clone(), MonitorStatus::new(check)))
+                    .collect(),
+            ),
+            config: std::sync::RwLock::new(Arc::new(config)),
+            http_client,
+            oauth2_tokens: Mutex::new(HashMap::new()),
+            bins: RwLock::new(HashMap::new()),
+            history: RwLock::new(VecDeque::new()),
+            crawls: RwLock::new(HashMap::new()),
+        }
+    }
+
+    /// 当前配置的快照, 同一个请求内应只取一次, 避免重新加载前后的配置混用
+    pub fn config(&self) -> Arc<Config> {
+        self.config.read().unwrap_or_else(PoisonError::into_inner).clone()
+    }
+
+    pub fn replace_config(&self, config: Config) {
+        *self.config.write().unwrap_or_else(PoisonError::into_inner) = Arc::new(config);
+    }
+}
+
+/// 一次待执行的 curl 请求, 也是 `POST /curl` 接收的 JSON 请求描述
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct CurlRequest {
+    pub url: String,
+    #[serde(default = "default_method")]
+    pub method: String,
+    #[serde(default, deserialize_with = "deserialize_headers")]
+    pub headers: Vec<(String, String)>,
+    #[serde(default)]
+    pub profile: Option<String>,
+    #[serde(default)]
+    pub auth: Option<AuthSpec>,
+    #[serde(default)]
+    pub body: Option<RequestBody>,
+    /// `host:port:addr` 形式的 DNS 覆盖, 对应 `--resolve`
+    #[serde(default)]
+    pub resolve: Vec<String>,
+    /// `host:port:connect_host:connect_port` 形式的连接目标覆盖, 对应 `--connect-to`
+    #[serde(default)]
+    pub connect_to: Vec<String>,
+    #[serde(default)]
+    pub assertions: Option<AssertionSpec>,
+    #[serde(default)]
+    pub checksum: Option<ChecksumSpec>,
+    #[serde(default)]
+    pub http_version: Option<HttpVersion>,
+    /// 返回 TLS 握手时服务器选定的 ALPN 协议, 需要额外读取 curl 的 `-v` 输出
+    #[serde(default)]
+    pub alpn: bool,
+    /// 模板中来自环境变量的值, 在 curl_command、历史记录、报告和日志中脱敏
+    #[serde(skip)]
+    pub secret_values: Vec<String>,
+}
+
+impl CurlRequest {
+    /// 用于展示和日志的 URL
+    pub fn display_url(&self) -> String {
+        mask_values(&self.url, &self.secret_values)
+    }
+
+    /// 用于展示的请求头, 敏感的请求头和敏感值都会脱敏
+    pub fn display_headers(&self) -> Vec<(&str, String)> {
+        self.headers
+            .iter()
+            .map(|(name, value)| {
+                let value = if is_sensitive_header(name) {
+                    MASK.to_string()
+                } else {
+                    mask_values(value, &self.secret_values)
+                };
+                (name.as_str(), value)
+            })
+            .collect()
+    }
+}
+
+/// 请求使用的 HTTP 版本, 未指定时由 curl 决定 (HTTPS 下通过 ALPN 协商)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HttpVersion {
+    Http10,
+    Http11,
+    /// HTTPS 下通过 ALPN 协商, 明文时发送 `Upgrade: h2c`
+    Http2,
+    /// 明文 HTTP/2, 不经过升级直接发送 HTTP/2 帧
+    Http2PriorKnowledge,
+}
+
+impl HttpVersion {
+    pub fn from_param(value: &str) -> Result<Self, AppError> {
+        match value.to_ascii_lowercase().as_str() {
+            "1.0" | "http1.0" => Ok(Self::Http10),
+            "1.1" | "http1.1" => Ok(Self::Http11),
+            "2" | "h2" | "http2" => Ok(Self::Http2),
+            "h2c" | "http2-prior-knowledge" => Ok(Self::Http2PriorKnowledge),
+            other => Err(AppError::BadRequest(format!(
+                "Unsupported http_version '{}', expected 1.0, 1.1, 2 or h2c",
+                other
+            ))),
+        }
+    }
+
+    /// 对应的 curl 选项
+    pub fn curl_flag(self) -> &'static str {
+        match self {
+            Self::Http10 => "--http1.0",
+            Self::Http11 => "--http1.1",
+            Self::Http2 => "--http2",
+            Self::Http2PriorKnowledge => "--http2-prior-knowledge",
+        }
+    }
 }
 
-#[derive(Debug, Serialize)]
+impl<'de> Deserialize<'de> for HttpVersion {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let value = String::deserialize(deserializer)?;
+        Self::from_param(&value).map_err(|e| serde::de::Error::custom(e.to_string()))
+    }
+}
+
+fn default_method() -> String {
+    "GET".to_string()
+}
+
+/// JSON 中的请求头使用对象形式 `{"name": "value"}`
+fn deserialize_headers<'de, D>(deserializer: D) -> Result<Vec<(String, String)>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Ok(BTreeMap::<String, String>::deserialize(deserializer)?
+        .into_iter()
+        .collect())
+}
+
+/// curl `--write-out '%{json}'` 输出中用到的字段
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct CurlMeta {
+    pub http_code: Option<u16>,
+    pub remote_ip: Option<String>,
+    pub url_effective: Option<String>,
+    pub num_redirects: Option<u32>,
+    /// 不跟随重定向时 `Location` 指向的绝对地址
+    pub redirect_url: Option<String>,
+    /// 收到的全部响应头 (包括重定向) 的字节数
+    pub size_header: Option<usize>,
+    /// 最后一次传输使用的 HTTP 版本, 例如 `1.1`、`2`
+    #[serde(default, deserialize_with = "deserialize_http_version")]
+    pub http_version: Option<String>,
+    pub time_namelookup: Option<f64>,
+    pub time_connect: Option<f64>,
+    pub time_appconnect: Option<f64>,
+    pub time_starttransfer: Option<f64>,
+    pub time_total: Option<f64>,
+}
+
+/// curl 将 HTTP/1.0 报告为 `1`, 没有收到响应时为 `0`
+fn deserialize_http_version<'de, D>(deserializer: D) -> Result<Option<String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Ok(match Option::<String>::deserialize(deserializer)?.as_deref() {
+        None | Some("0") => None,
+        Some("1") => Some("1.0".to_string()),
+        Some(version) => Some(version.to_string()),
+    })
+}
+
+/// 请求各阶段耗时, 单位为毫秒, 均从请求开始计算
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CurlTimings {
+    pub dns_ms: f64,
+    pub connect_ms: f64,
+    pub tls_ms: f64,
+    pub first_byte_ms: f64,
+    pub total_ms: f64,
+}
+
+impl CurlMeta {
+    pub fn timings(&self) -> Option<CurlTimings> {
+        let to_ms = |seconds: Option<f64>| seconds.unwrap_or_default() * 1000.0;
+        self.time_total.map(|total| CurlTimings {
+            dns_ms: to_ms(self.time_namelookup),
+            connect_ms: to_ms(self.time_connect),
+            tls_ms: to_ms(self.time_appconnect),
+            first_byte_ms: to_ms(self.time_starttransfer),
+            total_ms: total * 1000.0,
+        })
+    }
+}
+
+/// 跟随重定向时经过的一跳
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RedirectHop {
+    pub url: String,
+    pub status_code: Option<u16>,
+    pub location: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct CurlResponse {
     pub curl_command: String,
     pub response_body: Option<String>,
@@ -22,8 +259,37 @@ pub struct CurlResponse {
     pub error: Option<String>,
     pub redirected: bool,
     pub redirect_url: Option<String>,
+    /// 按顺序排列的重定向, 不含最终响应, 需要 `include_headers`
+    #[serde(default)]
+    pub redirects: Vec<RedirectHop>,
+    /// 响应超过大小限制并已保存到 sink (不限于 temp-file-host)
     pub uploaded_to_temp_host: bool,
     pub temp_file_url: Option<String>,
+    /// 保存到 sink 的文件信息
+    pub stored_file: Option<StoredFile>,
+    /// curl 实际连接的 IP
+    pub remote_ip: Option<String>,
+    /// 实际使用的 HTTP 版本, 例如 `1.1`、`2`
+    #[serde(default)]
+    pub http_version: Option<String>,
+    /// TLS 握手时服务端通过 ALPN 选定的协议, 明文请求或服务端未协商时为空
+    #[serde(default)]
+    pub alpn: Option<String>,
+    pub timings: Option<CurlTimings>,
+    /// 响应内容 (不含响应头) 的摘要
+    pub digests: Option<Digests>,
+    pub assertions: Option<Vec<AssertionResult>>,
+    /// 本次响应在历史记录中的 id, 可用于 `POST /curl/diff`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub history_id: Option<String>,
+}
+
+impl CurlResponse {
+    pub fn assertions_failed(&self) -> bool {
+        self.assertions
+            .as_ref()
+            .is_some_and(|results| results.iter().any(|result| !result.passed))
+    }
 }
 
 impl IntoResponse for CurlResponse {
@@ -0,0 +1,304 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::{collections::VecDeque, sync::Arc, time::Duration};
+use tracing::{error, info, warn};
+
+use crate::{
+    assertions::AssertionSpec,
+    config::MonitorCheckConfig,
+    models::{AppState, CurlRequest, CurlResponse},
+    services::execute_check,
+};
+
+/// webhook 通知的超时时间
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// 单次检查结果
+#[derive(Debug, Clone, Serialize)]
+pub struct MonitorResult {
+    pub timestamp: DateTime<Utc>,
+    pub up: bool,
+    pub status_code: Option<u16>,
+    pub latency_ms: Option<f64>,
+    pub remote_ip: Option<String>,
+    /// 未通过的检查项
+    pub failures: Vec<String>,
+}
+
+/// 监控项的当前状态和历史记录
+#[derive(Debug, Clone)]
+pub struct MonitorStatus {
+    pub name: String,
+    pub url: String,
+    pub interval_seconds: u64,
+    pub up: Option<bool>,
+    pub last_check: Option<DateTime<Utc>>,
+    pub last_change: Option<DateTime<Utc>>,
+    pub history: VecDeque<MonitorResult>,
+}
+
+#[derive(Debug, Serialize)]
+struct StateChangeEvent<'a> {
+    monitor: &'a str,
+    url: &'a str,
+    up: bool,
+    previous: Option<bool>,
+    result: &'a MonitorResult,
+}
+
+impl MonitorStatus {
+    pub fn new(check: &MonitorCheckConfig) -> Self {
+        Self {
+            name: check.name.clone(),
+            url: check.url.clone(),
+            interval_seconds: check.interval_seconds,
+            up: None,
+            last_check: None,
+            last_change: None,
+            history: VecDeque::new(),
+        }
+    }
+
+    /// 历史记录中检查成功的比例
+    pub fn uptime_ratio(&self) -> Option<f64> {
+        if self.history.is_empty() {
+            return None;
+        }
+        let up = self.history.iter().filter(|result| result.up).count();
+        Some(up as f64 / self.history.len() as f64)
+    }
+}
+
+/// 为配置中的每个监控项启动定时检查任务
+pub fn spawn_monitors(state: Arc<AppState>) {
+    for check in state.config().monitors.clone() {
+        let state = state.clone();
+        info!(
+            "Starting monitor '{}' for {} every {}s",
+            check.name, check.url, check.interval_seconds
+        );
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(Duration::from_secs(check.interval_seconds.max(1)));
+            loop {
+                interval.tick().await;
+                run_check(&state, &check).await;
+            }
+        });
+    }
+}
+
+async fn run_check(state: &Arc<AppState>, check: &MonitorCheckConfig) {
+    let request = CurlRequest {
+        url: check.url.clone(),
+        method: check.method.clone(),
+        headers: check
+            .headers
+            .iter()
+            .map(|(name, value)| (name.clone(), value.clone()))
+            .collect(),
+        profile: check.profile.clone(),
+        assertions: Some(AssertionSpec {
+            status: check.expect_status.into_iter().collect(),
+            body_contains: check.expect_body_contains.clone().into_iter().collect(),
+            max_latency_ms: check.max_latency_ms,
+            ..Default::default()
+        }),
+        ..Default::default()
+    };
+
+    // 检查不经过 VCR 和大文件转存, 上游接受连接后不响应时也会在超时后判为 down
+    let max_time = Duration::from_secs(check.timeout_seconds.unwrap_or(check.interval_seconds).max(1));
+    let result = match execute_check(state, &request, max_time).await {
+        Ok(response) => evaluate(check, &response),
+        Err(e) => MonitorResult {
+            timestamp: Utc::now(),
+            up: false,
+            status_code: None,
+            latency_ms: None,
+            remote_ip: None,
+            failures: vec![e.to_string()],
+        },
+    };
+
+    if !result.up {
+        warn!("Monitor '{}' check failed: {:?}", check.name, result.failures);
+    }
+
+    let history_size = state.config().monitor.history_size;
+    let previous = {
+        let mut monitors = state.monitors.write().await;
+        let Some(status) = monitors.get_mut(&check.name) else {
+            return;
+        };
+        let previous = status.up;
+        status.up = Some(result.up);
+        status.last_check = Some(result.timestamp);
+        if previous != Some(result.up) {
+            status.last_change = Some(result.timestamp);
+        }
+        status.history.push_back(result.clone());
+        while status.history.len() > history_size {
+            status.history.pop_front();
+        }
+        previous
+    };
+
+    if is_state_change(previous, result.up) {
+        info!("Monitor '{}' is now {}", check.name, if result.up { "up" } else { "down" });
+        // 在独立任务中通知, 缓慢的 webhook 不会推迟下一次检查
+        let (state, check) = (state.clone(), check.clone());
+        tokio::spawn(async move { notify_webhook(&state, &check, previous, &result).await });
+    }
+}
+
+/// 状态变化时通知 webhook, 首次检查成功不通知
+fn is_state_change(previous: Option<bool>, up: bool) -> bool {
+    match previous {
+        Some(previous) => previous != up,
+        None => !up,
+    }
+}
+
+/// 根据配置的期望判断检查是否通过
+fn evaluate(check: &MonitorCheckConfig, response: &CurlResponse) -> MonitorResult {
+    let mut failures = Vec::new();
+    let latency_ms = response.timings.as_ref().map(|timings| timings.total_ms);
+
+    // 传输错误由断言结果中的 `request` 一项报告
+    // 未配置期望状态码时, 任何小于 400 的状态码都视为正常
+    match response.status_code {
+        Some(actual) if check.expect_status.is_none() && actual >= 400 => {
+            failures.push(format!("unexpected status {}", actual));
+        }
+        None if response.error.is_none() => failures.push("no status code".to_string()),
+        _ => {}
+    }
+
+    for result in response.assertions.iter().flatten().filter(|result| !result.passed) {
+        failures.push(format!(
+            "{}: expected {}, got {}",
+            result.assertion,
+            result.expected,
+            result.actual.as_deref().unwrap_or("nothing")
+        ));
+    }
+
+    MonitorResult {
+        timestamp: Utc::now(),
+        up: failures.is_empty(),
+        status_code: response.status_code,
+        latency_ms,
+        remote_ip: response.remote_ip.clone(),
+        failures,
+    }
+}
+
+async fn notify_webhook(
+    state: &Arc<AppState>,
+    check: &MonitorCheckConfig,
+    previous: Option<bool>,
+    result: &MonitorResult,
+) {
+    let Some(webhook_url) = state.config().monitor.webhook_url.clone() else {
+        return;
+    };
+
+    let event = StateChangeEvent {
+        monitor: &check.name,
+        url: &check.url,
+        up: result.up,
+        previous,
+        result,
+    };
+    let request = state
+        .http_client
+        .post(webhook_url)
+        .timeout(WEBHOOK_TIMEOUT)
+        .json(&event);
+    match request.send().await {
+        Ok(response) if !response.status().is_success() => {
+            warn!("Monitor webhook returned status: {}", response.status());
+        }
+        Ok(_) => {}
+        Err(e) => error!("Failed to send monitor webhook: {}", e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assertions::AssertionResult;
+    use crate::models::CurlTimings;
+
+    fn check(expect_status: Option<u16>) -> MonitorCheckConfig {
+        MonitorCheckConfig {
+            name: "api".to_string(),
+            url: "https://example.com/health".to_string(),
+            method: "GET".to_string(),
+            interval_seconds: 30,
+            timeout_seconds: None,
+            headers: Default::default(),
+            profile: None,
+            expect_status,
+            expect_body_contains: None,
+            max_latency_ms: None,
+        }
+    }
+
+    fn response(status_code: Option<u16>) -> CurlResponse {
+        CurlResponse {
+            status_code,
+            remote_ip: Some("93.184.216.34".to_string()),
+            timings: Some(CurlTimings { total_ms: 42.0, ..Default::default() }),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn evaluate_reports_up_and_down() {
+        let result = evaluate(&check(None), &response(Some(204)));
+        assert!(result.up);
+        assert_eq!(result.latency_ms, Some(42.0));
+        assert_eq!(result.remote_ip.as_deref(), Some("93.184.216.34"));
+
+        let result = evaluate(&check(None), &response(Some(503)));
+        assert!(!result.up);
+        assert_eq!(result.failures, vec!["unexpected status 503"]);
+
+        // 配置了期望状态码时由断言判断, 不再套用 < 400 的规则
+        let mut expected = response(Some(404));
+        expected.assertions = Some(vec![AssertionResult {
+            assertion: "status".to_string(),
+            passed: true,
+            expected: "404".to_string(),
+            actual: Some("404".to_string()),
+        }]);
+        assert!(evaluate(&check(Some(404)), &expected).up);
+
+        let mut failed = response(Some(200));
+        failed.assertions = Some(vec![AssertionResult {
+            assertion: "status".to_string(),
+            passed: false,
+            expected: "404".to_string(),
+            actual: Some("200".to_string()),
+        }]);
+        let result = evaluate(&check(Some(404)), &failed);
+        assert!(!result.up);
+        assert_eq!(result.failures, vec!["status: expected 404, got 200"]);
+
+        assert_eq!(evaluate(&check(None), &response(None)).failures, vec!["no status code"]);
+    }
+
+    #[test]
+    fn state_changes_are_reported_once() {
+        // 首次检查: 成功不通知, 失败通知
+        assert!(!is_state_change(None, true));
+        assert!(is_state_change(None, false));
+        // up -> down 和 down -> up (恢复) 都通知
+        assert!(is_state_change(Some(true), false));
+        assert!(is_state_change(Some(false), true));
+        // 状态不变不通知
+        assert!(!is_state_change(Some(true), true));
+        assert!(!is_state_change(Some(false), false));
+    }
+}
@@ -0,0 +1,81 @@
+use crate::{
+    config::{Config, ProfileConfig},
+    errors::AppError,
+};
+
+/// 选择本次请求使用的 profile: 优先使用 `?profile=` 指定的名称, 否则按 host 通配匹配
+pub fn resolve_profile<'a>(
+    config: &'a Config,
+    target_url: &str,
+    name: Option<&str>,
+) -> Result<Option<&'a ProfileConfig>, AppError> {
+    if let Some(name) = name {
+        return config
+            .profiles
+            .iter()
+            .find(|profile| profile.name == name)
+            .map(Some)
+            .ok_or_else(|| AppError::BadRequest(format!("Unknown profile '{}'", name)));
+    }
+
+    let host = match url::Url::parse(target_url) {
+        Ok(parsed) => parsed.host_str().map(|host| host.to_ascii_lowercase()),
+        Err(_) => None,
+    };
+    let Some(host) = host else {
+        return Ok(None);
+    };
+
+    Ok(config.profiles.iter().find(|profile| {
+        profile
+            .hosts
+            .iter()
+            .any(|pattern| glob_match(&pattern.to_ascii_lowercase(), &host))
+    }))
+}
+
+/// 简单的通配匹配, 支持 `*` (任意长度) 和 `?` (单个字符)
+pub(crate) fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let (mut p, mut t) = (0, 0);
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while t < text.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == text[t]) {
+            p += 1;
+            t += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            backtrack = Some((p, t));
+            p += 1;
+        } else if let Some((star_p, star_t)) = backtrack {
+            p = star_p + 1;
+            t = star_t + 1;
+            backtrack = Some((star_p, star_t + 1));
+        } else {
+            return false;
+        }
+    }
+
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn glob_patterns() {
+        assert!(glob_match("*.example.com", "api.example.com"));
+        assert!(glob_match("*.example.com", "a.b.example.com"));
+        assert!(!glob_match("*.example.com", "example.com"));
+        assert!(!glob_match("*.example.com", "api.example.com.evil.net"));
+        assert!(glob_match("api-?.internal", "api-1.internal"));
+        assert!(!glob_match("api-?.internal", "api-12.internal"));
+        assert!(glob_match("*", "anything"));
+        assert!(glob_match("a*b*c", "aXXbYYc"));
+        assert!(!glob_match("a*b*c", "aXXbYY"));
+        assert!(glob_match("exact.host", "exact.host"));
+        assert!(!glob_match("exact.host", "exact.hosts"));
+    }
+}
@@ -0,0 +1,213 @@
+use anyhow::Result;
+use std::{collections::HashMap, sync::Arc, time::Instant};
+use tokio::{sync::Semaphore, task::JoinSet};
+use tracing::info;
+
+use crate::{
+    checksum::{DigestHasher, Digests},
+    command::CurlCommand,
+    config::SegmentedDownloadConfig,
+    models::{CurlMeta, CurlTimings, RedirectHop},
+    utils::{last_header_block, parse_curl_response, redirect_hops, split_curl_output},
+};
+
+/// 分段下载并按顺序拼接后的完整响应
+pub struct SegmentedResponse {
+    pub data: Vec<u8>,
+    /// 各段按顺序到齐时即计入的摘要
+    pub digests: Digests,
+    pub headers: HashMap<String, String>,
+    /// 探测请求经过的重定向
+    pub redirects: Vec<RedirectHop>,
+    /// 探测请求的元数据 (实际连接的 IP、重定向等)
+    pub meta: CurlMeta,
+    pub timings: CurlTimings,
+    pub segments: usize,
+}
+
+/// 上游支持 Range 请求时分段并行下载, 不支持或文件不足两段时返回 `None`
+///
+/// `base` 为不限制大小、不带 `-i` 的 curl 命令
+pub async fn fetch(
+    config: &SegmentedDownloadConfig,
+    base: &CurlCommand,
+    url: &str,
+) -> Result<Option<SegmentedResponse>> {
+    if !config.enabled || config.segment_size_bytes == 0 {
+        return Ok(None);
+    }
+    let started = Instant::now();
+
+    // 用 0-0 的 Range 请求探测: 206 且 Content-Range 带总长度时才分段
+    // 上游忽略 Range 时会返回完整内容, 用 --max-filesize 让 curl 立即放弃
+    let header_dump = tempfile::NamedTempFile::new()?;
+    let probe_body = tempfile::NamedTempFile::new()?;
+    let mut probe = base.clone();
+    probe
+        .hidden_arg("-r")
+        .hidden_arg("0-0")
+        .hidden_arg("--max-filesize")
+        .hidden_arg("1")
+        .hidden_arg("-D")
+        .hidden_arg(header_dump.path().to_string_lossy())
+        .hidden_arg("-o")
+        .hidden_arg(probe_body.path().to_string_lossy());
+    let output = probe.output().await?;
+    let (_, meta) = split_curl_output(&output.stdout);
+    let mut meta = meta.unwrap_or_default();
+    if !output.status.success() || meta.http_code != Some(206) {
+        return Ok(None);
+    }
+
+    let header_text = String::from_utf8_lossy(&tokio::fs::read(header_dump.path()).await?).to_string();
+    let (mut headers, _) = parse_curl_response(last_header_block(&header_text));
+    let redirects = redirect_hops(&header_text, url);
+    let Some(total) = headers.get("content-range").and_then(|value| content_range_total(value)) else {
+        return Ok(None);
+    };
+    if total <= config.segment_size_bytes {
+        return Ok(None);
+    }
+    // 总长度来自上游, 超过上限时不按它预分配内存
+    if total > config.max_total_bytes {
+        info!(
+            "Content-Range total {} exceeds segmented max_total_bytes ({}), not splitting",
+            total, config.max_total_bytes
+        );
+        return Ok(None);
+    }
+    // 各段带上 If-Range, 上游文件在下载期间变化时返回 200 而不是拼出混合内容
+    let validator = headers
+        .get("etag")
+        .filter(|etag| !etag.starts_with("W/"))
+        .or_else(|| headers.get("last-modified"))
+        .cloned();
+
+    let ranges: Vec<(u64, u64)> = (0..total)
+        .step_by(config.segment_size_bytes as usize)
+        .map(|start| (start, (start + config.segment_size_bytes).min(total) - 1))
+        .collect();
+    info!(
+        "Downloading {} bytes in {} segments ({} in parallel)",
+        total,
+        ranges.len(),
+        config.max_parallel
+    );
+
+    let semaphore = Arc::new(Semaphore::new(config.max_parallel.max(1)));
+    let mut tasks = JoinSet::new();
+    for (index, (start, end)) in ranges.iter().copied().enumerate() {
+        let semaphore = semaphore.clone();
+        let mut command = base.clone();
+        if let Some(validator) = &validator {
+            command.hidden_arg("-H").hidden_arg(format!("If-Range: {}", validator));
+        }
+        tasks.spawn(async move {
+            let _permit = semaphore.acquire_owned().await?;
+            let segment = tempfile::NamedTempFile::new()?;
+            command
+                .hidden_arg("-r")
+                .hidden_arg(format!("{}-{}", start, end))
+                .hidden_arg("--max-filesize")
+                .hidden_arg((end - start + 1).to_string())
+                .hidden_arg("-o")
+                .hidden_arg(segment.path().to_string_lossy());
+            let output = command.output().await?;
+            let (_, meta) = split_curl_output(&output.stdout);
+            let http_code = meta.and_then(|meta| meta.http_code);
+            if !output.status.success() || http_code != Some(206) {
+                return Err(anyhow::anyhow!(
+                    "Segment {}-{} failed (exit {:?}, status {:?})",
+                    start,
+                    end,
+                    output.status.code(),
+                    http_code
+                ));
+            }
+
+            let data = tokio::fs::read(segment.path()).await?;
+            if data.len() as u64 != end - start + 1 {
+                return Err(anyhow::anyhow!(
+                    "Segment {}-{} returned {} bytes, expected {}",
+                    start,
+                    end,
+                    data.len(),
+                    end - start + 1
+                ));
+            }
+            Ok::<_, anyhow::Error>((index, data))
+        });
+    }
+
+    // 按顺序拼接并计算摘要: 前面的段都已完成时立即计入, 不等全部下载结束
+    let mut segments: Vec<Option<Vec<u8>>> = vec![None; ranges.len()];
+    let mut next = 0;
+    let mut data = Vec::with_capacity(total as usize);
+    let mut hasher = DigestHasher::default();
+    while let Some(result) = tasks.join_next().await {
+        let (index, segment) = result??;
+        segments[index] = Some(segment);
+        while let Some(segment) = segments.get_mut(next).and_then(Option::take) {
+            hasher.update(&segment);
+            data.extend(segment);
+            next += 1;
+        }
+    }
+    if next != segments.len() {
+        return Err(anyhow::anyhow!("Missing segment"));
+    }
+    // 与 Content-Range 中的总长度核对
+    if data.len() as u64 != total {
+        return Err(anyhow::anyhow!(
+            "Joined segments are {} bytes, Content-Length is {}",
+            data.len(),
+            total
+        ));
+    }
+
+    // 对外呈现为一次完整的 200 响应, 元数据中的 206 也一并改写
+    headers.remove("content-range");
+    headers.remove("status");
+    headers.insert("content-length".to_string(), total.to_string());
+    meta.http_code = Some(200);
+    let mut timings = meta.timings().unwrap_or_default();
+    timings.total_ms = started.elapsed().as_secs_f64() * 1000.0;
+
+    Ok(Some(SegmentedResponse {
+        data,
+        digests: hasher.finish(),
+        headers,
+        redirects,
+        meta,
+        timings,
+        segments: ranges.len(),
+    }))
+}
+
+/// 从 `bytes 0-0/12345` 中取总长度
+fn content_range_total(value: &str) -> Option<u64> {
+    let (unit, range) = value.trim().split_once(' ')?;
+    if !unit.eq_ignore_ascii_case("bytes") {
+        return None;
+    }
+    range.rsplit_once('/')?.1.trim().parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn content_range_total_reads_the_complete_length() {
+        assert_eq!(content_range_total("bytes 0-0/12345"), Some(12345));
+        assert_eq!(content_range_total(" Bytes 0-0/ 42 "), Some(42));
+    }
+
+    #[test]
+    fn content_range_total_rejects_unknown_lengths_and_units() {
+        assert_eq!(content_range_total("bytes 0-0/*"), None);
+        assert_eq!(content_range_total("items 0-0/10"), None);
+        assert_eq!(content_range_total("bytes"), None);
+        assert_eq!(content_range_total("bytes 0-0/-1"), None);
+    }
+}
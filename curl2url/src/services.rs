@@ -1,228 +1,973 @@
-use anyhow::Result;
-use axum::{
-    http::HeaderMap,
-    response::{IntoResponse, Response},
-};
-use std::{collections::HashMap, process::Command, sync::Arc};
+use std::{borrow::Cow, collections::HashMap, sync::Arc, time::Duration};
+use tokio::process::Command;
+use tokio::io::AsyncReadExt;
 use tracing::{error, info, warn};
 
 use crate::{
+    auth::auth_args,
+    body::{prepare_body, PreparedBody},
+    checksum::{expected_checksums, DigestHasher, Digests, ExpectedChecksum},
+    command::{mask_url_credentials, mask_values, CurlCommand},
+    config::{Config, CurlConfig, ProfileConfig, VcrMode},
     errors::AppError,
-    models::{AppState, CurlResponse},
-    utils::parse_curl_response,
+    models::{AppState, CurlMeta, CurlRequest, CurlResponse, RedirectHop},
+    profiles::resolve_profile,
+    segmented,
+    sinks,
+    ssrf::{check_target, parse_connect_to, parse_resolve, ConnectToEntry, PinnedTarget, ResolveEntry},
+    utils::{
+        alpn_from_verbose, filename_from_content_disposition, filename_from_url, last_header_block,
+        parse_curl_response, redirect_hops, split_curl_output, strip_verbose, META_MARKER,
+    },
+    vcr,
 };
 
+/// curl 在响应超过 `--max-filesize` 时的退出码
+const CURL_FILESIZE_EXCEEDED: i32 = 63;
+
+/// 未设置 `max_redirects` 时最多跟随的重定向次数, 与 curl 默认值一致
+const DEFAULT_MAX_REDIRECTS: u32 = 50;
+
+/// 解析 profile、认证和请求体之后的请求
+struct PreparedRequest<'a> {
+    /// 由 curl2url 跟随重定向时为下一跳的请求
+    request: Cow<'a, CurlRequest>,
+    /// 准备请求时的配置快照, 执行过程中统一使用
+    config: Arc<Config>,
+    profile: Option<ProfileConfig>,
+    auth: Option<CurlCommand>,
+    body: Option<PreparedBody>,
+    resolve: Vec<String>,
+    connect_to: Vec<String>,
+    pinned: Option<PinnedTarget>,
+    limits: RunLimits,
+}
+
+/// 单次执行的限制, 普通请求使用默认值, 监控检查需要更严格的限制
+#[derive(Debug, Clone, Copy)]
+struct RunLimits {
+    /// 整个请求的超时, 对应 `--max-time`
+    max_time: Option<Duration>,
+    /// 超过大小限制时是否转存到 sink, 为 false 时返回错误
+    offload: bool,
+}
+
+impl Default for RunLimits {
+    fn default() -> Self {
+        Self {
+            max_time: None,
+            offload: true,
+        }
+    }
+}
+
+/// 合并请求和 profile 中的 `resolve`/`connect_to`
+pub(crate) struct ConnectionOverrides {
+    pub resolve: Vec<String>,
+    pub resolve_entries: Vec<ResolveEntry>,
+    pub connect_to: Vec<String>,
+    pub connect_to_entries: Vec<ConnectToEntry>,
+}
+
+pub(crate) fn connection_overrides(
+    request: &CurlRequest,
+    profile: Option<&ProfileConfig>,
+) -> Result<ConnectionOverrides, AppError> {
+    // 请求中的 DNS 覆盖优先, profile 中相同目标的覆盖会被忽略
+    let mut resolve = request.resolve.clone();
+    let mut resolve_entries = resolve
+        .iter()
+        .map(|entry| parse_resolve(entry))
+        .collect::<Result<Vec<_>, _>>()?;
+    for raw in profile.map(|profile| profile.resolve.as_slice()).unwrap_or_default() {
+        let entry = parse_resolve(raw)?;
+        if !resolve_entries
+            .iter()
+            .any(|existing| existing.host == entry.host && existing.port == entry.port)
+        {
+            resolve.push(raw.clone());
+            resolve_entries.push(entry);
+        }
+    }
+
+    let mut connect_to = request.connect_to.clone();
+    connect_to.extend(profile.map(|profile| profile.connect_to.clone()).unwrap_or_default());
+    let connect_to_entries = connect_to
+        .iter()
+        .map(|entry| parse_connect_to(entry))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(ConnectionOverrides {
+        resolve,
+        resolve_entries,
+        connect_to,
+        connect_to_entries,
+    })
+}
+
+async fn prepare_request<'a>(
+    state: &'a Arc<AppState>,
+    request: &'a CurlRequest,
+) -> Result<PreparedRequest<'a>, AppError> {
+    let config = state.config();
+    let profile = resolve_profile(&config, &request.url, request.profile.as_deref())?.cloned();
+    if let Some(profile) = &profile {
+        info!("Using request profile '{}' for {}", profile.name, request.display_url());
+    }
+
+    // 请求中指定的认证方式优先于 profile 中的配置
+    let auth = match request.auth.as_ref().or(profile.as_ref().and_then(|profile| profile.auth.as_ref())) {
+        Some(spec) => Some(auth_args(state, spec).await?),
+        None => None,
+    };
+
+    let ConnectionOverrides {
+        resolve,
+        resolve_entries,
+        connect_to,
+        connect_to_entries,
+    } = connection_overrides(request, profile.as_ref())?;
+
+    // 内网地址检查作用于覆盖之后实际连接的地址
+    let pinned = check_target(&config.security, &request.url, &resolve_entries, &connect_to_entries).await?;
+
+    let body = match &request.body {
+        Some(body) => Some(prepare_body(state, body).await?),
+        None => None,
+    };
+
+    Ok(PreparedRequest {
+        request: Cow::Borrowed(request),
+        config,
+        profile,
+        auth,
+        body,
+        resolve,
+        connect_to,
+        pinned,
+        limits: RunLimits::default(),
+    })
+}
+
+impl PreparedRequest<'_> {
+    fn curl_config(&self) -> CurlConfig {
+        match &self.profile {
+            Some(profile) => self.config.curl.merge(profile),
+            None => self.config.curl.clone(),
+        }
+    }
+
+    /// 开启内网地址限制时不使用 `-L`, 由 curl2url 逐跳检查 `Location` 的目标后重新发起请求
+    fn follows_redirects_manually(&self) -> bool {
+        self.config.security.block_private_networks && self.curl_config().follow_redirects
+    }
+
+    fn max_redirects(&self) -> u32 {
+        self.curl_config().max_redirects.unwrap_or(DEFAULT_MAX_REDIRECTS)
+    }
+
+    /// 按 curl `-L` 的规则生成下一跳: 301/302 的 POST 和 303 改为 GET 并去掉请求体,
+    /// 换到其他 host 时不再发送认证信息、Cookie 和自定义 Host; 新目标同样经过内网地址检查
+    async fn follow_redirect(mut self, status_code: u16, location: &str) -> Result<Self, AppError> {
+        let current = url::Url::parse(&self.request.url)
+            .map_err(|e| AppError::BadRequest(format!("Invalid target URL: {}", e)))?;
+        let next = current
+            .join(location)
+            .map_err(|e| AppError::BadGateway(format!("Invalid redirect location '{}': {}", location, e)))?;
+        if !matches!(next.scheme(), "http" | "https") {
+            return Err(AppError::Forbidden(format!("Refusing to follow redirect to '{}'", next)));
+        }
+
+        let mut request = self.request.into_owned();
+        let method = request.method.to_ascii_uppercase();
+        if (status_code == 303 && method != "HEAD") || (matches!(status_code, 301 | 302) && method == "POST") {
+            request.method = "GET".to_string();
+            request.body = None;
+            self.body = None;
+        }
+        let same_host = current.host_str() == next.host_str()
+            && current.port_or_known_default() == next.port_or_known_default();
+        if !same_host {
+            let host_bound = |name: &str| {
+                ["authorization", "cookie", "host"]
+                    .iter()
+                    .any(|header| name.eq_ignore_ascii_case(header))
+            };
+            request.headers.retain(|(name, _)| !host_bound(name));
+            if let Some(profile) = &mut self.profile {
+                profile.headers.retain(|name, _| !host_bound(name));
+            }
+            self.auth = None;
+        }
+        request.url = next.to_string();
+        info!("Following redirect ({}) to {}", status_code, request.display_url());
+
+        let ConnectionOverrides {
+            resolve_entries,
+            connect_to_entries,
+            ..
+        } = connection_overrides(&request, self.profile.as_ref())?;
+        self.pinned = check_target(&self.config.security, &request.url, &resolve_entries, &connect_to_entries).await?;
+        self.request = Cow::Owned(request);
+        Ok(self)
+    }
+}
+
+/// 超过重定向次数时与 curl 相同的错误信息
+fn too_many_redirects(max_redirects: u32) -> String {
+    format!("Maximum ({}) redirects followed", max_redirects)
+}
+
+/// 需要由 curl2url 跟随的重定向, 返回下一跳的绝对地址
+fn manual_redirect(prepared: &PreparedRequest, status_code: Option<u16>, location: Option<&String>) -> Option<(u16, String)> {
+    match (status_code, location) {
+        (Some(status_code @ 300..=399), Some(location)) if prepared.follows_redirects_manually() => {
+            Some((status_code, location.clone()))
+        }
+        _ => None,
+    }
+}
+
 pub async fn execute_curl_command(
     state: &Arc<AppState>,
-    target_url: &str,
-    headers: &HeaderMap,
-    method: &str,
-) -> Result<Response, AppError> {
-    // 构建curl命令
-    let mut curl_command = vec!["curl".to_string()];
-    
-    // 添加方法
-    if method != "GET" {
-        curl_command.push("-X".to_string());
-        curl_command.push(method.to_string());
+    request: &CurlRequest,
+) -> Result<CurlResponse, AppError> {
+    if let Some(assertions) = &request.assertions {
+        assertions.validate()?;
     }
-
-    // 添加头部
-    for (name, value) in headers.iter() {
-        if let Ok(value_str) = value.to_str() {
-            curl_command.push("-H".to_string());
-            curl_command.push(format!("{}: {}", name.as_str(), value_str));
+    let config = state.config();
+    let vcr_config = &config.vcr;
+    let mut response = match vcr_config.mode {
+        VcrMode::Replay => vcr::replay(vcr_config, request).await?,
+        VcrMode::Record => {
+            let response = run_curl_command(state, request, RunLimits::default()).await?;
+            vcr::record(vcr_config, request, &response).await?;
+            response
         }
+        VcrMode::Off => run_curl_command(state, request, RunLimits::default()).await?,
+    };
+
+    // 执行完成后对响应进行断言
+    if let Some(assertions) = &request.assertions {
+        response.assertions = Some(assertions.evaluate(&response)?);
     }
 
-    // 添加其他curl选项
-    if state.config.curl.include_headers {
-        curl_command.push("-i".to_string()); // 包含响应头
+    Ok(response)
+}
+
+/// 监控检查: 始终访问上游 (不经过 VCR), 整个请求受 `max_time` 限制, 超过大小限制时返回错误而不转存
+pub async fn execute_check(
+    state: &Arc<AppState>,
+    request: &CurlRequest,
+    max_time: Duration,
+) -> Result<CurlResponse, AppError> {
+    if let Some(assertions) = &request.assertions {
+        assertions.validate()?;
+    }
+    let limits = RunLimits {
+        max_time: Some(max_time),
+        offload: false,
+    };
+    let mut response = run_curl_command(state, request, limits).await?;
+    if let Some(assertions) = &request.assertions {
+        response.assertions = Some(assertions.evaluate(&response)?);
     }
-    curl_command.push("-s".to_string()); // 静默模式
-    if state.config.curl.follow_redirects {
-        curl_command.push("-L".to_string()); // 跟随重定向
+    Ok(response)
+}
+
+/// 执行请求并跟随重定向, 返回的重定向地址中的 secret 值已脱敏
+async fn run_curl_command(
+    state: &Arc<AppState>,
+    request: &CurlRequest,
+    limits: RunLimits,
+) -> Result<CurlResponse, AppError> {
+    let mut response = follow_redirects(state, request, limits).await?;
+    let mask = |url: &str| mask_values(url, &request.secret_values);
+    response.redirect_url = response.redirect_url.as_deref().map(mask);
+    for hop in &mut response.redirects {
+        hop.url = mask(&hop.url);
+        hop.location = hop.location.as_deref().map(mask);
     }
-    curl_command.push("--connect-timeout".to_string());
-    curl_command.push(state.config.curl.timeout_seconds.to_string());
-    curl_command.push("--max-filesize".to_string());
-    curl_command.push(state.config.proxy.max_response_size_bytes.to_string());
+    Ok(response)
+}
 
-    // 添加目标URL
-    curl_command.push(target_url.to_string());
+async fn follow_redirects(
+    state: &Arc<AppState>,
+    request: &CurlRequest,
+    limits: RunLimits,
+) -> Result<CurlResponse, AppError> {
+    let mut prepared = prepare_request(state, request).await?;
+    prepared.limits = limits;
+    let expected_checksums = match &request.checksum {
+        Some(spec) => expected_checksums(state, spec, &request.url).await?,
+        None => Vec::new(),
+    };
 
-    let curl_command_str = curl_command.join(" ");
+    // 返回的 curl_command 始终是第一跳的命令
+    let curl_command_str = build_curl_command(&prepared, true).to_display_string();
+    let mut hops = Vec::new();
+    loop {
+        let (status_code, location) = match run_prepared(state, &prepared, &expected_checksums).await? {
+            Attempt::Done(response) => {
+                let mut response = *response;
+                if !hops.is_empty() {
+                    hops.append(&mut response.redirects);
+                    response.redirects = hops;
+                    response.redirected = true;
+                    response.redirect_url = Some(prepared.request.url.to_string());
+                    response.curl_command = curl_command_str;
+                }
+                return Ok(response);
+            }
+            Attempt::Redirect(status_code, location) => (status_code, location),
+        };
+        if hops.len() as u32 >= prepared.max_redirects() {
+            return Ok(CurlResponse {
+                curl_command: curl_command_str,
+                error: Some(too_many_redirects(prepared.max_redirects())),
+                status_code: Some(status_code),
+                redirects: hops,
+                ..Default::default()
+            });
+        }
+        hops.push(RedirectHop {
+            url: prepared.request.url.clone(),
+            status_code: Some(status_code),
+            location: Some(location.clone()),
+        });
+        prepared = prepared.follow_redirect(status_code, &location).await?;
+    }
+}
+
+/// 单次 curl 执行的结果
+enum Attempt {
+    Done(Box<CurlResponse>),
+    /// 需要由 curl2url 跟随的重定向: 状态码和下一跳地址
+    Redirect(u16, String),
+}
+
+async fn run_prepared(
+    state: &Arc<AppState>,
+    prepared: &PreparedRequest<'_>,
+    expected_checksums: &[ExpectedChecksum],
+) -> Result<Attempt, AppError> {
+    let request = prepared.request.as_ref();
+
+    // 构建curl命令, 请求 ALPN 时才加上 `-v`, 其输出只用于读取协商结果
+    let mut curl_command = build_curl_command(prepared, true);
+    let curl_command_str = curl_command.to_display_string();
+    if request.alpn {
+        curl_command.hidden_arg("-v");
+    }
     info!("Executing curl command: {}", curl_command_str);
 
     // 执行curl命令
-    let output = Command::new("curl")
-        .args(&curl_command[1..]) // 去掉第一个"curl"
+    let output = curl_command
         .output()
+        .await
         .map_err(|e| AppError::InternalServerError(format!("Failed to execute curl: {}", e)))?;
 
-    let stdout = String::from_utf8_lossy(&output.stdout);
-    let stderr = String::from_utf8_lossy(&output.stderr);
+    let (stdout_bytes, meta) = split_curl_output(&output.stdout);
+    let meta = meta.unwrap_or_default();
+    let stdout = String::from_utf8_lossy(stdout_bytes);
+    let verbose = String::from_utf8_lossy(&output.stderr);
+    // `-v` 输出包含请求头 (可能有认证信息), 不能出现在错误信息和日志中
+    let stderr = strip_verbose(&verbose);
+    let alpn = alpn_from_verbose(&verbose);
 
     if !output.status.success() {
         warn!("Curl command failed: {}", stderr);
-        
-        // 检查是否是文件大小超限
-        if stderr.contains("Maximum file size exceeded") {
-            // 使用无大小限制的curl重新获取并上传到temp-file-host
-            return upload_large_response(state, target_url, &curl_command_str, headers, method).await;
+
+        // 检查是否是文件大小超限, `-s` 模式下 curl 不输出错误信息, 需要同时判断退出码
+        let too_large = output.status.code() == Some(CURL_FILESIZE_EXCEEDED) || stderr.contains("Maximum file size exceeded");
+        if too_large && prepared.limits.offload {
+            // 使用无大小限制的curl重新获取并保存到 sink
+            return upload_large_response(state, prepared, &curl_command_str, expected_checksums)
+                .await
+                .map(|response| Attempt::Done(Box::new(response)));
         }
 
-        return Ok(CurlResponse {
+        return Ok(Attempt::Done(Box::new(CurlResponse {
             curl_command: curl_command_str,
-            response_body: None,
-            response_headers: None,
-            status_code: None,
-            error: Some(stderr.to_string()),
-            redirected: false,
-            redirect_url: None,
-            uploaded_to_temp_host: false,
-            temp_file_url: None,
-        }
-        .into_response());
+            error: Some(if too_large {
+                format!(
+                    "Response exceeds max_response_size_bytes ({})",
+                    prepared.config.proxy.max_response_size_bytes
+                )
+            } else if stderr.trim().is_empty() {
+                // `-s` 模式下超时等错误没有输出, 只能给出退出码
+                format!("curl exited with code {}", output.status.code().unwrap_or(-1))
+            } else {
+                stderr
+            }),
+            timings: meta.timings(),
+            remote_ip: meta.remote_ip,
+            http_version: meta.http_version,
+            alpn,
+            ..Default::default()
+        })));
+    }
+
+    if let Some((status_code, location)) = manual_redirect(prepared, meta.http_code, meta.redirect_url.as_ref()) {
+        return Ok(Attempt::Redirect(status_code, location));
     }
 
-    // 解析响应
-    let (response_headers, response_body) = if state.config.curl.include_headers {
-        parse_curl_response(&stdout)
+    // 使用 `-i` 时输出以全部响应头 (包括每次重定向) 开头, 长度由 size_header 给出
+    let header_len = if prepared.config.curl.include_headers {
+        meta.size_header.unwrap_or(0).min(stdout_bytes.len())
     } else {
-        (HashMap::new(), stdout.to_string())
+        0
     };
+    let (header_bytes, body_bytes) = stdout_bytes.split_at(header_len);
+
+    // 摘要基于原始字节计算
+    let digests = Digests::compute(body_bytes);
+    digests.verify(expected_checksums)?;
 
-    Ok(CurlResponse {
+    // 解析响应, 响应头取最后一段
+    let (response_headers, response_body, redirects) = if !prepared.config.curl.include_headers {
+        (HashMap::new(), stdout.to_string(), Vec::new())
+    } else if meta.size_header.is_some() {
+        let header_text = String::from_utf8_lossy(header_bytes);
+        let (response_headers, _) = parse_curl_response(last_header_block(&header_text));
+        (
+            response_headers,
+            String::from_utf8_lossy(body_bytes).trim_end().to_string(),
+            redirect_hops(&header_text, &request.url),
+        )
+    } else {
+        let (response_headers, response_body) = parse_curl_response(&stdout);
+        (response_headers, response_body, Vec::new())
+    };
+
+    let redirected = meta.num_redirects.unwrap_or(0) > 0;
+    Ok(Attempt::Done(Box::new(CurlResponse {
         curl_command: curl_command_str,
         response_body: Some(response_body),
         response_headers: Some(response_headers),
-        status_code: None, // 可以从headers中解析
-        error: None,
-        redirected: false,
-        redirect_url: None,
-        uploaded_to_temp_host: false,
-        temp_file_url: None,
+        status_code: meta.http_code,
+        redirected,
+        redirect_url: if redirected { meta.url_effective.clone() } else { None },
+        redirects,
+        timings: meta.timings(),
+        remote_ip: meta.remote_ip,
+        http_version: meta.http_version,
+        alpn,
+        digests: Some(digests),
+        ..Default::default()
+    })))
+}
+
+/// 保持字节形式的原始响应, 供 `/browse` 原样转发
+pub struct RawResponse {
+    pub status_code: Option<u16>,
+    pub headers: HashMap<String, String>,
+    pub body: Vec<u8>,
+    /// 跟随重定向之后的地址
+    pub final_url: String,
+}
+
+/// 按与 `/curl` 相同的 profile、认证和内网地址策略获取原始响应, 超过大小限制时返回 `None`
+pub async fn fetch_raw(state: &Arc<AppState>, request: &CurlRequest) -> Result<Option<RawResponse>, AppError> {
+    let prepared = prepare_request(state, request).await?;
+    Ok(fetch_raw_hops(prepared).await?.1)
+}
+
+/// `fetch_raw_or_store` 的结果
+pub enum RawFetch {
+    Raw(RawResponse),
+    /// 超过大小限制, 已保存到 sink
+    Stored(Box<CurlResponse>),
+}
+
+/// 与 `fetch_raw` 相同, 但超过大小限制时直接将最后一跳保存到 sink, 不再从第一跳重新请求
+pub async fn fetch_raw_or_store(state: &Arc<AppState>, request: &CurlRequest) -> Result<RawFetch, AppError> {
+    let prepared = prepare_request(state, request).await?;
+    let (prepared, raw) = fetch_raw_hops(prepared).await?;
+    if let Some(raw) = raw {
+        return Ok(RawFetch::Raw(raw));
+    }
+    let curl_command = build_curl_command(&prepared, true).to_display_string();
+    let response = upload_large_response(state, &prepared, &curl_command, &[]).await?;
+    Ok(RawFetch::Stored(Box::new(response)))
+}
+
+/// 逐跳获取原始响应, 同时返回最后一跳的请求
+async fn fetch_raw_hops(
+    mut prepared: PreparedRequest<'_>,
+) -> Result<(PreparedRequest<'_>, Option<RawResponse>), AppError> {
+    for _ in 0..=prepared.max_redirects() {
+        let raw = fetch_raw_prepared(&prepared).await?;
+        let next = raw
+            .as_ref()
+            .and_then(|raw| manual_redirect(&prepared, raw.status_code, raw.headers.get("location")));
+        match next {
+            Some((status_code, location)) => prepared = prepared.follow_redirect(status_code, &location).await?,
+            None => return Ok((prepared, raw)),
+        }
+    }
+    let request = prepared.request.as_ref();
+    Err(AppError::BadGateway(format!(
+        "Failed to fetch {}: {}",
+        request.display_url(),
+        too_many_redirects(prepared.max_redirects())
+    )))
+}
+
+async fn fetch_raw_prepared(prepared: &PreparedRequest<'_>) -> Result<Option<RawResponse>, AppError> {
+    let request = prepared.request.as_ref();
+
+    // 响应头单独写入临时文件, 响应内容不经过文本解析
+    let header_dump = tempfile::NamedTempFile::new()
+        .map_err(|e| AppError::InternalServerError(format!("Failed to create header file: {}", e)))?;
+    let mut curl_command = build_curl_command(prepared, false);
+    curl_command
+        .hidden_arg("--max-filesize")
+        .hidden_arg(prepared.config.proxy.max_response_size_bytes.to_string())
+        .hidden_arg("-D")
+        .hidden_arg(header_dump.path().to_string_lossy());
+    info!("Executing curl command: {}", curl_command.to_display_string());
+
+    let output = curl_command
+        .output()
+        .await
+        .map_err(|e| AppError::InternalServerError(format!("Failed to execute curl: {}", e)))?;
+    if output.status.code() == Some(CURL_FILESIZE_EXCEEDED) {
+        return Ok(None);
+    }
+    if !output.status.success() {
+        return Err(AppError::BadGateway(format!(
+            "Failed to fetch {}: {}",
+            request.display_url(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
     }
-    .into_response())
+
+    let (body, meta) = split_curl_output(&output.stdout);
+    let meta = meta.unwrap_or_default();
+    let header_text = tokio::fs::read(header_dump.path())
+        .await
+        .map(|dump| String::from_utf8_lossy(&dump).to_string())
+        .unwrap_or_default();
+    let (headers, _) = parse_curl_response(last_header_block(&header_text));
+
+    Ok(Some(RawResponse {
+        status_code: meta.http_code,
+        headers,
+        body: body.to_vec(),
+        final_url: meta.url_effective.unwrap_or_else(|| request.url.clone()),
+    }))
+}
+
+/// 已读取最终响应头、响应内容仍在传输中的 curl 进程
+pub struct CurlStream {
+    pub curl_command: String,
+    pub status_code: Option<u16>,
+    pub headers: HashMap<String, String>,
+    /// 与响应头一起读到的响应内容开头
+    pub initial: Vec<u8>,
+    pub stdout: tokio::process::ChildStdout,
+    /// drop 时结束 curl 进程
+    pub child: tokio::process::Child,
 }
 
-pub async fn upload_large_response(
+/// 响应头的大小上限, 超出时视为上游异常
+const MAX_STREAM_HEADER_BYTES: usize = 64 * 1024;
+
+/// 启动不限制大小的 curl 进程, 读取到最终响应头 (跳过 1xx 和跟随的重定向) 后返回, 供流式输出使用
+pub async fn spawn_curl_stream(
     state: &Arc<AppState>,
-    target_url: &str,
-    curl_command: &str,
-    headers: &HeaderMap,
-    method: &str,
-) -> Result<Response, AppError> {
-    info!("Response too large, fetching and uploading to temp-file-host");
+    request: &CurlRequest,
+    header_timeout: std::time::Duration,
+) -> Result<CurlStream, AppError> {
+    // multipart 临时文件在收到响应头时已上传完毕, 随 prepared 一起释放
+    let mut prepared = prepare_request(state, request).await?;
+    let curl_command_str = build_curl_command(&prepared, false).to_display_string();
+    for _ in 0..=prepared.max_redirects() {
+        let stream = start_curl_stream(&prepared, &curl_command_str, header_timeout).await?;
+        match manual_redirect(&prepared, stream.status_code, stream.headers.get("location")) {
+            // 丢弃重定向响应, curl 进程随 stream 结束
+            Some((status_code, location)) => prepared = prepared.follow_redirect(status_code, &location).await?,
+            None => return Ok(stream),
+        }
+    }
+    Err(AppError::BadGateway(format!(
+        "Failed to fetch {}: {}",
+        request.display_url(),
+        too_many_redirects(prepared.max_redirects())
+    )))
+}
 
-    // 构建无大小限制的curl命令
-    let mut unlimited_curl_command = vec!["curl".to_string()];
-    
-    if method != "GET" {
-        unlimited_curl_command.push("-X".to_string());
-        unlimited_curl_command.push(method.to_string());
+async fn start_curl_stream(
+    prepared: &PreparedRequest<'_>,
+    curl_command_str: &str,
+    header_timeout: std::time::Duration,
+) -> Result<CurlStream, AppError> {
+    let request = prepared.request.as_ref();
+    // 由 curl2url 跟随重定向时, 3xx 响应返回给调用方处理
+    let follow_redirects = prepared.curl_config().follow_redirects && !prepared.follows_redirects_manually();
+
+    let mut curl_command = build_curl_command(prepared, false);
+    // 响应头和内容都写到 stdout, 不缓冲; 后出现的 -w 覆盖末尾的元数据输出
+    curl_command
+        .hidden_arg("-i")
+        .hidden_arg("-N")
+        .hidden_arg("--suppress-connect-headers")
+        .hidden_arg("-w")
+        .hidden_arg("");
+    info!("Streaming curl command: {}", curl_command_str);
+
+    let mut process = Command::new("curl");
+    process
+        .stdout(std::process::Stdio::piped())
+        // 流式请求不读取 stderr, 避免管道写满后阻塞 curl
+        .stderr(std::process::Stdio::null())
+        .kill_on_drop(true);
+    let mut child = curl_command
+        .spawn(&mut process)
+        .await
+        .map_err(|e| AppError::InternalServerError(format!("Failed to execute curl: {}", e)))?;
+    let mut stdout = child
+        .stdout
+        .take()
+        .ok_or_else(|| AppError::InternalServerError("Failed to capture curl output".to_string()))?;
+
+    let mut buffer = Vec::new();
+    let mut chunk = [0u8; 8192];
+    loop {
+        if let Some((end, separator)) = header_end(&buffer) {
+            let block = String::from_utf8_lossy(&buffer[..end]).to_string();
+            let rest = buffer.split_off(end + separator);
+            let (headers, _) = parse_curl_response(&block);
+            let status_code = headers
+                .get("status")
+                .and_then(|line| line.split_whitespace().nth(1))
+                .and_then(|code| code.parse::<u16>().ok());
+            let interim = matches!(status_code, Some(100..=199))
+                || (follow_redirects && matches!(status_code, Some(300..=399)) && headers.contains_key("location"));
+            buffer = rest;
+            if interim {
+                continue;
+            }
+            return Ok(CurlStream {
+                curl_command: curl_command_str.to_string(),
+                status_code,
+                headers,
+                initial: buffer,
+                stdout,
+                child,
+            });
+        }
+        if buffer.len() > MAX_STREAM_HEADER_BYTES {
+            return Err(AppError::BadGateway(format!("Response headers from {} are too large", request.display_url())));
+        }
+
+        let read = tokio::time::timeout(header_timeout, stdout.read(&mut chunk))
+            .await
+            .map_err(|_| {
+                AppError::BadGateway(format!(
+                    "No response headers from {} within {}s",
+                    request.display_url(),
+                    header_timeout.as_secs()
+                ))
+            })?
+            .map_err(|e| AppError::InternalServerError(format!("Failed to read curl output: {}", e)))?;
+        if read == 0 {
+            let status = child
+                .wait()
+                .await
+                .map_err(|e| AppError::InternalServerError(format!("Failed to wait for curl: {}", e)))?;
+            return Err(AppError::BadGateway(format!(
+                "Failed to fetch {}: curl exit code {}",
+                request.display_url(),
+                status.code().unwrap_or(-1)
+            )));
+        }
+        buffer.extend_from_slice(&chunk[..read]);
+    }
+}
+
+/// 响应头结束的位置及分隔符长度
+fn header_end(buffer: &[u8]) -> Option<(usize, usize)> {
+    let crlf = buffer.windows(4).position(|window| window == b"\r\n\r\n").map(|index| (index, 4));
+    let lf = buffer.windows(2).position(|window| window == b"\n\n").map(|index| (index, 2));
+    match (crlf, lf) {
+        (Some(crlf), Some(lf)) => Some(if lf.0 < crlf.0 { lf } else { crlf }),
+        (crlf, lf) => crlf.or(lf),
+    }
+}
+
+/// 根据全局配置与 profile 构建 curl 命令, `limit_size` 为 false 时用于完整下载大文件
+fn build_curl_command(
+    prepared: &PreparedRequest,
+    limit_size: bool,
+) -> CurlCommand {
+    let PreparedRequest {
+        request,
+        config,
+        profile,
+        auth,
+        body,
+        resolve,
+        connect_to,
+        pinned,
+        limits,
+    } = prepared;
+    let curl_config = prepared.curl_config();
+
+    let mut command = CurlCommand::new();
+
+    // 添加方法
+    if request.method != "GET" {
+        command.arg("-X").arg(&request.method);
     }
 
-    for (name, value) in headers.iter() {
-        if let Ok(value_str) = value.to_str() {
-            unlimited_curl_command.push("-H".to_string());
-            unlimited_curl_command.push(format!("{}: {}", name.as_str(), value_str));
+    // 添加头部, profile 中配置的同名头部优先
+    for (name, value) in &request.headers {
+        let overridden = profile.as_ref().is_some_and(|profile| {
+            profile.headers.keys().any(|key| key.eq_ignore_ascii_case(name))
+        }) || (curl_config.user_agent.is_some() && name.eq_ignore_ascii_case("user-agent"))
+            || (auth.is_some() && name.eq_ignore_ascii_case("authorization"));
+        if !overridden {
+            command.header(name, value);
+        }
+    }
+    if let Some(profile) = profile {
+        for (name, value) in &profile.headers {
+            command.secret_header(name, value);
         }
     }
+    if let Some(user_agent) = &curl_config.user_agent {
+        command.arg("-A").arg(user_agent);
+    }
+    if let Some(auth) = auth {
+        command.extend(auth);
+    }
+    if let Some(body) = body {
+        command.extend(&body.args);
+    }
 
-    unlimited_curl_command.push("-s".to_string());
-    if state.config.curl.follow_redirects {
-        unlimited_curl_command.push("-L".to_string());
+    // 添加其他curl选项
+    if limit_size && curl_config.include_headers {
+        command.arg("-i"); // 包含响应头
+    }
+    command.arg("-s"); // 静默模式
+    if let Some(http_version) = request.http_version {
+        command.arg(http_version.curl_flag());
+    }
+    if curl_config.follow_redirects && !prepared.follows_redirects_manually() {
+        command.arg("-L"); // 跟随重定向
+        if let Some(max_redirects) = curl_config.max_redirects {
+            command.arg("--max-redirs").arg(max_redirects.to_string());
+        }
+        // 不允许重定向到 file:// 等其他协议
+        command.arg("--proto-redir").arg("=http,https");
+    }
+    command
+        .arg("--connect-timeout")
+        .arg(curl_config.timeout_seconds.to_string());
+    if let Some(max_time) = limits.max_time {
+        command.arg("--max-time").arg(max_time.as_secs().max(1).to_string());
+    }
+    if let Some(proxy) = &curl_config.proxy {
+        command.secret_option("-x", proxy, mask_url_credentials(proxy));
+    }
+    if curl_config.insecure {
+        command.arg("-k");
+    }
+    if let Some(ca_cert) = &curl_config.ca_cert {
+        command.arg("--cacert").arg(ca_cert);
+    }
+    for entry in resolve {
+        command.arg("--resolve").arg(entry);
+    }
+    for entry in connect_to {
+        command.arg("--connect-to").arg(entry);
+    }
+    // 固定为已通过检查的地址, 避免 curl 重新解析时得到不同结果
+    if let Some(pinned) = pinned {
+        command.hidden_arg("--resolve").hidden_arg(pinned.resolve_arg());
+    }
+    // 在输出末尾追加请求元数据 (实际连接的 IP、状态码等)
+    command
+        .hidden_arg("-w")
+        .hidden_arg(format!("{}%{{json}}", META_MARKER));
+    if limit_size {
+        command
+            .arg("--max-filesize")
+            .arg(config.proxy.max_response_size_bytes.to_string());
     }
-    unlimited_curl_command.push("--connect-timeout".to_string());
-    unlimited_curl_command.push(state.config.curl.timeout_seconds.to_string());
-    unlimited_curl_command.push(target_url.to_string());
 
-    // 执行curl获取完整响应
-    let output = Command::new("curl")
-        .args(&unlimited_curl_command[1..])
-        .output()
-        .map_err(|e| AppError::InternalServerError(format!("Failed to execute unlimited curl: {}", e)))?;
+    // 添加目标URL
+    command.arg(&request.url);
+    command.mask_values(&request.secret_values);
+    command
+}
 
-    if !output.status.success() {
-        let stderr = String::from_utf8_lossy(&output.stderr);
-        return Ok(CurlResponse {
-            curl_command: curl_command.to_string(),
-            response_body: None,
-            response_headers: None,
-            status_code: None,
-            error: Some(format!("Failed to fetch large response: {}", stderr)),
-            redirected: false,
-            redirect_url: None,
-            uploaded_to_temp_host: false,
-            temp_file_url: None,
-        }
-        .into_response());
-    }
-
-    let response_data = output.stdout;
-    
-    // 上传到temp-file-host
-    match upload_to_temp_host(state, &response_data).await {
-        Ok(temp_url) => {
-            info!("Successfully uploaded large response to temp-file-host: {}", temp_url);
+/// 执行 curl 并边读取标准输出边计算摘要, 返回进程结果 (不含标准输出)、响应内容和摘要
+async fn stream_body(command: &CurlCommand) -> std::io::Result<(std::process::Output, Vec<u8>, Digests)> {
+    let mut process = Command::new("curl");
+    process
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped())
+        .kill_on_drop(true);
+    let mut child = command.spawn(&mut process).await?;
+    let mut stdout = child
+        .stdout
+        .take()
+        .ok_or_else(|| std::io::Error::other("Failed to capture curl output"))?;
+
+    let mut data = Vec::new();
+    let mut hasher = DigestHasher::default();
+    let mut chunk = vec![0u8; 64 * 1024];
+    loop {
+        let read = stdout.read(&mut chunk).await?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&chunk[..read]);
+        data.extend_from_slice(&chunk[..read]);
+    }
+
+    // 只剩 -w 输出的元数据, 体积很小, 读完标准输出后再收集不会阻塞 curl
+    let output = child.wait_with_output().await?;
+    Ok((output, data, hasher.finish()))
+}
+
+async fn upload_large_response(
+    state: &Arc<AppState>,
+    prepared: &PreparedRequest<'_>,
+    curl_command: &str,
+    expected_checksums: &[ExpectedChecksum],
+) -> Result<CurlResponse, AppError> {
+    let config = &prepared.config;
+    info!("Response too large, fetching and storing via {:?} sink", config.proxy.sink);
+
+    // 构建无大小限制的curl命令
+    let unlimited_curl_command = build_curl_command(prepared, false);
+
+    // 上游支持 Range 请求时分段并行下载, 失败时退回单连接下载
+    let segmented = if prepared.request.method.eq_ignore_ascii_case("GET") {
+        match segmented::fetch(&config.proxy.segmented, &unlimited_curl_command, &prepared.request.url).await {
+            Ok(segmented) => segmented,
+            Err(e) => {
+                warn!("Segmented download failed, falling back to a single stream: {}", e);
+                None
+            }
+        }
+    } else {
+        None
+    };
+
+    let (response_data, digests, response_headers, redirects, meta, timings, status_code) = match segmented {
+        Some(segmented) => {
+            info!("Fetched large response in {} segments", segmented.segments);
+            let status_code = segmented.meta.http_code;
+            (
+                segmented.data,
+                segmented.digests,
+                segmented.headers,
+                segmented.redirects,
+                segmented.meta,
+                Some(segmented.timings),
+                status_code,
+            )
+        }
+        None => {
+            // 响应头单独写入临时文件; 与分段下载使用相同的总大小上限
+            let header_dump = tempfile::NamedTempFile::new()
+                .map_err(|e| AppError::InternalServerError(format!("Failed to create header file: {}", e)))?;
+            let max_total_bytes = config.proxy.segmented.max_total_bytes;
+            let mut single_stream_command = unlimited_curl_command.clone();
+            single_stream_command
+                .hidden_arg("-D")
+                .hidden_arg(header_dump.path().to_string_lossy())
+                .hidden_arg("--max-filesize")
+                .hidden_arg(max_total_bytes.to_string())
+                // 元数据改写到标准错误, 标准输出只有响应内容, 可以边读边计算摘要
+                .hidden_arg("-w")
+                .hidden_arg("%{stderr}%{json}");
+
+            // 执行curl获取完整响应
+            let (output, data, digests) = stream_body(&single_stream_command)
+                .await
+                .map_err(|e| AppError::InternalServerError(format!("Failed to execute unlimited curl: {}", e)))?;
+
+            if !output.status.success() {
+                // curl 退出码 63: 超过 --max-filesize
+                let error = if output.status.code() == Some(63) {
+                    format!("Response exceeds proxy.segmented.max_total_bytes ({})", max_total_bytes)
+                } else {
+                    format!("Failed to fetch large response: {}", String::from_utf8_lossy(&output.stderr))
+                };
+                return Ok(CurlResponse {
+                    curl_command: curl_command.to_string(),
+                    error: Some(error),
+                    ..Default::default()
+                });
+            }
+
+            let meta: CurlMeta = serde_json::from_slice(&output.stderr).unwrap_or_default();
+            let header_text = tokio::fs::read(header_dump.path())
+                .await
+                .map(|dump| String::from_utf8_lossy(&dump).to_string())
+                .unwrap_or_default();
+            let (response_headers, _) = parse_curl_response(last_header_block(&header_text));
+            let redirects = redirect_hops(&header_text, &prepared.request.url);
+
+            let timings = meta.timings();
+            let status_code = meta.http_code;
+            (data, digests, response_headers, redirects, meta, timings, status_code)
+        }
+    };
+
+    // 校验失败时不保存到 sink
+    if let Err(e) = digests.verify(expected_checksums) {
+        warn!("Refusing to store large response: {}", e);
+        return Err(e);
+    }
+
+    // 文件名优先取 Content-Disposition, 其次是最终 URL 的路径
+    let final_url = meta.url_effective.as_deref().unwrap_or(&prepared.request.url);
+    let filename = response_headers
+        .get("content-disposition")
+        .and_then(|value| filename_from_content_disposition(value))
+        .or_else(|| filename_from_url(final_url))
+        .unwrap_or_else(|| utils_share::time::generate_timestamped_filename("curl_response", "dat"));
+    let content_type = response_headers
+        .get("content-type")
+        .cloned()
+        .unwrap_or_else(|| "application/octet-stream".to_string());
+    let redirected = meta.num_redirects.unwrap_or(0) > 0;
+
+    // 保存到配置的 sink
+    match sinks::store(config, &state.http_client, &response_data, &filename, &content_type).await {
+        Ok(stored) => {
+            info!("Successfully stored large response via {} sink: {}", stored.sink, stored.url);
             Ok(CurlResponse {
                 curl_command: curl_command.to_string(),
-                response_body: None,
-                response_headers: None,
-                status_code: None,
-                error: None,
-                redirected: false,
-                redirect_url: None,
+                response_headers: Some(response_headers),
+                status_code,
+                redirected,
+                redirect_url: if redirected { meta.url_effective.clone() } else { None },
+                redirects,
                 uploaded_to_temp_host: true,
-                temp_file_url: Some(temp_url),
-            }
-            .into_response())
+                temp_file_url: Some(stored.url.clone()),
+                stored_file: Some(stored),
+                timings,
+                digests: Some(digests),
+                remote_ip: meta.remote_ip,
+                http_version: meta.http_version,
+                ..Default::default()
+            })
         }
         Err(e) => {
-            error!("Failed to upload to temp-file-host: {}", e);
+            error!("Failed to store large response: {}", e);
             Ok(CurlResponse {
                 curl_command: curl_command.to_string(),
-                response_body: None,
-                response_headers: None,
-                status_code: None,
                 error: Some(format!("Failed to upload large response: {}", e)),
-                redirected: false,
-                redirect_url: None,
-                uploaded_to_temp_host: false,
-                temp_file_url: None,
-            }
-            .into_response())
+                ..Default::default()
+            })
         }
     }
 }
-
-async fn upload_to_temp_host(
-    state: &Arc<AppState>,
-    data: &[u8],
-) -> Result<String> {
-    // 生成文件名
-    let filename = utils_share::time::generate_timestamped_filename("curl_response", "dat");
-    
-    // 创建multipart表单
-    let form = reqwest::multipart::Form::new()
-        .part("file", reqwest::multipart::Part::bytes(data.to_vec())
-            .file_name(filename.clone())
-            .mime_str("application/octet-stream")?);
-
-    // 上传到temp-file-host
-    let upload_url = format!("{}/upload", state.config.proxy.temp_file_host_url);
-    let response = state.http_client
-        .post(&upload_url)
-        .multipart(form)
-        .send()
-        .await?;
-
-    if response.status().is_success() {
-        let download_url = response.text().await?;
-        Ok(download_url)
-    } else {
-        Err(anyhow::anyhow!("Upload failed with status: {}", response.status()))
-    }
-} 
\ No newline at end of file
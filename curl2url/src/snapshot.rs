@@ -0,0 +1,514 @@
+use axum::{
+    extract::{Json, Query, State},
+    response::{IntoResponse, Response},
+};
+use chrono::{DateTime, Utc};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    io::Write,
+    sync::{Arc, LazyLock},
+};
+use tracing::{info, warn};
+
+use crate::{
+    errors::AppError,
+    html::{self, LinkRef, LinkRewriter},
+    links::{rewrite_stored_file, PublicBaseUrl},
+    models::{AppState, CurlRequest},
+    services::{fetch_raw, RawResponse},
+    sinks,
+    vcr,
+};
+
+/// 需要一起保存的 `<link rel>` 类型
+static REQUISITE_REL: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"(?i)\srel\s*=\s*["']?[^"'>]*\b(stylesheet|icon|preload|modulepreload)\b"#).expect("valid rel regex")
+});
+
+#[derive(Debug, Deserialize)]
+pub struct SnapshotQuery {
+    url: Option<String>,
+    /// 低于配置时生效
+    max_assets: Option<usize>,
+    max_bytes: Option<u64>,
+}
+
+/// 压缩包中的一个文件
+#[derive(Debug, Clone, Serialize)]
+pub struct ManifestEntry {
+    pub path: String,
+    pub url: String,
+    pub status_code: Option<u16>,
+    pub content_type: Option<String>,
+    pub size_bytes: u64,
+    pub sha256: String,
+}
+
+/// 引用了但没有保存的资源
+#[derive(Debug, Clone, Serialize)]
+pub struct SkippedAsset {
+    pub url: String,
+    pub reason: String,
+}
+
+/// 快照清单, 同时以 `manifest.json` 保存在压缩包中
+#[derive(Debug, Clone, Serialize)]
+pub struct SnapshotManifest {
+    pub url: String,
+    pub final_url: String,
+    pub created_at: DateTime<Utc>,
+    pub files: Vec<ManifestEntry>,
+    pub skipped: Vec<SkippedAsset>,
+    pub total_bytes: u64,
+}
+
+/// 已下载的文件, 写入压缩包前还需要改写其中的链接
+struct Fetched {
+    path: String,
+    url: String,
+    final_url: String,
+    kind: FileKind,
+    response: RawResponse,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FileKind {
+    Html,
+    Css,
+    Other,
+}
+
+/// 收集页面所需的资源 (类似 `wget -p`), 不做改写
+#[derive(Default)]
+struct Requisites {
+    urls: Vec<String>,
+}
+
+impl LinkRewriter for Requisites {
+    fn rewrite(&mut self, link: &LinkRef) -> Option<String> {
+        let requisite = match (link.tag, link.attr) {
+            // CSS 中的 url() 和 @import
+            ("", _) => true,
+            ("img" | "source" | "input" | "audio" | "video" | "track" | "embed", "src" | "srcset") => true,
+            ("video", "poster") | ("script", "src") => true,
+            ("link", "href") => REQUISITE_REL.is_match(link.attributes),
+            _ => false,
+        };
+        if requisite {
+            self.urls.push(link.url.to_string());
+        }
+        None
+    }
+}
+
+/// 已保存的资源改为相对路径, 其余链接改为绝对地址 (类似 `wget -k`)
+struct ArchiveLinks<'a> {
+    /// 当前文件在压缩包中的路径
+    from: &'a str,
+    paths: &'a HashMap<String, String>,
+}
+
+impl LinkRewriter for ArchiveLinks<'_> {
+    fn rewrite(&mut self, link: &LinkRef) -> Option<String> {
+        Some(match self.paths.get(link.url) {
+            Some(path) => relative_path(self.from, path),
+            None => link.url.to_string(),
+        })
+    }
+}
+
+/// `POST /snapshot?url=`: 保存页面及其同源资源, 打包为 zip 后上传到 sink
+pub async fn create_snapshot(
+    State(state): State<Arc<AppState>>,
+    PublicBaseUrl(public_base): PublicBaseUrl,
+    Query(query): Query<SnapshotQuery>,
+) -> Result<Response, AppError> {
+    vcr::ensure_live(&state.config().vcr, "Snapshots")?;
+    let url = query
+        .url
+        .ok_or_else(|| AppError::BadRequest("Missing 'url' parameter".to_string()))?;
+    let config = state.config().snapshot.clone();
+    let max_assets = query.max_assets.unwrap_or(config.max_assets).min(config.max_assets);
+    let max_bytes = query
+        .max_bytes
+        .unwrap_or(config.max_total_bytes)
+        .min(config.max_total_bytes);
+
+    let page = fetch(&state, &url)
+        .await?
+        .ok_or_else(|| AppError::BadRequest(format!("Page {} exceeds max_response_size_bytes", url)))?;
+    if !matches!(page.status_code, Some(200..=299)) {
+        return Err(AppError::BadGateway(format!(
+            "Page {} returned status {:?}",
+            url, page.status_code
+        )));
+    }
+    if file_kind(&page) != FileKind::Html {
+        return Err(AppError::BadRequest(format!(
+            "{} is not an HTML page (content type {})",
+            url,
+            page.headers.get("content-type").map(String::as_str).unwrap_or("unknown")
+        )));
+    }
+    let page_url = page.final_url.clone();
+    let origin = url::Url::parse(&page_url)
+        .map_err(|e| AppError::BadRequest(format!("Invalid page URL: {}", e)))?
+        .origin();
+    info!("Creating snapshot of {}", page_url);
+
+    let mut requisites = Requisites::default();
+    html::rewrite_html(&String::from_utf8_lossy(&page.body), &page_url, &mut requisites);
+
+    let mut used_paths: HashSet<String> = ["index.html", "manifest.json"].into_iter().map(String::from).collect();
+    let mut total_bytes = page.body.len() as u64;
+    let mut files = vec![Fetched {
+        path: "index.html".to_string(),
+        url: url.clone(),
+        final_url: page_url.clone(),
+        kind: FileKind::Html,
+        response: page,
+    }];
+    let mut skipped = Vec::new();
+    let mut seen = HashSet::new();
+    let mut queue: VecDeque<String> = requisites.urls.into();
+
+    while let Some(asset_url) = queue.pop_front() {
+        if !seen.insert(asset_url.clone()) {
+            continue;
+        }
+        let mut skip = |reason: String| {
+            skipped.push(SkippedAsset {
+                url: asset_url.clone(),
+                reason,
+            })
+        };
+        let Ok(parsed) = url::Url::parse(&asset_url) else {
+            continue;
+        };
+        if !is_same_origin(&parsed, &origin) {
+            skip("cross-origin".to_string());
+            continue;
+        }
+        if files.len() > max_assets {
+            skip("asset limit reached".to_string());
+            continue;
+        }
+
+        let response = match fetch(&state, &asset_url).await {
+            Ok(Some(response)) => response,
+            Ok(None) => {
+                skip("larger than max_response_size_bytes".to_string());
+                continue;
+            }
+            Err(e) => {
+                skip(e.to_string());
+                continue;
+            }
+        };
+        if !matches!(response.status_code, Some(200..=299)) {
+            skip(format!("status {:?}", response.status_code));
+            continue;
+        }
+        if total_bytes + response.body.len() as u64 > max_bytes {
+            skip("size limit reached".to_string());
+            continue;
+        }
+        total_bytes += response.body.len() as u64;
+
+        // 样式表中引用的图片、字体等一并保存
+        let kind = file_kind(&response);
+        if kind == FileKind::Css {
+            let mut nested = Requisites::default();
+            html::rewrite_css(&String::from_utf8_lossy(&response.body), &response.final_url, false, &mut nested);
+            queue.extend(nested.urls);
+        }
+
+        files.push(Fetched {
+            path: asset_path(&parsed, &mut used_paths),
+            url: asset_url.clone(),
+            final_url: response.final_url.clone(),
+            kind,
+            response,
+        });
+    }
+
+    // 按引用时的地址查找本地路径
+    let paths: HashMap<String, String> = files
+        .iter()
+        .map(|file| (file.url.clone(), file.path.clone()))
+        .collect();
+
+    let mut entries = Vec::new();
+    let mut archive = Vec::new();
+    for file in files {
+        let body = match file.kind {
+            FileKind::Html => html::rewrite_html(
+                &String::from_utf8_lossy(&file.response.body),
+                &file.final_url,
+                &mut ArchiveLinks {
+                    from: &file.path,
+                    paths: &paths,
+                },
+            )
+            .into_bytes(),
+            FileKind::Css => html::rewrite_css(
+                &String::from_utf8_lossy(&file.response.body),
+                &file.final_url,
+                false,
+                &mut ArchiveLinks {
+                    from: &file.path,
+                    paths: &paths,
+                },
+            )
+            .into_bytes(),
+            FileKind::Other => file.response.body,
+        };
+        entries.push(ManifestEntry {
+            path: file.path.clone(),
+            url: file.url,
+            status_code: file.response.status_code,
+            content_type: file.response.headers.get("content-type").cloned(),
+            size_bytes: body.len() as u64,
+            sha256: utils_share::crypto::sha256_hash(&body),
+        });
+        archive.push((file.path, body));
+    }
+
+    let manifest = SnapshotManifest {
+        url,
+        final_url: page_url.clone(),
+        created_at: Utc::now(),
+        files: entries,
+        skipped,
+        total_bytes,
+    };
+    let manifest_json = serde_json::to_vec_pretty(&manifest)
+        .map_err(|e| AppError::InternalServerError(format!("Failed to serialize manifest: {}", e)))?;
+    archive.push(("manifest.json".to_string(), manifest_json));
+
+    let zip = build_zip(&archive)
+        .map_err(|e| AppError::InternalServerError(format!("Failed to build snapshot archive: {}", e)))?;
+    let host = url::Url::parse(&page_url)
+        .ok()
+        .and_then(|parsed| parsed.host_str().map(clean_segment))
+        .unwrap_or_else(|| "page".to_string());
+    let filename = utils_share::time::generate_timestamped_filename(&format!("snapshot_{}", host), "zip");
+    let mut stored = sinks::store(&state.config(), &state.http_client, &zip, &filename, "application/zip")
+        .await
+        .map_err(|e| {
+            warn!("Failed to store snapshot of {}: {}", page_url, e);
+            AppError::InternalServerError(format!("Failed to upload snapshot: {}", e))
+        })?;
+    rewrite_stored_file(&mut stored, public_base.as_deref());
+    info!(
+        "Stored snapshot of {} ({} files, {} skipped) at {}",
+        page_url,
+        manifest.files.len(),
+        manifest.skipped.len(),
+        stored.url
+    );
+
+    Ok(Json(json!({
+        "download_url": stored.url,
+        "stored_file": stored,
+        "manifest": manifest,
+    }))
+    .into_response())
+}
+
+async fn fetch(state: &Arc<AppState>, url: &str) -> Result<Option<RawResponse>, AppError> {
+    let request = CurlRequest {
+        url: url.to_string(),
+        method: "GET".to_string(),
+        ..Default::default()
+    };
+    fetch_raw(state, &request).await
+}
+
+fn file_kind(response: &RawResponse) -> FileKind {
+    let content_type = response
+        .headers
+        .get("content-type")
+        .map(|value| value.to_ascii_lowercase())
+        .unwrap_or_default();
+    if content_type.contains("text/html") || content_type.contains("xhtml") {
+        FileKind::Html
+    } else if content_type.contains("text/css") {
+        FileKind::Css
+    } else {
+        FileKind::Other
+    }
+}
+
+/// 按 URL 路径生成 `assets/` 下的文件名, 带查询参数时追加其摘要, 重名时追加序号
+fn asset_path(url: &url::Url, used: &mut HashSet<String>) -> String {
+    let mut segments: Vec<String> = url
+        .path_segments()
+        .map(|segments| segments.filter(|segment| !segment.is_empty()).map(clean_segment).collect())
+        .unwrap_or_default();
+    if segments.is_empty() || url.path().ends_with('/') {
+        segments.push("index".to_string());
+    }
+    if let Some(query) = url.query()
+        && let Some(last) = segments.last_mut()
+    {
+        let digest = utils_share::crypto::sha256_hash(query.as_bytes());
+        *last = with_suffix(last, &format!("_{}", &digest[..8]));
+    }
+
+    let path = format!("assets/{}", segments.join("/"));
+    let mut candidate = path.clone();
+    let mut counter = 2;
+    while !used.insert(candidate.clone()) {
+        candidate = with_suffix(&path, &format!("-{}", counter));
+        counter += 1;
+    }
+    candidate
+}
+
+/// 在扩展名之前插入后缀
+fn with_suffix(name: &str, suffix: &str) -> String {
+    let file_start = name.rfind('/').map(|index| index + 1).unwrap_or(0);
+    match name[file_start..].rfind('.') {
+        Some(dot) if dot > 0 => {
+            let dot = file_start + dot;
+            format!("{}{}{}", &name[..dot], suffix, &name[dot..])
+        }
+        _ => format!("{}{}", name, suffix),
+    }
+}
+
+/// 路径中只保留字母、数字和 `._-`, 生成的相对链接无需转义
+fn clean_segment(segment: &str) -> String {
+    let decoded = urlencoding::decode(segment).map(|decoded| decoded.into_owned()).unwrap_or_else(|_| segment.to_string());
+    let cleaned: String = decoded
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-') { c } else { '_' })
+        .collect();
+    if cleaned.chars().all(|c| c == '.') {
+        "_".to_string()
+    } else {
+        cleaned
+    }
+}
+
+/// 只保存与页面同源 (协议、主机和端口都相同) 的资源
+fn is_same_origin(url: &url::Url, origin: &url::Origin) -> bool {
+    url.origin() == *origin
+}
+
+/// 从压缩包中的一个文件指向另一个文件的相对路径
+fn relative_path(from: &str, to: &str) -> String {
+    let mut from_dir: Vec<&str> = from.split('/').collect();
+    from_dir.pop();
+    let to: Vec<&str> = to.split('/').collect();
+    let common = from_dir.iter().zip(&to).take_while(|(a, b)| a == b).count();
+    let mut parts = vec![".."; from_dir.len() - common];
+    parts.extend(&to[common..]);
+    parts.join("/")
+}
+
+fn build_zip(files: &[(String, Vec<u8>)]) -> anyhow::Result<Vec<u8>> {
+    let mut writer = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+    let options = zip::write::SimpleFileOptions::default().compression_method(zip::CompressionMethod::Deflated);
+    for (path, data) in files {
+        writer.start_file(path.as_str(), options)?;
+        writer.write_all(data)?;
+    }
+    Ok(writer.finish()?.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn requisites(html: &str, page_url: &str) -> Vec<String> {
+        let mut requisites = Requisites::default();
+        html::rewrite_html(html, page_url, &mut requisites);
+        requisites.urls
+    }
+
+    #[test]
+    fn relative_paths_between_archive_files() {
+        assert_eq!(relative_path("index.html", "assets/css/site.css"), "assets/css/site.css");
+        assert_eq!(relative_path("assets/css/site.css", "assets/img/bg.png"), "../img/bg.png");
+        assert_eq!(relative_path("assets/css/site.css", "assets/css/fonts.css"), "fonts.css");
+        assert_eq!(relative_path("assets/a/b/c.css", "index.html"), "../../../index.html");
+    }
+
+    #[test]
+    fn collects_page_requisites() {
+        let html = r#"<html><head>
+            <link rel="stylesheet" href="/css/site.css">
+            <link rel="alternate" href="/feed.xml">
+            <link rel="icon" href="favicon.ico">
+            <script src="app.js"></script>
+            <style>body { background: url(img/bg.png) }</style>
+            </head><body>
+            <a href="/about">About</a>
+            <img src="https://cdn.example.net/logo.png">
+            <video poster="poster.jpg"></video>
+            <form action="/search"></form>
+            </body></html>"#;
+        assert_eq!(
+            requisites(html, "https://example.com/page/"),
+            vec![
+                "https://example.com/page/img/bg.png",
+                "https://example.com/css/site.css",
+                "https://example.com/page/favicon.ico",
+                "https://example.com/page/app.js",
+                "https://cdn.example.net/logo.png",
+                "https://example.com/page/poster.jpg",
+            ]
+        );
+    }
+
+    #[test]
+    fn only_same_origin_assets_are_saved() {
+        let origin = url::Url::parse("https://example.com/page/").unwrap().origin();
+        let same = |asset: &str| is_same_origin(&url::Url::parse(asset).unwrap(), &origin);
+        assert!(same("https://example.com/css/site.css"));
+        assert!(same("https://example.com:443/app.js"));
+        assert!(!same("https://cdn.example.net/logo.png"));
+        assert!(!same("https://static.example.com/app.js"));
+        assert!(!same("http://example.com/app.js"));
+        assert!(!same("https://example.com:8443/app.js"));
+    }
+
+    #[test]
+    fn archive_links_point_to_saved_files() {
+        let paths: HashMap<String, String> = [
+            ("https://example.com/css/site.css", "assets/css/site.css"),
+            ("https://example.com/img/bg.png", "assets/img/bg.png"),
+        ]
+        .into_iter()
+        .map(|(url, path)| (url.to_string(), path.to_string()))
+        .collect();
+
+        let html = html::rewrite_html(
+            r#"<link rel="stylesheet" href="/css/site.css"><a href="/about">About</a>"#,
+            "https://example.com/",
+            &mut ArchiveLinks {
+                from: "index.html",
+                paths: &paths,
+            },
+        );
+        assert!(html.contains(r#"href="assets/css/site.css""#), "{}", html);
+        assert!(html.contains(r#"href="https://example.com/about""#), "{}", html);
+
+        let css = html::rewrite_css(
+            "body { background: url(../img/bg.png) } .x { background: url(/missing.png) }",
+            "https://example.com/css/site.css",
+            false,
+            &mut ArchiveLinks {
+                from: "assets/css/site.css",
+                paths: &paths,
+            },
+        );
+        assert!(css.contains("../img/bg.png"), "{}", css);
+        assert!(css.contains("https://example.com/missing.png"), "{}", css);
+    }
+}
@@ -0,0 +1,328 @@
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use tracing::warn;
+
+use crate::{config::SecurityConfig, errors::AppError, profiles::glob_match};
+
+/// `--resolve host:port:addr[,addr]` 形式的 DNS 覆盖
+#[derive(Debug, Clone)]
+pub struct ResolveEntry {
+    pub host: String,
+    pub port: u16,
+    pub addrs: Vec<IpAddr>,
+}
+
+/// `--connect-to host:port:connect_host:connect_port` 形式的连接目标覆盖, 空字段表示任意
+#[derive(Debug, Clone)]
+pub struct ConnectToEntry {
+    pub host: Option<String>,
+    pub port: Option<u16>,
+    pub connect_host: Option<String>,
+    pub connect_port: Option<u16>,
+}
+
+/// 经过校验的实际连接目标, 用于在 curl 中固定解析结果
+#[derive(Debug, Clone)]
+pub struct PinnedTarget {
+    pub host: String,
+    pub port: u16,
+    pub addrs: Vec<IpAddr>,
+}
+
+impl PinnedTarget {
+    /// 对应的 `--resolve` 参数, IPv6 的 host 和地址都加方括号
+    pub fn resolve_arg(&self) -> String {
+        let host = match self.host.parse::<IpAddr>() {
+            Ok(IpAddr::V6(addr)) => format!("[{}]", addr),
+            _ => self.host.clone(),
+        };
+        let addrs: Vec<String> = self
+            .addrs
+            .iter()
+            .map(|addr| match addr {
+                IpAddr::V4(addr) => addr.to_string(),
+                IpAddr::V6(addr) => format!("[{}]", addr),
+            })
+            .collect();
+        format!("{}:{}:{}", host, self.port, addrs.join(","))
+    }
+}
+
+pub fn parse_resolve(raw: &str) -> Result<ResolveEntry, AppError> {
+    let invalid = || AppError::BadRequest(format!("Invalid resolve entry '{}', expected host:port:addr", raw));
+
+    let mut parts = raw.splitn(3, ':');
+    let host = parts.next().filter(|host| !host.is_empty()).ok_or_else(invalid)?;
+    let port = parts.next().and_then(|port| port.parse().ok()).ok_or_else(invalid)?;
+    let addrs = parts
+        .next()
+        .ok_or_else(invalid)?
+        .split(',')
+        .map(|addr| addr.trim_start_matches('[').trim_end_matches(']').parse::<IpAddr>())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|_| invalid())?;
+
+    Ok(ResolveEntry {
+        host: host.to_ascii_lowercase(),
+        port,
+        addrs,
+    })
+}
+
+pub fn parse_connect_to(raw: &str) -> Result<ConnectToEntry, AppError> {
+    let invalid = || {
+        AppError::BadRequest(format!(
+            "Invalid connect_to entry '{}', expected host:port:connect_host:connect_port",
+            raw
+        ))
+    };
+
+    // connect_host 可能是 [ipv6] 形式, 因此从两端分别取 port
+    let (host, rest) = raw.split_once(':').ok_or_else(invalid)?;
+    let (port, rest) = rest.split_once(':').ok_or_else(invalid)?;
+    let (connect_host, connect_port) = rest.rsplit_once(':').ok_or_else(invalid)?;
+
+    let parse_port = |port: &str| -> Result<Option<u16>, AppError> {
+        if port.is_empty() {
+            Ok(None)
+        } else {
+            port.parse().map(Some).map_err(|_| invalid())
+        }
+    };
+    let non_empty = |value: &str| (!value.is_empty()).then(|| value.to_ascii_lowercase());
+
+    Ok(ConnectToEntry {
+        host: non_empty(host),
+        port: parse_port(port)?,
+        connect_host: non_empty(connect_host.trim_start_matches('[').trim_end_matches(']')),
+        connect_port: parse_port(connect_port)?,
+    })
+}
+
+/// 按照 connect_to 和 resolve 覆盖计算实际连接的地址, 并在开启限制时拒绝内网地址
+pub async fn check_target(
+    security: &SecurityConfig,
+    target_url: &str,
+    resolve: &[ResolveEntry],
+    connect_to: &[ConnectToEntry],
+) -> Result<Option<PinnedTarget>, AppError> {
+    if !security.block_private_networks {
+        return Ok(None);
+    }
+
+    let (host, _) = target_host_port(target_url)?;
+    if is_allowed_host(security, &host) {
+        return Ok(None);
+    }
+
+    let target = resolve_target(target_url, resolve, connect_to).await?;
+    reject_private(target_url, &target.host, &target.addrs)?;
+    Ok(Some(target))
+}
+
+/// 不经过 curl 的查询 (例如 DNS 诊断) 使用: 开启限制时拒绝解析到内网地址的 host
+pub fn check_addresses(security: &SecurityConfig, host: &str, addrs: &[IpAddr]) -> Result<(), AppError> {
+    if !security.block_private_networks || is_allowed_host(security, &host.to_ascii_lowercase()) {
+        return Ok(());
+    }
+    reject_private(host, host, addrs)
+}
+
+fn is_allowed_host(security: &SecurityConfig, host: &str) -> bool {
+    security
+        .allowed_hosts
+        .iter()
+        .any(|pattern| glob_match(&pattern.to_ascii_lowercase(), host))
+}
+
+fn reject_private(target: &str, host: &str, addrs: &[IpAddr]) -> Result<(), AppError> {
+    match addrs.iter().find(|addr| is_private_address(addr)) {
+        Some(blocked) => {
+            warn!("Blocked request to {} ({} resolves to {})", target, host, blocked);
+            Err(AppError::Forbidden(format!(
+                "Target '{}' resolves to a private or reserved address ({})",
+                host, blocked
+            )))
+        }
+        None => Ok(()),
+    }
+}
+
+/// 实际连接的 host、端口和地址, 与 curl 一致: 先应用 connect_to, 再对实际连接的 host 应用 resolve
+pub async fn resolve_target(
+    target_url: &str,
+    resolve: &[ResolveEntry],
+    connect_to: &[ConnectToEntry],
+) -> Result<PinnedTarget, AppError> {
+    let (host, port) = target_host_port(target_url)?;
+    let (connect_host, connect_port) = connect_to
+        .iter()
+        .find(|entry| {
+            entry.host.as_ref().is_none_or(|entry_host| *entry_host == host)
+                && entry.port.is_none_or(|entry_port| entry_port == port)
+        })
+        .map(|entry| {
+            (
+                entry.connect_host.clone().unwrap_or_else(|| host.clone()),
+                entry.connect_port.unwrap_or(port),
+            )
+        })
+        .unwrap_or_else(|| (host.clone(), port));
+
+    let addrs = match resolve
+        .iter()
+        .find(|entry| entry.host == connect_host && entry.port == connect_port)
+    {
+        Some(entry) => entry.addrs.clone(),
+        None => match connect_host.parse::<IpAddr>() {
+            Ok(addr) => vec![addr],
+            Err(_) => tokio::net::lookup_host((connect_host.as_str(), connect_port))
+                .await
+                .map_err(|e| AppError::BadRequest(format!("Failed to resolve '{}': {}", connect_host, e)))?
+                .map(|addr| addr.ip())
+                .collect(),
+        },
+    };
+
+    Ok(PinnedTarget {
+        host: connect_host,
+        port: connect_port,
+        addrs,
+    })
+}
+
+fn target_host_port(target_url: &str) -> Result<(String, u16), AppError> {
+    let parsed = url::Url::parse(target_url)
+        .map_err(|e| AppError::BadRequest(format!("Invalid target URL: {}", e)))?;
+    let host = parsed
+        .host_str()
+        .ok_or_else(|| AppError::BadRequest("Target URL has no host".to_string()))?
+        .trim_start_matches('[')
+        .trim_end_matches(']')
+        .to_ascii_lowercase();
+    let port = parsed
+        .port_or_known_default()
+        .ok_or_else(|| AppError::BadRequest("Target URL has no port".to_string()))?;
+    Ok((host, port))
+}
+
+pub fn is_private_address(addr: &IpAddr) -> bool {
+    match addr {
+        IpAddr::V4(addr) => is_private_ipv4(addr),
+        IpAddr::V6(addr) => match addr.to_ipv4_mapped() {
+            Some(mapped) => is_private_ipv4(&mapped),
+            None => is_private_ipv6(addr),
+        },
+    }
+}
+
+fn is_private_ipv4(addr: &Ipv4Addr) -> bool {
+    let octets = addr.octets();
+    addr.is_private()
+        || addr.is_loopback()
+        || addr.is_link_local()
+        || addr.is_unspecified()
+        || addr.is_broadcast()
+        || addr.is_documentation()
+        || octets[0] == 0
+        // 100.64.0.0/10 运营商级 NAT
+        || (octets[0] == 100 && (octets[1] & 0xc0) == 64)
+        // 198.18.0.0/15 基准测试网络
+        || (octets[0] == 198 && (octets[1] & 0xfe) == 18)
+        // 240.0.0.0/4 保留地址
+        || octets[0] >= 240
+}
+
+fn is_private_ipv6(addr: &Ipv6Addr) -> bool {
+    let segments = addr.segments();
+    addr.is_loopback()
+        || addr.is_unspecified()
+        // fc00::/7 唯一本地地址
+        || (segments[0] & 0xfe00) == 0xfc00
+        // fe80::/10 链路本地地址
+        || (segments[0] & 0xffc0) == 0xfe80
+        // 64:ff9b::/96 NAT64, 可以映射到任意 IPv4 地址
+        || segments[..6] == [0x64, 0xff9b, 0, 0, 0, 0]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn private(addr: &str) -> bool {
+        is_private_address(&addr.parse().unwrap())
+    }
+
+    #[test]
+    fn private_and_reserved_addresses() {
+        for addr in [
+            "10.1.2.3",
+            "127.0.0.1",
+            "169.254.169.254",
+            "172.16.0.1",
+            "192.168.1.1",
+            "100.64.0.1",
+            "0.0.0.0",
+            "198.18.0.1",
+            "198.19.255.255",
+            "240.0.0.1",
+            "255.255.255.255",
+            "::1",
+            "::",
+            "fd00::1",
+            "fe80::1",
+            "::ffff:127.0.0.1",
+            "64:ff9b::a9fe:a9fe",
+        ] {
+            assert!(private(addr), "{}", addr);
+        }
+        for addr in ["93.184.216.34", "198.20.0.1", "100.128.0.1", "2606:2800:220:1::1", "64:ff9b:1::1"] {
+            assert!(!private(addr), "{}", addr);
+        }
+    }
+
+    #[test]
+    fn parses_resolve_entries() {
+        let entry = parse_resolve("Example.com:443:1.2.3.4,[::1]").unwrap();
+        assert_eq!(entry.host, "example.com");
+        assert_eq!(entry.port, 443);
+        assert_eq!(entry.addrs, vec!["1.2.3.4".parse::<IpAddr>().unwrap(), "::1".parse().unwrap()]);
+
+        for raw in ["example.com", ":443:1.2.3.4", "example.com:https:1.2.3.4", "example.com:443:not-an-ip"] {
+            assert!(parse_resolve(raw).is_err(), "{}", raw);
+        }
+    }
+
+    #[test]
+    fn parses_connect_to_entries() {
+        let entry = parse_connect_to("example.com:443:[::1]:8443").unwrap();
+        assert_eq!(entry.host.as_deref(), Some("example.com"));
+        assert_eq!(entry.port, Some(443));
+        assert_eq!(entry.connect_host.as_deref(), Some("::1"));
+        assert_eq!(entry.connect_port, Some(8443));
+
+        let entry = parse_connect_to("::backend.local:").unwrap();
+        assert!(entry.host.is_none() && entry.port.is_none() && entry.connect_port.is_none());
+        assert_eq!(entry.connect_host.as_deref(), Some("backend.local"));
+
+        for raw in ["example.com:443", "example.com:x:backend:80"] {
+            assert!(parse_connect_to(raw).is_err(), "{}", raw);
+        }
+    }
+
+    #[test]
+    fn resolve_arg_brackets_ipv6() {
+        let target = PinnedTarget {
+            host: "example.com".to_string(),
+            port: 443,
+            addrs: vec!["1.2.3.4".parse().unwrap(), "::1".parse().unwrap()],
+        };
+        assert_eq!(target.resolve_arg(), "example.com:443:1.2.3.4,[::1]");
+
+        let target = PinnedTarget {
+            host: "2001:db8::1".to_string(),
+            port: 8080,
+            addrs: vec!["2001:db8::1".parse().unwrap()],
+        };
+        assert_eq!(target.resolve_arg(), "[2001:db8::1]:8080:[2001:db8::1]");
+    }
+}
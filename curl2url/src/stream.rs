@@ -0,0 +1,433 @@
+use axum::{
+    body::{Body, Bytes},
+    http::{header, HeaderName, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+};
+use serde_json::{json, Value};
+use std::{convert::Infallible, sync::Arc, time::Duration};
+use tokio::{io::AsyncReadExt, sync::mpsc, time::Instant};
+use tracing::info;
+
+use crate::{
+    config::StreamConfig,
+    errors::AppError,
+    models::{AppState, CurlRequest},
+    services::{spawn_curl_stream, CurlStream},
+    vcr,
+};
+
+/// 原样返回给客户端的上游响应头
+const PASSED_HEADERS: &[&str] = &["content-type", "cache-control", "content-disposition", "content-language"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StreamMode {
+    /// 每行一个 JSON 事件 (NDJSON)
+    Envelope,
+    /// 原样转发上游的状态码和响应内容
+    Raw,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct StreamOptions {
+    pub mode: StreamMode,
+    pub idle_timeout: Duration,
+    pub max_duration: Duration,
+}
+
+impl StreamOptions {
+    /// 解析 `stream`、`idle_timeout`、`max_duration` 参数, 未指定 `stream` 时返回 `None`
+    pub fn from_params(
+        config: &StreamConfig,
+        stream: Option<&str>,
+        idle_timeout: Option<u64>,
+        max_duration: Option<u64>,
+    ) -> Result<Option<Self>, AppError> {
+        let mode = match stream {
+            None => return Ok(None),
+            Some("envelope") => StreamMode::Envelope,
+            Some("raw") => StreamMode::Raw,
+            Some(other) => {
+                return Err(AppError::BadRequest(format!(
+                    "Unsupported stream mode '{}', expected envelope or raw",
+                    other
+                )));
+            }
+        };
+        let seconds = |value: Option<u64>, limit: u64| Duration::from_secs(value.unwrap_or(limit).clamp(1, limit.max(1)));
+        Ok(Some(Self {
+            mode,
+            idle_timeout: seconds(idle_timeout, config.idle_timeout_seconds),
+            max_duration: seconds(max_duration, config.max_duration_seconds),
+        }))
+    }
+}
+
+/// 边接收边转发响应内容, 不经过大小限制、VCR、历史记录和断言
+pub async fn stream_response(
+    state: &Arc<AppState>,
+    request: &CurlRequest,
+    options: StreamOptions,
+) -> Result<Response, AppError> {
+    if request.assertions.is_some() || request.checksum.is_some() {
+        return Err(AppError::BadRequest(
+            "Assertions and checksums are not supported in stream mode".to_string(),
+        ));
+    }
+    vcr::ensure_live(&state.config().vcr, "Stream mode")?;
+
+    let started = Instant::now();
+    let stream = spawn_curl_stream(state, request, options.idle_timeout).await?;
+    let status = stream
+        .status_code
+        .and_then(|code| StatusCode::from_u16(code).ok())
+        .unwrap_or(StatusCode::BAD_GATEWAY);
+    let content_type = stream.headers.get("content-type").cloned().unwrap_or_default();
+    info!("Streaming {} ({}, {:?} mode)", request.url, status, options.mode);
+
+    let (tx, rx) = mpsc::channel::<Bytes>(32);
+    let mut response = match options.mode {
+        StreamMode::Raw => {
+            let mut response = (status, body_from(rx)).into_response();
+            for name in PASSED_HEADERS {
+                if let Some(value) = stream.headers.get(*name).and_then(|value| HeaderValue::from_str(value).ok()) {
+                    response.headers_mut().insert(HeaderName::from_static(name), value);
+                }
+            }
+            response
+        }
+        StreamMode::Envelope => {
+            let mut response = body_from(rx).into_response();
+            response
+                .headers_mut()
+                .insert(header::CONTENT_TYPE, HeaderValue::from_static("application/x-ndjson"));
+            response
+        }
+    };
+    // 避免反向代理缓冲事件流
+    response
+        .headers_mut()
+        .insert(HeaderName::from_static("x-accel-buffering"), HeaderValue::from_static("no"));
+
+    let sse = content_type.to_ascii_lowercase().starts_with("text/event-stream");
+    let url = request.url.clone();
+    tokio::spawn(pump(stream, Sink::new(tx, options.mode, sse), options, started, url));
+    Ok(response)
+}
+
+fn body_from(rx: mpsc::Receiver<Bytes>) -> Body {
+    Body::from_stream(futures_util::stream::unfold(rx, |mut rx| async move {
+        rx.recv().await.map(|chunk| (Ok::<_, Infallible>(chunk), rx))
+    }))
+}
+
+/// 从 curl 读取响应内容并写入客户端, 直到上游结束、超时或客户端断开
+async fn pump(mut stream: CurlStream, mut sink: Sink, options: StreamOptions, started: Instant, url: String) {
+    let deadline = started + options.max_duration;
+
+    let mut start = json!({
+        "type": "start",
+        "status_code": stream.status_code,
+        "headers": stream.headers,
+        "curl_command": stream.curl_command,
+    });
+    if let Some(headers) = start["headers"].as_object_mut() {
+        headers.remove("status");
+    }
+    let initial = std::mem::take(&mut stream.initial);
+    if !sink.event(start).await || !sink.data(&initial).await {
+        return;
+    }
+
+    let mut chunk = vec![0u8; 16 * 1024];
+    let reason = loop {
+        if sink.failed() {
+            break "error";
+        }
+        let now = Instant::now();
+        if now >= deadline {
+            break "max_duration";
+        }
+        let wait = options.idle_timeout.min(deadline - now);
+        tokio::select! {
+            // 客户端断开时结束 curl 进程
+            _ = sink.tx.closed() => {
+                info!("Client disconnected from stream of {}", url);
+                return;
+            }
+            read = tokio::time::timeout(wait, stream.stdout.read(&mut chunk)) => match read {
+                Err(_) if Instant::now() >= deadline => break "max_duration",
+                Err(_) => break "idle_timeout",
+                Ok(Ok(0)) => break "eof",
+                Ok(Ok(read)) => {
+                    if !sink.data(&chunk[..read]).await {
+                        return;
+                    }
+                }
+                Ok(Err(e)) => {
+                    sink.event(json!({ "type": "error", "message": format!("Failed to read curl output: {}", e) })).await;
+                    break "error";
+                }
+            },
+        }
+    };
+
+    if reason != "eof" {
+        let _ = stream.child.start_kill();
+    }
+    let exit_code = stream.child.wait().await.ok().and_then(|status| status.code());
+    sink.flush().await;
+    info!(
+        "Stream of {} ended ({}) after {} bytes, {} events",
+        url, reason, sink.bytes, sink.events
+    );
+    sink.event(json!({
+        "type": "end",
+        "reason": reason,
+        "exit_code": exit_code,
+        "bytes": sink.bytes,
+        "events": sink.events,
+        "elapsed_ms": started.elapsed().as_millis() as u64,
+    }))
+    .await;
+}
+
+/// 按模式写入客户端; envelope 模式下 SSE 按事件、其他内容按数据块包装为 JSON 行
+struct Sink {
+    tx: mpsc::Sender<Bytes>,
+    mode: StreamMode,
+    sse: Option<SseParser>,
+    /// 跨数据块的不完整 UTF-8 字符
+    pending: Vec<u8>,
+    bytes: u64,
+    events: u64,
+}
+
+impl Sink {
+    fn new(tx: mpsc::Sender<Bytes>, mode: StreamMode, sse: bool) -> Self {
+        Self {
+            tx,
+            mode,
+            sse: sse.then(SseParser::default),
+            pending: Vec::new(),
+            bytes: 0,
+            events: 0,
+        }
+    }
+
+    /// 客户端断开时返回 false
+    async fn send(&self, chunk: Bytes) -> bool {
+        self.tx.send(chunk).await.is_ok()
+    }
+
+    /// envelope 模式下写入一行 JSON, raw 模式下忽略
+    async fn event(&mut self, event: Value) -> bool {
+        if self.mode == StreamMode::Raw {
+            return true;
+        }
+        let mut line = event.to_string();
+        line.push('\n');
+        self.send(Bytes::from(line)).await
+    }
+
+    async fn data(&mut self, data: &[u8]) -> bool {
+        if data.is_empty() {
+            return true;
+        }
+        self.bytes += data.len() as u64;
+        if self.mode == StreamMode::Raw {
+            return self.send(Bytes::copy_from_slice(data)).await;
+        }
+
+        if let Some(parser) = &mut self.sse {
+            let events = parser.feed(data);
+            for event in events {
+                self.events += 1;
+                if !self.event(event).await {
+                    return false;
+                }
+            }
+            return true;
+        }
+
+        self.pending.extend_from_slice(data);
+        let chunk = match std::str::from_utf8(&self.pending) {
+            Ok(text) => json!({ "type": "chunk", "data": text }),
+            // 末尾的字符不完整, 留到下一个数据块
+            Err(e) if e.error_len().is_none() => {
+                let valid = e.valid_up_to();
+                if valid == 0 {
+                    return true;
+                }
+                let text = String::from_utf8_lossy(&self.pending[..valid]).into_owned();
+                self.pending.drain(..valid);
+                self.events += 1;
+                return self.event(json!({ "type": "chunk", "data": text })).await;
+            }
+            Err(_) => json!({ "type": "chunk", "encoding": "hex", "data": hex::encode(&self.pending) }),
+        };
+        self.pending.clear();
+        self.events += 1;
+        self.event(chunk).await
+    }
+
+    /// SSE 内容超出上限, 流应结束
+    fn failed(&self) -> bool {
+        self.sse.as_ref().is_some_and(|parser| parser.failed)
+    }
+
+    /// 输出剩余的不完整字符; 按 SSE 规范, 没有以空行结束的事件丢弃
+    async fn flush(&mut self) {
+        if !self.pending.is_empty() {
+            let chunk = json!({ "type": "chunk", "encoding": "hex", "data": hex::encode(&self.pending) });
+            self.pending.clear();
+            self.events += 1;
+            self.event(chunk).await;
+        }
+    }
+}
+
+/// 单行及单个事件的数据上限, 超出时输出 error 事件并结束
+const MAX_SSE_LINE_BYTES: usize = 1024 * 1024;
+
+/// 按 `text/event-stream` 格式拆分事件
+#[derive(Default)]
+struct SseParser {
+    line: Vec<u8>,
+    /// 上一个字节是 `\r`, 紧随的 `\n` 属于同一个换行
+    after_cr: bool,
+    event: Option<String>,
+    data: Vec<String>,
+    data_len: usize,
+    id: Option<String>,
+    retry: Option<u64>,
+    /// 超出上限后不再解析
+    failed: bool,
+}
+
+impl SseParser {
+    /// `\r\n`、`\n`、`\r` 都是行结束符
+    fn feed(&mut self, data: &[u8]) -> Vec<Value> {
+        let mut events = Vec::new();
+        for &byte in data {
+            if self.failed {
+                break;
+            }
+            let after_cr = std::mem::replace(&mut self.after_cr, byte == b'\r');
+            match byte {
+                b'\n' if after_cr => {}
+                b'\r' | b'\n' => events.extend(self.end_line()),
+                _ if self.line.len() >= MAX_SSE_LINE_BYTES => events.push(self.fail("line")),
+                _ => self.line.push(byte),
+            }
+        }
+        events
+    }
+
+    fn fail(&mut self, what: &str) -> Value {
+        self.failed = true;
+        json!({
+            "type": "error",
+            "message": format!("SSE {} exceeds {} bytes", what, MAX_SSE_LINE_BYTES),
+        })
+    }
+
+    fn end_line(&mut self) -> Option<Value> {
+        let line = String::from_utf8_lossy(&self.line).into_owned();
+        self.line.clear();
+        if line.is_empty() {
+            return self.dispatch();
+        }
+        // 以冒号开头的是注释 (常用作心跳)
+        if line.starts_with(':') {
+            return None;
+        }
+        let (field, value) = line.split_once(':').unwrap_or((line.as_str(), ""));
+        let value = value.strip_prefix(' ').unwrap_or(value).to_string();
+        match field {
+            "event" => self.event = Some(value),
+            "data" => {
+                self.data_len += value.len() + 1;
+                if self.data_len > MAX_SSE_LINE_BYTES {
+                    return Some(self.fail("event"));
+                }
+                self.data.push(value);
+            }
+            "id" => self.id = Some(value),
+            "retry" => self.retry = value.parse().ok(),
+            _ => {}
+        }
+        None
+    }
+
+    /// 空行结束一个事件, 没有 data 字段的事件不输出
+    fn dispatch(&mut self) -> Option<Value> {
+        let event = self.event.take();
+        let retry = self.retry.take();
+        if self.data.is_empty() {
+            return None;
+        }
+        self.data_len = 0;
+        let data = std::mem::take(&mut self.data).join("\n");
+        Some(json!({
+            "type": "event",
+            "event": event.unwrap_or_else(|| "message".to_string()),
+            "data": data,
+            "id": self.id,
+            "retry": retry,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sse_events_are_split_across_chunks() {
+        let mut parser = SseParser::default();
+        assert!(parser.feed(b": ping\n\nevent: update\nid: 7\nda").is_empty());
+        let events = parser.feed(b"ta: one\ndata:two\nretry: 1000\n\n");
+        assert_eq!(
+            events,
+            vec![json!({ "type": "event", "event": "update", "data": "one\ntwo", "id": "7", "retry": 1000 })]
+        );
+        // 没有 data 的事件不输出, id 保留到后续事件
+        assert_eq!(
+            parser.feed(b"event: empty\n\ndata: three\n\n"),
+            vec![json!({ "type": "event", "event": "message", "data": "three", "id": "7", "retry": null })]
+        );
+    }
+
+    #[test]
+    fn sse_accepts_cr_lf_and_crlf_line_endings() {
+        let mut parser = SseParser::default();
+        let events = parser.feed(b"data: a\r\rdata: b\r\n\r\ndata: c\n\n");
+        let data: Vec<&str> = events.iter().map(|event| event["data"].as_str().unwrap()).collect();
+        assert_eq!(data, ["a", "b", "c"]);
+
+        // `\r\n` 被拆到两个数据块中时只算一个换行
+        let mut parser = SseParser::default();
+        assert!(parser.feed(b"data: d\r").is_empty());
+        assert_eq!(parser.feed(b"\n\r\n").len(), 1);
+    }
+
+    #[test]
+    fn sse_overlong_lines_fail_the_stream() {
+        let mut parser = SseParser::default();
+        let events = parser.feed(&vec![b'x'; MAX_SSE_LINE_BYTES + 1]);
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0]["type"], "error");
+        assert!(parser.failed);
+        assert!(parser.feed(b"\n\ndata: late\n\n").is_empty());
+    }
+
+    #[test]
+    fn sse_oversized_events_fail_the_stream() {
+        let mut parser = SseParser::default();
+        let line = format!("data: {}\n", "y".repeat(MAX_SSE_LINE_BYTES / 2));
+        assert!(parser.feed(line.as_bytes()).is_empty());
+        let events = parser.feed(line.as_bytes());
+        assert_eq!(events[0]["type"], "error");
+        assert!(parser.failed);
+    }
+}
@@ -0,0 +1,297 @@
+use axum::{
+    body::Bytes,
+    extract::{Json, Path, Query, State},
+    response::Response,
+};
+use regex::Regex;
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+use std::{
+    collections::{BTreeSet, HashMap, HashSet},
+    sync::{Arc, LazyLock},
+};
+use tracing::info;
+
+use crate::{
+    config::{Config, TemplateConfig},
+    errors::AppError,
+    format::OutputFormat,
+    handlers::curl_response,
+    history,
+    links::{rewrite_response_links, PublicBaseUrl},
+    models::{AppState, CurlRequest},
+    services::execute_curl_command,
+};
+
+/// `{{name}}` 形式的占位符, 两侧允许空白
+static PLACEHOLDER: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\{\{\s*([A-Za-z0-9_.-]+)\s*\}\}").expect("valid placeholder regex"));
+
+#[derive(Debug, Deserialize)]
+pub struct RunQuery {
+    env: Option<String>,
+    format: Option<String>,
+}
+
+/// `POST /templates/{name}/run` 的可选请求体
+#[derive(Debug, Default, Deserialize)]
+pub struct RunRequest {
+    /// 本次运行的变量, 优先级最高
+    #[serde(default)]
+    variables: HashMap<String, String>,
+}
+
+/// 校验模板名称唯一, 且请求描述 (占位符未替换时) 的结构合法
+pub fn validate_templates(config: &Config) -> anyhow::Result<()> {
+    let mut names = HashSet::new();
+    for template in &config.templates {
+        if !names.insert(template.name.as_str()) {
+            return Err(anyhow::anyhow!("Duplicate template name '{}'", template.name));
+        }
+        serde_json::from_value::<CurlRequest>(template.request.clone())
+            .map_err(|e| anyhow::anyhow!("Invalid request in template '{}': {}", template.name, e))?;
+    }
+    Ok(())
+}
+
+/// `GET /templates`: 模板列表及其使用的变量
+pub async fn list_templates(State(state): State<Arc<AppState>>) -> Json<Value> {
+    let config = state.config();
+    let templates: Vec<Value> = config
+        .templates
+        .iter()
+        .map(|template| {
+            let mut variables = BTreeSet::new();
+            collect_placeholders(&template.request, &mut variables);
+            json!({
+                "name": template.name,
+                "description": template.description,
+                "variables": variables,
+            })
+        })
+        .collect();
+    let mut environments: Vec<&String> = config.environments.keys().collect();
+    environments.sort();
+
+    Json(json!({
+        "templates": templates,
+        "environments": environments,
+    }))
+}
+
+/// `POST /templates/{name}/run?env=`: 填充变量后按 `POST /curl` 的流程执行
+pub async fn run_template(
+    State(state): State<Arc<AppState>>,
+    PublicBaseUrl(public_base): PublicBaseUrl,
+    Path(name): Path<String>,
+    Query(query): Query<RunQuery>,
+    body: Bytes,
+) -> Result<Response, AppError> {
+    let format = OutputFormat::from_param(query.format.as_deref())?;
+    let run: RunRequest = if body.is_empty() {
+        RunRequest::default()
+    } else {
+        serde_json::from_slice(&body).map_err(|e| AppError::BadRequest(format!("Invalid request body: {}", e)))?
+    };
+
+    let config = state.config();
+    let template = config
+        .templates
+        .iter()
+        .find(|template| template.name == name)
+        .ok_or_else(|| AppError::NotFound(format!("Template '{}' not found", name)))?;
+    let mut request = render_template(&config, template, query.env.as_deref(), &run.variables)?;
+    request.alpn |= format.is_diagnostic();
+
+    info!(
+        "Running template '{}' (env {}) against {}",
+        template.name,
+        query.env.as_deref().unwrap_or("none"),
+        request.display_url()
+    );
+    let response = execute_curl_command(&state, &request).await?;
+    let mut response = history::record(&state, &request, response).await;
+    rewrite_response_links(&mut response, public_base.as_deref());
+    Ok(curl_response(&request, response, format))
+}
+
+/// 替换模板中的占位符, 变量优先级: 请求 > 环境 > 模板默认值
+fn render_template(
+    config: &Config,
+    template: &TemplateConfig,
+    env: Option<&str>,
+    overrides: &HashMap<String, String>,
+) -> Result<CurlRequest, AppError> {
+    let environment = match env {
+        Some(env) => Some(
+            config
+                .environments
+                .get(env)
+                .ok_or_else(|| AppError::BadRequest(format!("Unknown environment '{}'", env)))?,
+        ),
+        None => None,
+    };
+
+    // 只解析实际用到的变量, 未使用的环境变量缺失不影响运行; 来自配置 (环境和模板默认值) 的值都视为敏感值
+    let mut names = BTreeSet::new();
+    collect_placeholders(&template.request, &mut names);
+    let mut values = HashMap::new();
+    let mut secret_values = Vec::new();
+    for name in names {
+        if let Some(value) = overrides.get(&name) {
+            values.insert(name, value.clone());
+            continue;
+        }
+        let Some(secret) = environment
+            .and_then(|environment| environment.get(&name))
+            .or_else(|| template.variables.get(&name))
+        else {
+            continue;
+        };
+        let value = secret
+            .resolve()
+            .map_err(|e| AppError::InternalServerError(format!("Failed to resolve variable '{}': {}", name, e)))?;
+        secret_values.push(value.clone());
+        values.insert(name, value);
+    }
+    let lookup = |name: &str| values.get(name).cloned();
+
+    let mut request = template.request.clone();
+    let mut missing = BTreeSet::new();
+    substitute(&mut request, &lookup, &mut missing);
+    if !missing.is_empty() {
+        let missing: Vec<String> = missing.into_iter().collect();
+        return Err(AppError::BadRequest(format!(
+            "Missing variables for template '{}': {}",
+            template.name,
+            missing.join(", ")
+        )));
+    }
+
+    let mut request: CurlRequest = serde_json::from_value(request).map_err(|e| {
+        AppError::BadRequest(format!("Invalid request after rendering template '{}': {}", template.name, e))
+    })?;
+    request.secret_values = secret_values;
+    Ok(request)
+}
+
+fn substitute(value: &mut Value, lookup: &dyn Fn(&str) -> Option<String>, missing: &mut BTreeSet<String>) {
+    match value {
+        Value::String(text) => *text = substitute_str(text, lookup, missing),
+        Value::Array(items) => {
+            for item in items {
+                substitute(item, lookup, missing);
+            }
+        }
+        Value::Object(map) => {
+            // 键名 (例如请求头名称) 中同样允许占位符
+            let mut rendered = Map::new();
+            for (key, mut item) in std::mem::take(map) {
+                substitute(&mut item, lookup, missing);
+                rendered.insert(substitute_str(&key, lookup, missing), item);
+            }
+            *map = rendered;
+        }
+        _ => {}
+    }
+}
+
+fn substitute_str(text: &str, lookup: &dyn Fn(&str) -> Option<String>, missing: &mut BTreeSet<String>) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut last = 0;
+    for captures in PLACEHOLDER.captures_iter(text) {
+        let (Some(whole), Some(name)) = (captures.get(0), captures.get(1)) else {
+            continue;
+        };
+        result.push_str(&text[last..whole.start()]);
+        match lookup(name.as_str()) {
+            Some(value) => result.push_str(&value),
+            None => {
+                missing.insert(name.as_str().to_string());
+                result.push_str(whole.as_str());
+            }
+        }
+        last = whole.end();
+    }
+    result.push_str(&text[last..]);
+    result
+}
+
+fn collect_placeholders(value: &Value, names: &mut BTreeSet<String>) {
+    match value {
+        Value::String(text) => collect_placeholders_in(text, names),
+        Value::Array(items) => items.iter().for_each(|item| collect_placeholders(item, names)),
+        Value::Object(map) => {
+            for (key, item) in map {
+                collect_placeholders_in(key, names);
+                collect_placeholders(item, names);
+            }
+        }
+        _ => {}
+    }
+}
+
+fn collect_placeholders_in(text: &str, names: &mut BTreeSet<String>) {
+    for captures in PLACEHOLDER.captures_iter(text) {
+        names.insert(captures[1].to_string());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> Config {
+        toml::from_str(
+            r#"
+            [server]
+            listen_addr = "127.0.0.1:0"
+            [proxy]
+            temp_file_host_url = "http://localhost:3001"
+            max_response_size_bytes = 1024
+            [curl]
+            timeout_seconds = 5
+            follow_redirects = true
+            include_headers = true
+
+            [environments.staging]
+            base_url = "https://staging.example.com"
+            api_key = { env = "CURL2URL_TEST_TEMPLATE_KEY" }
+
+            [[templates]]
+            name = "get-user"
+            variables = { user = "1" }
+            request = { url = "{{ base_url }}/users/{{user}}?key={{api_key}}", headers = { "X-Trace" = "{{trace}}" } }
+            "#,
+        )
+        .expect("valid test config")
+    }
+
+    #[test]
+    fn renders_variables_and_tracks_config_values() {
+        // SAFETY: 只有这个测试读取该环境变量
+        unsafe { std::env::set_var("CURL2URL_TEST_TEMPLATE_KEY", "k-123") };
+        let config = config();
+        let template = &config.templates[0];
+        let overrides = HashMap::from([("trace".to_string(), "abc".to_string())]);
+
+        let request = render_template(&config, template, Some("staging"), &overrides).unwrap();
+        assert_eq!(request.url, "https://staging.example.com/users/1?key=k-123");
+        assert_eq!(request.headers, vec![("X-Trace".to_string(), "abc".to_string())]);
+        // 请求中传入的变量不脱敏, 来自配置的值 (无论内联还是环境变量) 都脱敏
+        let mut secret_values = request.secret_values.clone();
+        secret_values.sort();
+        assert_eq!(secret_values, vec!["1", "https://staging.example.com", "k-123"]);
+        assert_eq!(request.display_url(), "******/users/******?key=******");
+        assert_eq!(request.display_headers(), vec![("X-Trace", "abc".to_string())]);
+    }
+
+    #[test]
+    fn reports_missing_variables_and_unknown_environments() {
+        let config = config();
+        let template = &config.templates[0];
+        let error = render_template(&config, template, None, &HashMap::new()).unwrap_err();
+        assert!(error.to_string().contains("api_key, base_url, trace"), "{}", error);
+        assert!(render_template(&config, template, Some("prod"), &HashMap::new()).is_err());
+    }
+}
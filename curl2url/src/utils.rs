@@ -1,2 +1,228 @@
 // 重新导出共享工具函数
-pub use utils_share::http::parse_curl_response; 
\ No newline at end of file
+pub use utils_share::http::parse_curl_response;
+
+use crate::models::{CurlMeta, RedirectHop};
+
+/// curl 输出末尾追加元数据时使用的分隔符
+pub const META_MARKER: &str = "\n--curl2url-meta--";
+
+/// 从 curl 输出中拆分出响应内容和 `--write-out` 元数据
+pub fn split_curl_output(stdout: &[u8]) -> (&[u8], Option<CurlMeta>) {
+    let marker = META_MARKER.as_bytes();
+    let position = stdout
+        .windows(marker.len())
+        .rposition(|window| window == marker);
+
+    match position {
+        Some(position) => (
+            &stdout[..position],
+            serde_json::from_slice(&stdout[position + marker.len()..]).ok(),
+        ),
+        None => (stdout, None),
+    }
+}
+
+/// 将 `-i`/`-D` 输出的响应头按响应拆分, 重定向时每一跳各有一段
+pub fn header_blocks(dump: &str) -> Vec<&str> {
+    dump.split("\r\n\r\n")
+        .flat_map(|block| block.split("\n\n"))
+        .filter(|block| !block.trim().is_empty())
+        .collect()
+}
+
+/// `-D` 输出的响应头中可能包含多段 (重定向), 只取最后一段
+pub fn last_header_block(dump: &str) -> &str {
+    header_blocks(dump).last().copied().unwrap_or_default()
+}
+
+/// 从全部响应头中还原重定向经过的地址, `start_url` 为请求的原始地址
+pub fn redirect_hops(dump: &str, start_url: &str) -> Vec<RedirectHop> {
+    let mut hops = Vec::new();
+    let mut current = start_url.to_string();
+    let blocks = header_blocks(dump);
+    // 最后一段是最终响应
+    for block in &blocks[..blocks.len().saturating_sub(1)] {
+        let (headers, _) = parse_curl_response(block);
+        let status_code = headers
+            .get("status")
+            .and_then(|status| status.split_whitespace().nth(1))
+            .and_then(|code| code.parse::<u16>().ok());
+        // 只有带 Location 的 3xx 才算一跳, 跳过 100 Continue 等中间响应
+        let Some(location) = headers.get("location").filter(|_| matches!(status_code, Some(300..=399))) else {
+            continue;
+        };
+        let next = url::Url::parse(&current)
+            .and_then(|base| base.join(location))
+            .map(|next| next.to_string())
+            .unwrap_or_else(|_| location.clone());
+        hops.push(RedirectHop {
+            url: std::mem::replace(&mut current, next),
+            status_code,
+            location: Some(location.clone()),
+        });
+    }
+    hops
+}
+
+/// 从 `Content-Disposition` 中取文件名, `filename*` (RFC 5987) 优先
+pub fn filename_from_content_disposition(value: &str) -> Option<String> {
+    let mut plain = None;
+    for part in value.split(';').map(str::trim) {
+        let Some((name, raw)) = part.split_once('=') else {
+            continue;
+        };
+        match name.trim().to_ascii_lowercase().as_str() {
+            "filename*" => {
+                // 形如 UTF-8''report%20v2.pdf
+                let encoded = raw.trim().rsplit('\'').next().unwrap_or_default();
+                if let Ok(decoded) = urlencoding::decode(encoded)
+                    && let Some(filename) = clean_filename(&decoded)
+                {
+                    return Some(filename);
+                }
+            }
+            "filename" => plain = clean_filename(raw.trim().trim_matches('"')),
+            _ => {}
+        }
+    }
+    plain
+}
+
+/// 使用 URL 路径的最后一段作为文件名
+pub fn filename_from_url(raw_url: &str) -> Option<String> {
+    let parsed = url::Url::parse(raw_url).ok()?;
+    let segment = parsed.path_segments()?.next_back()?;
+    clean_filename(&urlencoding::decode(segment).ok()?)
+}
+
+/// 去掉路径分隔符和控制字符, 拒绝空文件名和 `.`/`..`
+fn clean_filename(raw: &str) -> Option<String> {
+    let filename: String = utils_share::http::sanitize_filename(raw)
+        .chars()
+        .filter(|c| !c.is_control())
+        .collect();
+    let filename = filename.trim();
+    (!filename.is_empty() && filename != "." && filename != "..").then(|| filename.to_string())
+}
+
+/// curl `-v` 输出中的说明 (`*`)、请求头 (`>`)、响应头 (`<`) 和数据摘要 (`{`、`}`) 行
+fn is_verbose_line(line: &str) -> bool {
+    matches!(line.chars().next(), Some('*' | '>' | '<' | '{' | '}'))
+}
+
+/// 去掉 `-v` 输出, 只保留 curl 自身的错误信息
+pub fn strip_verbose(stderr: &str) -> String {
+    stderr
+        .lines()
+        .filter(|line| !is_verbose_line(line))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// 从 `-v` 输出中取出服务端通过 ALPN 选定的协议, 有多次连接 (重定向) 时取最后一次
+pub fn alpn_from_verbose(stderr: &str) -> Option<String> {
+    let mut alpn = None;
+    for line in stderr.lines() {
+        let Some(info) = line.strip_prefix("* ") else {
+            continue;
+        };
+        if info.starts_with("Connected to ") {
+            alpn = None;
+        }
+        // 新版本为 `ALPN: server accepted h2`, 旧版本为 `ALPN, server accepted to use h2`
+        if let Some(rest) = info.strip_prefix("ALPN").and_then(|rest| rest.split_once("server accepted")) {
+            alpn = rest
+                .1
+                .trim()
+                .trim_start_matches("to use")
+                .split_whitespace()
+                .next()
+                .map(str::to_string);
+        }
+    }
+    alpn
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn content_disposition_prefers_the_encoded_filename() {
+        assert_eq!(
+            filename_from_content_disposition("attachment; filename=\"report.pdf\"; filename*=UTF-8''report%20v2.pdf").as_deref(),
+            Some("report v2.pdf")
+        );
+        assert_eq!(
+            filename_from_content_disposition("attachment; FILENAME=\"data.csv\"").as_deref(),
+            Some("data.csv")
+        );
+        assert_eq!(filename_from_content_disposition("inline"), None);
+    }
+
+    #[test]
+    fn filenames_cannot_escape_the_directory() {
+        assert_eq!(
+            filename_from_content_disposition("attachment; filename=\"../../etc/passwd\"").as_deref(),
+            Some(".._.._etc_passwd")
+        );
+        assert_eq!(filename_from_content_disposition("attachment; filename=\"..\""), None);
+        assert_eq!(filename_from_content_disposition("attachment; filename*=UTF-8''%0A"), None);
+    }
+
+    #[test]
+    fn filenames_fall_back_to_the_last_path_segment() {
+        assert_eq!(
+            filename_from_url("https://example.com/files/tool%201.2.tar.gz?download=1").as_deref(),
+            Some("tool 1.2.tar.gz")
+        );
+        assert_eq!(filename_from_url("https://example.com/"), None);
+        assert_eq!(filename_from_url("not a url"), None);
+    }
+
+    #[test]
+    fn redirect_hops_follow_locations_between_header_blocks() {
+        let dump = "HTTP/1.1 301 Moved Permanently\r\nLocation: /v2/\r\n\r\n\
+                    HTTP/1.1 100 Continue\r\n\r\n\
+                    HTTP/1.1 302 Found\r\nLocation: https://cdn.example.com/file\r\n\r\n\
+                    HTTP/1.1 200 OK\r\nContent-Type: text/plain\r\n\r\n";
+        let hops = redirect_hops(dump, "http://example.com/v1");
+        let summary: Vec<(&str, Option<u16>, Option<&str>)> = hops
+            .iter()
+            .map(|hop| (hop.url.as_str(), hop.status_code, hop.location.as_deref()))
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                ("http://example.com/v1", Some(301), Some("/v2/")),
+                ("http://example.com/v2/", Some(302), Some("https://cdn.example.com/file")),
+            ]
+        );
+    }
+
+    #[test]
+    fn redirect_hops_are_empty_without_redirects() {
+        assert!(redirect_hops("HTTP/1.1 200 OK\r\n\r\n", "http://example.com/").is_empty());
+        assert!(redirect_hops("", "http://example.com/").is_empty());
+    }
+
+    #[test]
+    fn alpn_is_read_from_new_and_old_verbose_formats() {
+        assert_eq!(
+            alpn_from_verbose("* Connected to example.com (1.2.3.4) port 443\n* ALPN: server accepted h2\n> GET / HTTP/2").as_deref(),
+            Some("h2")
+        );
+        assert_eq!(
+            alpn_from_verbose("* Connected to example.com (1.2.3.4) port 443 (#0)\n* ALPN, server accepted to use http/1.1").as_deref(),
+            Some("http/1.1")
+        );
+        assert_eq!(alpn_from_verbose("* ALPN: curl offers h2,http/1.1\n* ALPN: server did not agree on a protocol"), None);
+    }
+
+    #[test]
+    fn alpn_is_taken_from_the_last_connection() {
+        let verbose = "* Connected to a.example (1.2.3.4) port 443\n* ALPN: server accepted h2\n\
+                       < HTTP/2 301\n* Connected to b.example (5.6.7.8) port 80\n< HTTP/1.1 200 OK";
+        assert_eq!(alpn_from_verbose(verbose), None);
+    }
+}
@@ -0,0 +1,271 @@
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use tracing::info;
+
+use crate::{
+    command::{is_sensitive_header, mask_values, MASK},
+    config::{VcrConfig, VcrMode},
+    errors::AppError,
+    models::{CurlRequest, CurlResponse},
+};
+
+/// 磁盘上保存的一组请求/响应
+#[derive(Debug, Serialize, Deserialize)]
+struct Fixture {
+    key: String,
+    /// 参与匹配的请求字段, 便于人工查看; 敏感请求头和 secret 值已脱敏, 不能用于重新计算 key
+    request: Vec<(String, String)>,
+    response: CurlResponse,
+}
+
+/// 按 `match_on` 规则提取参与匹配的请求字段
+fn match_fields(config: &VcrConfig, request: &CurlRequest) -> Vec<(String, String)> {
+    config
+        .match_on
+        .iter()
+        .map(|rule| {
+            let value = match rule.as_str() {
+                "method" => request.method.to_ascii_uppercase(),
+                "url" => request.url.clone(),
+                "body" => request
+                    .body
+                    .as_ref()
+                    .and_then(|body| serde_json::to_string(body).ok())
+                    .unwrap_or_default(),
+                "profile" => request.profile.clone().unwrap_or_default(),
+                rule => match rule.strip_prefix("header:") {
+                    Some(name) => request
+                        .headers
+                        .iter()
+                        .filter(|(header, _)| header.eq_ignore_ascii_case(name))
+                        .map(|(_, value)| value.as_str())
+                        .collect::<Vec<_>>()
+                        .join(", "),
+                    None => String::new(),
+                },
+            };
+            (rule.clone(), value)
+        })
+        .collect()
+}
+
+/// 写入 fixture 的字段, 敏感请求头整体脱敏, 其余字段中的 secret 值替换为掩码
+fn display_fields(request: &CurlRequest, fields: &[(String, String)]) -> Vec<(String, String)> {
+    fields
+        .iter()
+        .map(|(rule, value)| {
+            let sensitive = rule
+                .strip_prefix("header:")
+                .is_some_and(|name| is_sensitive_header(name) && !value.is_empty());
+            let value = if sensitive {
+                MASK.to_string()
+            } else {
+                mask_values(value, &request.secret_values)
+            };
+            (rule.clone(), value)
+        })
+        .collect()
+}
+
+fn fixture_key(fields: &[(String, String)]) -> String {
+    let joined: Vec<String> = fields
+        .iter()
+        .map(|(rule, value)| format!("{}={}", rule, value))
+        .collect();
+    utils_share::crypto::sha256_hash(joined.join("\n").as_bytes())
+}
+
+fn fixture_path(config: &VcrConfig, key: &str) -> PathBuf {
+    PathBuf::from(&config.fixtures_dir).join(format!("{}.json", key))
+}
+
+/// 校验 `match_on` 中的规则
+pub fn validate_rules(config: &VcrConfig) -> anyhow::Result<()> {
+    for rule in &config.match_on {
+        let valid = matches!(rule.as_str(), "method" | "url" | "body" | "profile")
+            || rule.strip_prefix("header:").is_some_and(|name| !name.is_empty());
+        if !valid {
+            return Err(anyhow::anyhow!(
+                "Invalid vcr.match_on rule '{}', expected method, url, body, profile or header:<name>",
+                rule
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// 回放模式下拒绝无法从 fixture 返回的功能 (流式、浏览、快照、爬取、WebSocket), 避免访问网络
+pub fn ensure_live(config: &VcrConfig, feature: &str) -> Result<(), AppError> {
+    if config.mode == VcrMode::Replay {
+        return Err(AppError::BadRequest(format!(
+            "{} is not available in VCR replay mode",
+            feature
+        )));
+    }
+    Ok(())
+}
+
+/// 回放模式: 读取匹配的 fixture, 没有匹配时直接报错而不访问网络
+pub async fn replay(config: &VcrConfig, request: &CurlRequest) -> Result<CurlResponse, AppError> {
+    let fields = match_fields(config, request);
+    let key = fixture_key(&fields);
+    let path = fixture_path(config, &key);
+
+    let content = tokio::fs::read(&path).await.map_err(|_| {
+        AppError::InternalServerError(format!(
+            "VCR replay: no fixture recorded for {} {} (key {}, match_on {:?})",
+            request.method, request.display_url(), key, config.match_on
+        ))
+    })?;
+    let fixture: Fixture = serde_json::from_slice(&content).map_err(|e| {
+        AppError::InternalServerError(format!("VCR replay: invalid fixture {:?}: {}", path, e))
+    })?;
+
+    info!("VCR replay: serving {} {} from {:?}", request.method, request.display_url(), path);
+    Ok(fixture.response)
+}
+
+/// 录制模式: 将请求与响应写入 fixture 文件, 相同 key 会覆盖旧的记录
+pub async fn record(config: &VcrConfig, request: &CurlRequest, response: &CurlResponse) -> Result<(), AppError> {
+    let fields = match_fields(config, request);
+    let key = fixture_key(&fields);
+    let path = fixture_path(config, &key);
+
+    let fixture = Fixture {
+        key,
+        request: display_fields(request, &fields),
+        response: response.clone(),
+    };
+    let content = serde_json::to_vec_pretty(&fixture)
+        .map_err(|e| AppError::InternalServerError(format!("VCR record: failed to serialize fixture: {}", e)))?;
+
+    tokio::fs::create_dir_all(&config.fixtures_dir)
+        .await
+        .map_err(|e| AppError::InternalServerError(format!("VCR record: failed to create fixtures dir: {}", e)))?;
+    tokio::fs::write(&path, content)
+        .await
+        .map_err(|e| AppError::InternalServerError(format!("VCR record: failed to write {:?}: {}", path, e)))?;
+
+    info!("VCR record: saved {} {} to {:?}", request.method, request.display_url(), path);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::body::RequestBody;
+
+    fn config(match_on: &[&str], fixtures_dir: &str) -> VcrConfig {
+        VcrConfig {
+            mode: VcrMode::Replay,
+            fixtures_dir: fixtures_dir.to_string(),
+            match_on: match_on.iter().map(|rule| rule.to_string()).collect(),
+        }
+    }
+
+    fn request() -> CurlRequest {
+        CurlRequest {
+            url: "https://api.example.com/items?key=k-123".to_string(),
+            method: "post".to_string(),
+            headers: vec![
+                ("Authorization".to_string(), "Bearer t0ken".to_string()),
+                ("X-Tenant".to_string(), "acme".to_string()),
+                ("x-tenant".to_string(), "beta".to_string()),
+            ],
+            body: Some(RequestBody::Raw { content: "{}".to_string() }),
+            secret_values: vec!["k-123".to_string()],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn match_fields_follow_the_rules() {
+        let config = config(&["method", "url", "header:x-tenant", "header:accept", "profile"], "fixtures");
+        let fields = match_fields(&config, &request());
+        assert_eq!(
+            fields,
+            vec![
+                ("method".to_string(), "POST".to_string()),
+                ("url".to_string(), "https://api.example.com/items?key=k-123".to_string()),
+                ("header:x-tenant".to_string(), "acme, beta".to_string()),
+                ("header:accept".to_string(), String::new()),
+                ("profile".to_string(), String::new()),
+            ]
+        );
+    }
+
+    #[test]
+    fn fixture_key_depends_on_the_matched_fields_only() {
+        let config = config(&["method", "url"], "fixtures");
+        let mut other = request();
+        other.headers.clear();
+        other.body = None;
+        assert_eq!(
+            fixture_key(&match_fields(&config, &request())),
+            fixture_key(&match_fields(&config, &other))
+        );
+
+        other.method = "GET".to_string();
+        let key = fixture_key(&match_fields(&config, &other));
+        assert_ne!(fixture_key(&match_fields(&config, &request())), key);
+        assert_eq!(key.len(), 64);
+    }
+
+    #[test]
+    fn recorded_fields_are_masked() {
+        let config = config(&["url", "header:authorization", "header:x-tenant"], "fixtures");
+        let request = request();
+        let fields = display_fields(&request, &match_fields(&config, &request));
+        assert_eq!(
+            fields,
+            vec![
+                ("url".to_string(), format!("https://api.example.com/items?key={}", MASK)),
+                ("header:authorization".to_string(), MASK.to_string()),
+                ("header:x-tenant".to_string(), "acme, beta".to_string()),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn record_then_replay() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = config(&["method", "url", "header:authorization"], dir.path().to_str().unwrap());
+        let response = CurlResponse {
+            status_code: Some(201),
+            ..Default::default()
+        };
+
+        record(&config, &request(), &response).await.unwrap();
+        let content = std::fs::read_dir(dir.path())
+            .unwrap()
+            .map(|entry| std::fs::read_to_string(entry.unwrap().path()).unwrap())
+            .collect::<String>();
+        assert!(!content.contains("t0ken") && !content.contains("k-123"));
+
+        assert_eq!(replay(&config, &request()).await.unwrap().status_code, Some(201));
+    }
+
+    #[tokio::test]
+    async fn replay_without_fixture_fails() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = config(&["method", "url"], dir.path().to_str().unwrap());
+        match replay(&config, &request()).await {
+            Err(AppError::InternalServerError(message)) => {
+                assert!(message.contains("no fixture recorded"));
+                assert!(!message.contains("k-123"));
+            }
+            other => panic!("unexpected result: {:?}", other.map(|response| response.status_code)),
+        }
+    }
+
+    #[test]
+    fn live_features_are_rejected_in_replay_mode() {
+        assert!(matches!(
+            ensure_live(&config(&[], "fixtures"), "Browse"),
+            Err(AppError::BadRequest(message)) if message == "Browse is not available in VCR replay mode"
+        ));
+        let mut record = config(&[], "fixtures");
+        record.mode = VcrMode::Record;
+        assert!(ensure_live(&record, "Browse").is_ok());
+    }
+}
@@ -0,0 +1,717 @@
+use axum::{
+    extract::{
+        ws::{rejection::WebSocketUpgradeRejection, CloseFrame, Message, WebSocket, WebSocketUpgrade},
+        Json, Query, State,
+    },
+    http::{HeaderMap, HeaderName, HeaderValue},
+    response::{IntoResponse, Response},
+};
+use futures_util::{SinkExt, StreamExt};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::{collections::HashMap, sync::Arc, time::Duration};
+use tokio::{net::TcpStream, time::Instant};
+use tokio_tungstenite::{
+    client_async_tls_with_config,
+    tungstenite::{self as ts, client::IntoClientRequest},
+    Connector, MaybeTlsStream, WebSocketStream,
+};
+use tracing::{info, warn};
+
+use crate::{
+    auth::{auth_headers, AuthSpec},
+    config::WebSocketConfig,
+    errors::AppError,
+    models::{AppState, CurlRequest, HttpVersion},
+    profiles::resolve_profile,
+    services::{connection_overrides, ConnectionOverrides},
+    ssrf::{check_target, resolve_target, PinnedTarget},
+    vcr,
+};
+
+/// 握手相关的请求头由 WebSocket 客户端生成, 不从调用方转发
+const SKIPPED_HEADERS: &[&str] = &[
+    "host",
+    "connection",
+    "upgrade",
+    "content-length",
+    "transfer-encoding",
+    "sec-websocket-key",
+    "sec-websocket-version",
+    "sec-websocket-extensions",
+    "sec-websocket-protocol",
+];
+
+/// 中继时从客户端转发给上游的请求头, 其余 (Cookie、Authorization 等) 不转发
+const FORWARDED_HEADERS: &[&str] = &["origin", "accept-language"];
+
+type UpstreamSocket = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+/// `POST /ws` 的请求体: 与 `POST /curl` 相同的请求描述, 加上要发送的消息
+#[derive(Debug, Deserialize)]
+pub struct ProbeRequest {
+    #[serde(flatten)]
+    pub request: CurlRequest,
+    /// 字符串原样发送, 其他 JSON 值序列化后作为文本消息发送
+    #[serde(default)]
+    pub messages: Vec<Value>,
+    #[serde(default)]
+    pub subprotocols: Vec<String>,
+    /// 发送后收集回复的秒数
+    pub wait: Option<u64>,
+    /// 收到指定数量的消息后提前结束
+    pub max_messages: Option<usize>,
+}
+
+/// 已连接的上游, 保留握手结果用于报告
+struct Upstream {
+    socket: UpstreamSocket,
+    status_code: u16,
+    headers: HashMap<String, String>,
+    subprotocol: Option<String>,
+    connect_ms: f64,
+}
+
+/// `GET /ws?url=`: WebSocket 请求时与上游双向中继; 普通请求时为一次性探测, 消息来自 `send` 参数
+pub async fn websocket(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<Vec<(String, String)>>,
+    headers: HeaderMap,
+    upgrade: Result<WebSocketUpgrade, WebSocketUpgradeRejection>,
+) -> Result<Response, AppError> {
+    vcr::ensure_live(&state.config().vcr, "WebSocket")?;
+    let params: HashMap<String, String> = query.iter().cloned().collect();
+    let request = websocket_request(&query, &params, &headers)?;
+    // 调用方请求的子协议原样转发, 上游选中的协议返回给调用方
+    let subprotocols: Vec<String> = headers
+        .get("sec-websocket-protocol")
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.split(',').map(|protocol| protocol.trim().to_string()).collect())
+        .unwrap_or_default();
+
+    let Ok(upgrade) = upgrade else {
+        let report = probe(
+            &state,
+            &request,
+            &values(&query, "send").into_iter().map(Value::String).collect::<Vec<_>>(),
+            &values(&query, "subprotocol"),
+            number(&params, "wait")?,
+            number(&params, "max_messages")?.map(|max| max as usize),
+        )
+        .await?;
+        return Ok(Json(report).into_response());
+    };
+
+    let config = state.config().websocket.clone();
+    let limits = relay_limits(&params, &config)?;
+    let upstream = connect(&state, &request, &subprotocols).await?;
+    info!("Relaying WebSocket to {}", request.url);
+
+    let url = request.url.clone();
+    let mut upgrade = upgrade.max_message_size(config.max_message_bytes);
+    if let Some(protocol) = upstream.subprotocol {
+        upgrade = upgrade.protocols([protocol]);
+    }
+    Ok(upgrade.on_upgrade(move |client| relay(client, upstream.socket, url, limits)))
+}
+
+/// 中继连接的时限和帧日志设置
+struct RelayLimits {
+    idle_timeout: Duration,
+    max_duration: Duration,
+    log_preview: Option<usize>,
+}
+
+/// 由 `/ws` 的查询参数构建请求, 只转发 `FORWARDED_HEADERS` 中的请求头
+fn websocket_request(
+    query: &[(String, String)],
+    params: &HashMap<String, String>,
+    headers: &HeaderMap,
+) -> Result<CurlRequest, AppError> {
+    Ok(CurlRequest {
+        url: params
+            .get("url")
+            .cloned()
+            .ok_or_else(|| AppError::BadRequest("Missing 'url' parameter".to_string()))?,
+        method: "GET".to_string(),
+        headers: FORWARDED_HEADERS
+            .iter()
+            .filter_map(|name| {
+                headers
+                    .get(*name)
+                    .and_then(|value| value.to_str().ok())
+                    .map(|value| (name.to_string(), value.to_string()))
+            })
+            .collect(),
+        profile: params.get("profile").cloned(),
+        auth: AuthSpec::from_params(params)?,
+        resolve: values(query, "resolve"),
+        connect_to: values(query, "connect_to"),
+        ..Default::default()
+    })
+}
+
+/// 同名查询参数的全部取值
+fn values(query: &[(String, String)], name: &str) -> Vec<String> {
+    query
+        .iter()
+        .filter(|(key, _)| key == name)
+        .map(|(_, value)| value.clone())
+        .collect()
+}
+
+/// 解析非负整数参数, 缺省时为 `None`
+fn number(params: &HashMap<String, String>, name: &str) -> Result<Option<u64>, AppError> {
+    params
+        .get(name)
+        .map(|value| {
+            value
+                .parse::<u64>()
+                .map_err(|_| AppError::BadRequest(format!("Invalid '{}' parameter: {}", name, value)))
+        })
+        .transpose()
+}
+
+/// 与 `wait` 相同, 请求参数只能缩短配置的时限
+fn relay_limits(params: &HashMap<String, String>, config: &WebSocketConfig) -> Result<RelayLimits, AppError> {
+    let seconds = |value: Option<u64>, limit: u64| Duration::from_secs(value.unwrap_or(limit).clamp(1, limit.max(1)));
+    let log = params.get("log").is_some_and(|value| value == "true" || value == "1");
+    Ok(RelayLimits {
+        idle_timeout: seconds(number(params, "idle_timeout")?, config.relay_idle_timeout_seconds),
+        max_duration: seconds(number(params, "max_duration")?, config.relay_max_duration_seconds),
+        log_preview: log.then_some(config.log_preview_chars),
+    })
+}
+
+/// 一次性探测收集回复的时间, 不超过 `max_wait_seconds`
+fn wait_duration(wait: Option<u64>, config: &WebSocketConfig) -> Duration {
+    Duration::from_secs(wait.unwrap_or(config.wait_seconds).min(config.max_wait_seconds))
+}
+
+/// `POST /ws`: 一次性探测, 发送消息后在 `wait` 秒内收集回复, 返回完整记录
+pub async fn websocket_probe(
+    State(state): State<Arc<AppState>>,
+    Json(probe_request): Json<ProbeRequest>,
+) -> Result<Json<Value>, AppError> {
+    vcr::ensure_live(&state.config().vcr, "WebSocket")?;
+    let report = probe(
+        &state,
+        &probe_request.request,
+        &probe_request.messages,
+        &probe_request.subprotocols,
+        probe_request.wait,
+        probe_request.max_messages,
+    )
+    .await?;
+    Ok(Json(report))
+}
+
+async fn probe(
+    state: &Arc<AppState>,
+    request: &CurlRequest,
+    messages: &[Value],
+    subprotocols: &[String],
+    wait: Option<u64>,
+    max_messages: Option<usize>,
+) -> Result<Value, AppError> {
+    let config = state.config().websocket.clone();
+    let wait = wait_duration(wait, &config);
+    let started = Instant::now();
+    let mut upstream = connect(state, request, subprotocols).await?;
+    info!("Probing WebSocket {} with {} messages", request.url, messages.len());
+
+    let mut transcript = Vec::new();
+    let mut close = None;
+    let mut error = None;
+    let mut reason = "wait_elapsed";
+
+    for message in messages {
+        let text = match message {
+            Value::String(text) => text.clone(),
+            other => other.to_string(),
+        };
+        transcript.push(transcript_entry("sent", started, "text", json!(text), text.len()));
+        if let Err(e) = upstream.socket.send(ts::Message::text(text)).await {
+            error = Some(format!("Failed to send message: {}", e));
+            break;
+        }
+    }
+
+    let deadline = Instant::now() + wait;
+    let mut received = 0;
+    while error.is_none() {
+        if max_messages.is_some_and(|max| received >= max) {
+            reason = "max_messages";
+            break;
+        }
+        let message = match tokio::time::timeout_at(deadline, upstream.socket.next()).await {
+            Err(_) => break,
+            Ok(None) => {
+                reason = "closed";
+                break;
+            }
+            Ok(Some(Err(e))) => {
+                error = Some(e.to_string());
+                break;
+            }
+            Ok(Some(Ok(message))) => message,
+        };
+        match message {
+            ts::Message::Text(text) => {
+                received += 1;
+                transcript.push(transcript_entry("received", started, "text", json!(text.as_str()), text.len()));
+            }
+            ts::Message::Binary(data) => {
+                received += 1;
+                transcript.push(transcript_entry("received", started, "binary", json!(hex::encode(&data)), data.len()));
+            }
+            ts::Message::Ping(data) => {
+                transcript.push(transcript_entry("received", started, "ping", json!(hex::encode(&data)), data.len()));
+            }
+            ts::Message::Pong(data) => {
+                transcript.push(transcript_entry("received", started, "pong", json!(hex::encode(&data)), data.len()));
+            }
+            ts::Message::Close(frame) => {
+                let frame = frame.map(|frame| json!({ "code": u16::from(frame.code), "reason": frame.reason.as_str() }));
+                transcript.push(transcript_entry("received", started, "close", frame.clone().unwrap_or(Value::Null), 0));
+                close = frame;
+                reason = "closed";
+                break;
+            }
+            ts::Message::Frame(_) => {}
+        }
+    }
+    if reason != "closed" {
+        // 只等待很短时间, 上游不回应关闭帧时直接断开
+        let _ = tokio::time::timeout(Duration::from_secs(1), upstream.socket.close(None)).await;
+    }
+    if error.is_some() {
+        reason = "error";
+    }
+
+    Ok(json!({
+        "url": request.url,
+        "status_code": upstream.status_code,
+        "subprotocol": upstream.subprotocol,
+        "response_headers": upstream.headers,
+        "connect_ms": upstream.connect_ms,
+        "reason": reason,
+        "close": close,
+        "error": error,
+        "transcript": transcript,
+    }))
+}
+
+fn transcript_entry(direction: &str, started: Instant, kind: &str, data: Value, size: usize) -> Value {
+    json!({
+        "direction": direction,
+        "elapsed_ms": started.elapsed().as_secs_f64() * 1000.0,
+        "type": kind,
+        "data": data,
+        "size": size,
+    })
+}
+
+/// 与 `/curl` 相同的 profile、请求头、认证和内网地址策略, 直接连接检查过的地址
+async fn connect(state: &Arc<AppState>, request: &CurlRequest, subprotocols: &[String]) -> Result<Upstream, AppError> {
+    let url = url::Url::parse(&request.url).map_err(|e| AppError::BadRequest(format!("Invalid target URL: {}", e)))?;
+    if !matches!(url.scheme(), "ws" | "wss") {
+        return Err(AppError::BadRequest(format!(
+            "Unsupported scheme '{}', expected ws or wss",
+            url.scheme()
+        )));
+    }
+
+    let config = state.config();
+    let profile = resolve_profile(&config, &request.url, request.profile.as_deref())?;
+    if let Some(profile) = profile {
+        info!("Using request profile '{}' for {}", profile.name, request.url);
+    }
+    let curl_config = match profile {
+        Some(profile) => config.curl.merge(profile),
+        None => config.curl.clone(),
+    };
+    if curl_config.proxy.is_some() {
+        return Err(AppError::BadRequest(
+            "WebSocket connections through a proxy are not supported".to_string(),
+        ));
+    }
+    if request
+        .http_version
+        .is_some_and(|version| version != HttpVersion::Http11)
+    {
+        return Err(AppError::BadRequest(
+            "WebSocket connections only support http_version 1.1".to_string(),
+        ));
+    }
+    let auth = match request.auth.as_ref().or(profile.and_then(|profile| profile.auth.as_ref())) {
+        Some(spec) => auth_headers(state, spec).await?,
+        None => Vec::new(),
+    };
+
+    let ConnectionOverrides {
+        resolve_entries,
+        connect_to_entries,
+        ..
+    } = connection_overrides(request, profile)?;
+    let target = match check_target(&config.security, &request.url, &resolve_entries, &connect_to_entries).await? {
+        Some(target) => target,
+        None => resolve_target(&request.url, &resolve_entries, &connect_to_entries).await?,
+    };
+
+    // 请求头的优先级与 curl 命令一致: profile 头、user_agent 和认证覆盖请求中的同名头
+    let mut handshake = request
+        .url
+        .as_str()
+        .into_client_request()
+        .map_err(|e| AppError::BadRequest(format!("Invalid WebSocket URL: {}", e)))?;
+    let mut extra: Vec<(String, String)> = request.headers.clone();
+    if let Some(profile) = profile {
+        extra.extend(profile.headers.iter().map(|(name, value)| (name.clone(), value.clone())));
+    }
+    if let Some(user_agent) = &curl_config.user_agent {
+        extra.push(("User-Agent".to_string(), user_agent.clone()));
+    }
+    extra.extend(auth);
+    if !subprotocols.is_empty() {
+        extra.push(("Sec-WebSocket-Protocol".to_string(), subprotocols.join(", ")));
+    }
+    insert_headers(handshake.headers_mut(), extra)?;
+
+    let connector = match url.scheme() {
+        "wss" if curl_config.insecure || curl_config.ca_cert.is_some() => {
+            Some(Connector::NativeTls(tls_connector(curl_config.insecure, curl_config.ca_cert.as_deref())?))
+        }
+        _ => None,
+    };
+    let websocket_config = ts::protocol::WebSocketConfig::default()
+        .max_message_size(Some(config.websocket.max_message_bytes));
+
+    let started = Instant::now();
+    let timeout = Duration::from_secs(curl_config.timeout_seconds);
+    let stream = tcp_connect(&target, timeout).await?;
+    let (socket, response) = tokio::time::timeout(
+        timeout,
+        client_async_tls_with_config(handshake, stream, Some(websocket_config), connector),
+    )
+    .await
+    .map_err(|_| AppError::BadGateway(format!("WebSocket handshake with {} timed out", request.url)))?
+    .map_err(|e| match e {
+        ts::Error::Http(response) => AppError::BadGateway(format!(
+            "WebSocket handshake with {} failed: HTTP {}",
+            request.url,
+            response.status()
+        )),
+        e => AppError::BadGateway(format!("WebSocket handshake with {} failed: {}", request.url, e)),
+    })?;
+
+    let headers: HashMap<String, String> = response
+        .headers()
+        .iter()
+        .filter_map(|(name, value)| value.to_str().ok().map(|value| (name.to_string(), value.to_string())))
+        .collect();
+    Ok(Upstream {
+        socket,
+        status_code: response.status().as_u16(),
+        subprotocol: headers.get("sec-websocket-protocol").cloned(),
+        headers,
+        connect_ms: started.elapsed().as_secs_f64() * 1000.0,
+    })
+}
+
+/// 写入握手请求头, 后出现的同名头覆盖之前的; 跳过 `SKIPPED_HEADERS`, 子协议除外
+fn insert_headers(headers: &mut HeaderMap, extra: Vec<(String, String)>) -> Result<(), AppError> {
+    for (name, value) in extra {
+        if SKIPPED_HEADERS.contains(&name.to_ascii_lowercase().as_str()) && !name.eq_ignore_ascii_case("sec-websocket-protocol") {
+            continue;
+        }
+        let name = HeaderName::from_bytes(name.as_bytes())
+            .map_err(|_| AppError::BadRequest(format!("Invalid header name '{}'", name)))?;
+        let value = HeaderValue::from_str(&value)
+            .map_err(|_| AppError::BadRequest(format!("Invalid value for header '{}'", name)))?;
+        headers.insert(name, value);
+    }
+    Ok(())
+}
+
+/// 依次尝试解析出的地址
+async fn tcp_connect(target: &PinnedTarget, timeout: Duration) -> Result<TcpStream, AppError> {
+    let mut last_error = None;
+    for addr in &target.addrs {
+        match tokio::time::timeout(timeout, TcpStream::connect((*addr, target.port))).await {
+            Ok(Ok(stream)) => return Ok(stream),
+            Ok(Err(e)) => last_error = Some(e.to_string()),
+            Err(_) => last_error = Some("connection timed out".to_string()),
+        }
+    }
+    Err(AppError::BadGateway(format!(
+        "Failed to connect to {}:{}: {}",
+        target.host,
+        target.port,
+        last_error.unwrap_or_else(|| "no addresses".to_string())
+    )))
+}
+
+fn tls_connector(insecure: bool, ca_cert: Option<&str>) -> Result<native_tls::TlsConnector, AppError> {
+    let mut builder = native_tls::TlsConnector::builder();
+    builder.danger_accept_invalid_certs(insecure);
+    if let Some(path) = ca_cert {
+        let pem = std::fs::read(path)
+            .map_err(|e| AppError::InternalServerError(format!("Failed to read CA certificate {}: {}", path, e)))?;
+        let certificate = native_tls::Certificate::from_pem(&pem)
+            .map_err(|e| AppError::InternalServerError(format!("Invalid CA certificate {}: {}", path, e)))?;
+        builder.add_root_certificate(certificate);
+    }
+    builder
+        .build()
+        .map_err(|e| AppError::InternalServerError(format!("Failed to create TLS connector: {}", e)))
+}
+
+/// 双向转发消息, 任意一方关闭或超时时结束; ping/pong 由两端各自应答, 不转发
+async fn relay(client: WebSocket, upstream: UpstreamSocket, url: String, limits: RelayLimits) {
+    let (mut client_tx, mut client_rx) = client.split();
+    let (mut upstream_tx, mut upstream_rx) = upstream.split();
+    let deadline = Instant::now() + limits.max_duration;
+
+    let reason = loop {
+        let now = Instant::now();
+        if now >= deadline {
+            break "max_duration";
+        }
+        tokio::select! {
+            message = client_rx.next() => {
+                let Some(Ok(message)) = message else {
+                    break "client closed";
+                };
+                let closing = matches!(message, Message::Close(_));
+                let Some(message) = into_upstream(message) else {
+                    continue;
+                };
+                if let Some(chars) = limits.log_preview {
+                    log_frame(&url, "->", &message, chars);
+                }
+                if upstream_tx.send(message).await.is_err() || closing {
+                    break "client closed";
+                }
+            }
+            message = upstream_rx.next() => {
+                let message = match message {
+                    Some(Ok(message)) => message,
+                    Some(Err(e)) => {
+                        warn!("WebSocket relay to {} failed: {}", url, e);
+                        break "upstream error";
+                    }
+                    None => break "upstream closed",
+                };
+                if let Some(chars) = limits.log_preview {
+                    log_frame(&url, "<-", &message, chars);
+                }
+                let closing = matches!(message, ts::Message::Close(_));
+                let Some(message) = from_upstream(message) else {
+                    continue;
+                };
+                if client_tx.send(message).await.is_err() || closing {
+                    break "upstream closed";
+                }
+            }
+            _ = tokio::time::sleep(limits.idle_timeout.min(deadline - now)) => {
+                if Instant::now() < deadline {
+                    break "idle_timeout";
+                }
+            }
+        }
+    };
+
+    // 超时由本服务关闭两端, 关闭原因写入 close 帧
+    if matches!(reason, "idle_timeout" | "max_duration") {
+        let _ = client_tx
+            .send(Message::Close(Some(CloseFrame {
+                code: 1001,
+                reason: reason.into(),
+            })))
+            .await;
+        let _ = upstream_tx
+            .send(ts::Message::Close(Some(ts::protocol::CloseFrame {
+                code: ts::protocol::frame::coding::CloseCode::Away,
+                reason: reason.into(),
+            })))
+            .await;
+    }
+    info!("WebSocket relay to {} closed ({})", url, reason);
+}
+
+fn into_upstream(message: Message) -> Option<ts::Message> {
+    match message {
+        Message::Text(text) => Some(ts::Message::text(text.as_str())),
+        Message::Binary(data) => Some(ts::Message::Binary(data)),
+        Message::Close(frame) => Some(ts::Message::Close(frame.map(|frame| ts::protocol::CloseFrame {
+            code: frame.code.into(),
+            reason: frame.reason.as_str().into(),
+        }))),
+        Message::Ping(_) | Message::Pong(_) => None,
+    }
+}
+
+fn from_upstream(message: ts::Message) -> Option<Message> {
+    match message {
+        ts::Message::Text(text) => Some(Message::Text(text.as_str().into())),
+        ts::Message::Binary(data) => Some(Message::Binary(data)),
+        ts::Message::Close(frame) => Some(Message::Close(frame.map(|frame| CloseFrame {
+            code: frame.code.into(),
+            reason: frame.reason.as_str().into(),
+        }))),
+        ts::Message::Ping(_) | ts::Message::Pong(_) | ts::Message::Frame(_) => None,
+    }
+}
+
+fn log_frame(url: &str, direction: &str, message: &ts::Message, chars: usize) {
+    let (kind, preview) = match message {
+        ts::Message::Text(text) => ("text", text.chars().take(chars).collect::<String>()),
+        ts::Message::Binary(data) => ("binary", hex::encode(&data[..data.len().min(chars / 2)])),
+        ts::Message::Ping(_) => ("ping", String::new()),
+        ts::Message::Pong(_) => ("pong", String::new()),
+        ts::Message::Close(frame) => (
+            "close",
+            frame
+                .as_ref()
+                .map(|frame| format!("{} {}", u16::from(frame.code), frame.reason))
+                .unwrap_or_default(),
+        ),
+        ts::Message::Frame(_) => ("frame", String::new()),
+    };
+    info!("ws {} {} {} ({} bytes) {}", url, direction, kind, message.len(), preview);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn query(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs.iter().map(|(name, value)| (name.to_string(), value.to_string())).collect()
+    }
+
+    fn params(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        query(pairs).into_iter().collect()
+    }
+
+    fn config() -> WebSocketConfig {
+        WebSocketConfig {
+            wait_seconds: 5,
+            max_wait_seconds: 30,
+            relay_idle_timeout_seconds: 300,
+            relay_max_duration_seconds: 3600,
+            log_preview_chars: 80,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn builds_request_from_query() {
+        let query = query(&[
+            ("url", "wss://example.com/socket"),
+            ("profile", "internal"),
+            ("auth", "bearer"),
+            ("auth_secret", "token"),
+            ("resolve", "example.com:443:127.0.0.1"),
+            ("resolve", "other.test:443:127.0.0.2"),
+            ("connect_to", "example.com:443:backend.test:8443"),
+            ("send", "hello"),
+        ]);
+        let mut headers = HeaderMap::new();
+        headers.insert("origin", HeaderValue::from_static("https://app.example.com"));
+        headers.insert("cookie", HeaderValue::from_static("session=1"));
+        headers.insert("authorization", HeaderValue::from_static("Bearer caller"));
+        headers.insert("sec-websocket-key", HeaderValue::from_static("dGhlIHNhbXBsZSBub25jZQ=="));
+
+        let params: HashMap<String, String> = query.iter().cloned().collect();
+        let request = websocket_request(&query, &params, &headers).unwrap();
+        assert_eq!(request.url, "wss://example.com/socket");
+        assert_eq!(request.method, "GET");
+        assert_eq!(request.headers, vec![("origin".to_string(), "https://app.example.com".to_string())]);
+        assert_eq!(request.profile.as_deref(), Some("internal"));
+        assert!(matches!(request.auth, Some(AuthSpec::Bearer { ref secret }) if secret == "token"));
+        assert_eq!(request.resolve, vec!["example.com:443:127.0.0.1", "other.test:443:127.0.0.2"]);
+        assert_eq!(request.connect_to, vec!["example.com:443:backend.test:8443"]);
+        assert_eq!(values(&query, "send"), vec!["hello"]);
+    }
+
+    #[test]
+    fn rejects_invalid_query() {
+        let query = query(&[("send", "hello")]);
+        assert!(matches!(
+            websocket_request(&query, &params(&[("send", "hello")]), &HeaderMap::new()),
+            Err(AppError::BadRequest(_))
+        ));
+        assert!(matches!(number(&params(&[("wait", "-1")]), "wait"), Err(AppError::BadRequest(_))));
+        assert_eq!(number(&params(&[("wait", "3")]), "wait").unwrap(), Some(3));
+        assert_eq!(number(&params(&[]), "wait").unwrap(), None);
+    }
+
+    #[test]
+    fn skips_handshake_headers() {
+        let mut headers = HeaderMap::new();
+        insert_headers(
+            &mut headers,
+            vec![
+                ("Host".to_string(), "evil.test".to_string()),
+                ("Sec-WebSocket-Key".to_string(), "abc".to_string()),
+                ("Upgrade".to_string(), "h2c".to_string()),
+                ("Content-Length".to_string(), "10".to_string()),
+                ("Origin".to_string(), "https://app.example.com".to_string()),
+                ("User-Agent".to_string(), "caller".to_string()),
+                ("User-Agent".to_string(), "profile-agent".to_string()),
+                ("Sec-WebSocket-Protocol".to_string(), "chat, superchat".to_string()),
+            ],
+        )
+        .unwrap();
+
+        assert_eq!(headers.len(), 3);
+        assert_eq!(headers["origin"], "https://app.example.com");
+        // 后出现的 (profile、user_agent 和认证) 覆盖请求中的同名头
+        assert_eq!(headers["user-agent"], "profile-agent");
+        assert_eq!(headers["sec-websocket-protocol"], "chat, superchat");
+
+        assert!(matches!(
+            insert_headers(&mut headers, vec![("bad header".to_string(), "x".to_string())]),
+            Err(AppError::BadRequest(_))
+        ));
+        assert!(matches!(
+            insert_headers(&mut headers, vec![("X-Test".to_string(), "a\nb".to_string())]),
+            Err(AppError::BadRequest(_))
+        ));
+    }
+
+    #[test]
+    fn wait_is_capped_by_config() {
+        let config = config();
+        assert_eq!(wait_duration(None, &config), Duration::from_secs(5));
+        assert_eq!(wait_duration(Some(2), &config), Duration::from_secs(2));
+        assert_eq!(wait_duration(Some(600), &config), Duration::from_secs(30));
+    }
+
+    #[test]
+    fn relay_limits_can_only_be_lowered() {
+        let config = config();
+        let limits = relay_limits(&params(&[]), &config).unwrap();
+        assert_eq!(limits.idle_timeout, Duration::from_secs(300));
+        assert_eq!(limits.max_duration, Duration::from_secs(3600));
+        assert_eq!(limits.log_preview, None);
+
+        let limits = relay_limits(
+            &params(&[("idle_timeout", "10"), ("max_duration", "99999"), ("log", "true")]),
+            &config,
+        )
+        .unwrap();
+        assert_eq!(limits.idle_timeout, Duration::from_secs(10));
+        assert_eq!(limits.max_duration, Duration::from_secs(3600));
+        assert_eq!(limits.log_preview, Some(80));
+
+        // 0 秒会立即关闭连接, 至少保留 1 秒
+        let limits = relay_limits(&params(&[("idle_timeout", "0")]), &config).unwrap();
+        assert_eq!(limits.idle_timeout, Duration::from_secs(1));
+        assert!(matches!(
+            relay_limits(&params(&[("max_duration", "soon")]), &config),
+            Err(AppError::BadRequest(_))
+        ));
+    }
+}
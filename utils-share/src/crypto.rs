@@ -58,7 +58,18 @@ pub fn sha256_hash(data: &[u8]) -> String {
     Sha256::digest(data).encode_hex()
 }
 
+pub fn sha1_hash(data: &[u8]) -> String {
+    Sha1::digest(data).encode_hex()
+}
+
 pub fn sha256_short_hash(data: &[u8]) -> String {
     let full_hash = sha256_hash(data);
     full_hash[..16].to_string()
-} 
\ No newline at end of file
+}
+
+/// 原始字节形式的 HMAC-SHA256, 用于需要多轮派生密钥的签名 (例如 AWS SigV4)
+pub fn hmac_sha256(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts keys of any length");
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}